@@ -218,6 +218,12 @@ export interface VariantEvaluationResponse {
   requestDurationMillis: number;
   /** Timestamp when the response was generated. */
   timestamp: string;
+  /** Id of the rule that produced the result. */
+  ruleId?: string;
+  /** Rank of the rule that produced the result. */
+  ruleRank?: number;
+  /** Index of the chosen distribution among the distributions of the rule. */
+  distributionIndex?: number;
 }
 
 /**
@@ -236,6 +242,10 @@ export interface BooleanEvaluationResponse {
   timestamp: string;
   /** Segments that impacted evaluation. */
   segmentKeys: string[];
+  /** Rank of the rollout that produced the result. */
+  rolloutRank?: number;
+  /** Type of the rollout that produced the result. */
+  rolloutType?: string;
 }
 
 /**
@@ -25,6 +25,7 @@ fn main() {
         flag_key: "flag1".into(),
         entity_id: "entity".into(),
         context: context.clone(),
+        list_context: HashMap::new(),
     });
 
     println!("variant key {:?}", variant.unwrap().variant_key);
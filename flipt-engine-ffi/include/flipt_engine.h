@@ -31,6 +31,56 @@ const char *get_snapshot_ffi(void *engine_ptr);
  */
 const char *get_snapshot(void *engine_ptr);
 
+/**
+ * # Safety
+ *
+ * This function will take in a pointer to the engine and a JSON snapshot format and return the
+ * current snapshot base64-encoded in that format.
+ */
+const char *get_snapshot_with_format_ffi(void *engine_ptr, const char *format);
+
+/**
+ * # Safety
+ *
+ * This function will take in a pointer to the engine and a JSON snapshot format and return the
+ * current snapshot base64-encoded in that format.
+ */
+const char *get_snapshot_with_format(void *engine_ptr, const char *format);
+
+/**
+ * # Safety
+ *
+ * This function will take in a pointer to the engine and return the content digest of the
+ * current snapshot.
+ */
+const char *get_snapshot_digest_ffi(void *engine_ptr);
+
+/**
+ * # Safety
+ *
+ * This function will take in a pointer to the engine and return the content digest of the
+ * current snapshot.
+ */
+const char *get_snapshot_digest(void *engine_ptr);
+
+/**
+ * # Safety
+ *
+ * This function will take in a pointer to the engine and return its fetch health: the last
+ * successful fetch, the last error, consecutive failures, the current ETag, the fetch mode, the
+ * stream connection and the snapshot being served.
+ */
+const char *get_status_ffi(void *engine_ptr);
+
+/**
+ * # Safety
+ *
+ * This function will take in a pointer to the engine and return its fetch health: the last
+ * successful fetch, the last error, consecutive failures, the current ETag, the fetch mode, the
+ * stream connection and the snapshot being served.
+ */
+const char *get_status(void *engine_ptr);
+
 /**
  * # Safety
  *
@@ -73,6 +123,40 @@ const char *evaluate_batch_ffi(void *engine_ptr, const char *batch_evaluation_re
  */
 const char *evaluate_batch(void *engine_ptr, const char *batch_evaluation_request);
 
+/**
+ * # Safety
+ *
+ * This function will take in a pointer to the engine and return the distribution of the simulated entities.
+ * Simulations of more than 100,000 entities are rejected.
+ */
+const char *simulate_rollout_ffi(void *engine_ptr,
+                                 const char *simulation_request);
+
+/**
+ * # Safety
+ *
+ * This function will take in a pointer to the engine and return the distribution of the simulated entities.
+ * Simulations of more than 100,000 entities are rejected.
+ */
+const char *simulate_rollout(void *engine_ptr,
+                             const char *simulation_request);
+
+/**
+ * # Safety
+ *
+ * This function will take in a pointer to the engine and return a context that reaches the requested target.
+ */
+const char *synthesize_context_ffi(void *engine_ptr,
+                                   const char *synthesis_request);
+
+/**
+ * # Safety
+ *
+ * This function will take in a pointer to the engine and return a context that reaches the requested target.
+ */
+const char *synthesize_context(void *engine_ptr,
+                               const char *synthesis_request);
+
 /**
  * # Safety
  *
@@ -87,6 +171,20 @@ const char *list_flags_ffi(void *engine_ptr);
  */
 const char *list_flags(void *engine_ptr);
 
+/**
+ * # Safety
+ *
+ * This function will take in a pointer to the engine and return the evaluation cache statistics.
+ */
+const char *get_cache_stats_ffi(void *engine_ptr);
+
+/**
+ * # Safety
+ *
+ * This function will take in a pointer to the engine and return the evaluation cache statistics.
+ */
+const char *get_cache_stats(void *engine_ptr);
+
 /**
  * # Safety
  *
@@ -115,6 +213,70 @@ const char *update_authentication_ffi(void *engine_ptr, const char *auth_json);
  */
 const char *update_authentication(void *engine_ptr, const char *auth_json);
 
+/**
+ * # Safety
+ *
+ * This function will register a callback that is invoked with a JSON event whenever a changed
+ * snapshot is applied, a fetch fails, the stream connects or disconnects, or authentication is
+ * updated. Events from fetching arrive on the engine's background thread; `auth_updated` is
+ * delivered on the thread calling `update_authentication`. It replaces any previously registered
+ * callback. The callback may call back into the engine, but must not destroy it.
+ */
+const char *register_snapshot_callback_ffi(void *engine_ptr,
+                                           void (*callback)(const char *event, void *user_data),
+                                           void *user_data);
+
+/**
+ * # Safety
+ *
+ * This function will register a callback that is invoked with a JSON event whenever a changed
+ * snapshot is applied, a fetch fails, the stream connects or disconnects, or authentication is
+ * updated. Events from fetching arrive on the engine's background thread; `auth_updated` is
+ * delivered on the thread calling `update_authentication`. It replaces any previously registered
+ * callback. The callback may call back into the engine, but must not destroy it.
+ */
+const char *register_snapshot_callback(void *engine_ptr,
+                                       void (*callback)(const char *event, void *user_data),
+                                       void *user_data);
+
+/**
+ * # Safety
+ *
+ * This function will unregister the snapshot callback. Once it returns the callback is not running
+ * on any other thread and will not be invoked again, so the user data may be released when it is
+ * not called from within the callback. `destroy_engine` unregisters the callback as well.
+ */
+const char *unregister_snapshot_callback_ffi(void *engine_ptr);
+
+/**
+ * # Safety
+ *
+ * This function will unregister the snapshot callback. Once it returns the callback is not running
+ * on any other thread and will not be invoked again, so the user data may be released when it is
+ * not called from within the callback. `destroy_engine` unregisters the callback as well.
+ */
+const char *unregister_snapshot_callback(void *engine_ptr);
+
+/**
+ * # Safety
+ *
+ * This function will take all engine events queued since the last call and return them as a JSON
+ * array. If none are queued it waits up to `timeout_ms` milliseconds for one to arrive; a timeout of
+ * zero returns immediately. `destroy_engine` wakes up any waiting call.
+ */
+const char *poll_events_ffi(void *engine_ptr,
+                            uint64_t timeout_ms);
+
+/**
+ * # Safety
+ *
+ * This function will take all engine events queued since the last call and return them as a JSON
+ * array. If none are queued it waits up to `timeout_ms` milliseconds for one to arrive; a timeout of
+ * zero returns immediately. `destroy_engine` wakes up any waiting call.
+ */
+const char *poll_events(void *engine_ptr,
+                        uint64_t timeout_ms);
+
 /**
  * # Safety
  *
@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+/// Exponential backoff with jitter between attempts of a failing operation.
+///
+/// The delay doubles with every attempt up to the maximum. Each delay is randomized between half
+/// and all of its nominal value so that many clients don't retry in lockstep.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max: max.max(initial),
+            attempt: 0,
+        }
+    }
+
+    /// The delay to wait before the next attempt.
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self
+            .initial
+            .saturating_mul(2u32.saturating_pow(self.attempt))
+            .min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+
+        let half = delay / 2;
+        half + half.mul_f64(fastrand::f64())
+    }
+
+    /// Start over from the initial delay once the operation succeeded.
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+/// Spread `duration` randomly by up to `fraction` of it in either direction.
+pub fn jitter(duration: Duration, fraction: f64) -> Duration {
+    duration.mul_f64(1.0 + fraction * (2.0 * fastrand::f64() - 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(500));
+
+        for nominal in [100, 200, 400, 500, 500] {
+            let delay = backoff.next_delay();
+            assert!(delay >= Duration::from_millis(nominal / 2), "{delay:?}");
+            assert!(delay <= Duration::from_millis(nominal), "{delay:?}");
+        }
+
+        backoff.reset();
+        assert!(backoff.next_delay() <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_jitter() {
+        for _ in 0..100 {
+            let delay = jitter(Duration::from_secs(10), 0.1);
+            assert!(delay >= Duration::from_secs(9), "{delay:?}");
+            assert!(delay <= Duration::from_secs(11), "{delay:?}");
+        }
+    }
+
+    #[test]
+    fn test_backoff_does_not_overflow() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(30));
+
+        for _ in 0..100 {
+            assert!(backoff.next_delay() <= Duration::from_secs(30));
+        }
+    }
+}
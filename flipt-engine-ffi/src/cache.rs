@@ -0,0 +1,333 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use fliptevaluation::clock::Clock;
+use fliptevaluation::{
+    BooleanEvaluationResponse, ContextList, EvaluationRequest, VariantEvaluationResponse,
+};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_MAX_ENTRIES: usize = 10_000;
+
+/// Configuration for memoizing evaluation results.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct CacheOpts {
+    /// Maximum number of cached results; the oldest entries are evicted first.
+    pub max_entries: Option<usize>,
+}
+
+/// Hit and miss counters of an [`EvaluationCache`].
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    Variant,
+    Boolean,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    version: u64,
+    kind: Kind,
+    flag_key: String,
+    entity_id: String,
+    context: Vec<(String, String)>,
+    list_context: Vec<(String, ContextList)>,
+}
+
+impl CacheKey {
+    fn new(version: u64, kind: Kind, request: &EvaluationRequest) -> Self {
+        let mut context: Vec<(String, String)> = request
+            .context
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        context.sort_unstable();
+
+        let mut list_context: Vec<(String, ContextList)> = request
+            .list_context
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        list_context.sort_unstable();
+
+        Self {
+            version,
+            kind,
+            flag_key: request.flag_key.clone(),
+            entity_id: request.entity_id.clone(),
+            context,
+            list_context,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Entry {
+    Variant(VariantEvaluationResponse),
+    Boolean(BooleanEvaluationResponse),
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<CacheKey, Entry>,
+    order: VecDeque<CacheKey>,
+    stats: CacheStats,
+}
+
+/// Bounded cache of evaluation results keyed by snapshot version and request.
+///
+/// Entries written for an older snapshot version can never be hit again; the evaluator
+/// also clears the cache whenever it replaces its snapshot. Results served from the cache are
+/// stamped with the time of the lookup. Flags whose result depends on the evaluation time must
+/// not be cached; the evaluator bypasses the cache for them.
+pub struct EvaluationCache {
+    max_entries: usize,
+    state: Mutex<CacheState>,
+}
+
+impl EvaluationCache {
+    pub fn new(opts: CacheOpts) -> Self {
+        Self {
+            max_entries: opts
+                .max_entries
+                .filter(|m| *m > 0)
+                .unwrap_or(DEFAULT_MAX_ENTRIES),
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    pub fn variant<F, E>(
+        &self,
+        version: u64,
+        clock: &dyn Clock,
+        request: &EvaluationRequest,
+        evaluation: F,
+    ) -> Result<VariantEvaluationResponse, E>
+    where
+        F: FnOnce() -> Result<VariantEvaluationResponse, E>,
+    {
+        let start = Instant::now();
+        let key = CacheKey::new(version, Kind::Variant, request);
+        if let Some(Entry::Variant(mut response)) = self.get(&key) {
+            response.timestamp = clock.now();
+            response.request_duration_millis = start.elapsed().as_millis() as f64;
+            return Ok(response);
+        }
+
+        let response = evaluation()?;
+        self.insert(key, Entry::Variant(response.clone()));
+        Ok(response)
+    }
+
+    pub fn boolean<F, E>(
+        &self,
+        version: u64,
+        clock: &dyn Clock,
+        request: &EvaluationRequest,
+        evaluation: F,
+    ) -> Result<BooleanEvaluationResponse, E>
+    where
+        F: FnOnce() -> Result<BooleanEvaluationResponse, E>,
+    {
+        let start = Instant::now();
+        let key = CacheKey::new(version, Kind::Boolean, request);
+        if let Some(Entry::Boolean(mut response)) = self.get(&key) {
+            response.timestamp = clock.now();
+            response.request_duration_millis = start.elapsed().as_millis() as f64;
+            return Ok(response);
+        }
+
+        let response = evaluation()?;
+        self.insert(key, Entry::Boolean(response.clone()));
+        Ok(response)
+    }
+
+    /// Drop every cached result.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.order.clear();
+        state.stats.entries = 0;
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.state.lock().unwrap().stats
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<Entry> {
+        let mut state = self.state.lock().unwrap();
+        match state.entries.get(key).cloned() {
+            Some(entry) => {
+                state.stats.hits += 1;
+                Some(entry)
+            }
+            None => {
+                state.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn insert(&self, key: CacheKey, entry: Entry) {
+        let mut state = self.state.lock().unwrap();
+        if state.entries.insert(key.clone(), entry).is_none() {
+            state.order.push_back(key);
+        }
+
+        while state.entries.len() > self.max_entries {
+            match state.order.pop_front() {
+                Some(oldest) => {
+                    state.entries.remove(&oldest);
+                    state.stats.evictions += 1;
+                }
+                None => break,
+            }
+        }
+
+        state.stats.entries = state.entries.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use fliptevaluation::clock::{FixedClock, SystemClock};
+
+    fn request(entity_id: &str, context: &[(&str, &str)]) -> EvaluationRequest {
+        EvaluationRequest {
+            flag_key: "flag1".into(),
+            entity_id: entity_id.into(),
+            context: context
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>(),
+            list_context: HashMap::new(),
+        }
+    }
+
+    fn response(enabled: bool) -> Result<BooleanEvaluationResponse, ()> {
+        Ok(BooleanEvaluationResponse {
+            enabled,
+            flag_key: "flag1".into(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_cache_hits_and_misses() {
+        let cache = EvaluationCache::new(CacheOpts::default());
+        let req = request("entity", &[("a", "1"), ("b", "2")]);
+
+        assert!(
+            cache
+                .boolean(1, &SystemClock, &req, || response(true))
+                .unwrap()
+                .enabled
+        );
+        assert!(
+            cache
+                .boolean(1, &SystemClock, &req, || response(false))
+                .unwrap()
+                .enabled
+        );
+
+        // a new snapshot version never returns results from the old one
+        assert!(
+            !cache
+                .boolean(2, &SystemClock, &req, || response(false))
+                .unwrap()
+                .enabled
+        );
+
+        // list-valued properties are part of the key
+        let mut list_req = req.clone();
+        list_req
+            .list_context
+            .insert("roles".into(), ContextList::Strings(vec!["admin".into()]));
+        assert!(
+            !cache
+                .boolean(2, &SystemClock, &list_req, || response(false))
+                .unwrap()
+                .enabled
+        );
+
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 3,
+                evictions: 0,
+                entries: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn test_cache_restamps_hits() {
+        let cache = EvaluationCache::new(CacheOpts::default());
+        let req = request("entity", &[]);
+
+        let first = cache
+            .boolean(1, &SystemClock, &req, || response(true))
+            .unwrap();
+        let later = FixedClock(first.timestamp + chrono::TimeDelta::hours(1));
+        let hit = cache.boolean(1, &later, &req, || response(false)).unwrap();
+
+        assert!(hit.enabled);
+        assert_eq!(hit.timestamp, later.0);
+        assert_eq!(cache.stats().hits, 1);
+    }
+
+    #[test]
+    fn test_cache_does_not_store_errors() {
+        let cache = EvaluationCache::new(CacheOpts::default());
+        let req = request("entity", &[]);
+
+        assert!(cache.boolean(1, &SystemClock, &req, || Err(())).is_err());
+        assert!(cache
+            .boolean(1, &SystemClock, &req, || response(true))
+            .is_ok());
+        assert_eq!(cache.stats().misses, 2);
+        assert_eq!(cache.stats().entries, 1);
+    }
+
+    #[test]
+    fn test_cache_evicts_oldest() {
+        let cache = EvaluationCache::new(CacheOpts {
+            max_entries: Some(2),
+        });
+
+        for entity in ["a", "b", "c"] {
+            let _ = cache.boolean(1, &SystemClock, &request(entity, &[]), || response(true));
+        }
+
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.evictions, 1);
+
+        // "a" was evicted, "c" is still cached
+        assert!(
+            !cache
+                .boolean(1, &SystemClock, &request("a", &[]), || response(false))
+                .unwrap()
+                .enabled
+        );
+        assert!(
+            cache
+                .boolean(1, &SystemClock, &request("c", &[]), || response(false))
+                .unwrap()
+                .enabled
+        );
+    }
+}
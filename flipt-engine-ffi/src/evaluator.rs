@@ -1,12 +1,19 @@
+use std::collections::HashSet;
 use std::sync::{Arc, RwLock};
 
+use crate::cache::{CacheOpts, CacheStats, EvaluationCache};
+
 use fliptevaluation::{
-    batch_evaluation, boolean_evaluation,
+    batch_evaluation_with_options, boolean_evaluation_with_options,
+    clock::{Clock, SystemClock},
     error::Error,
+    hook::{self, Hooks},
     models::{flipt, snapshot},
+    simulation::{self, SimulationRequest, SimulationResponse},
     store::Store,
-    variant_evaluation, BatchEvaluationResponse, BooleanEvaluationResponse, EvaluationRequest,
-    VariantEvaluationResponse,
+    synthesis::{self, SynthesisRequest, SynthesisResponse},
+    variant_evaluation_with_options, BatchEvaluationResponse, BooleanEvaluationResponse,
+    BucketResolution, EvaluationOptions, EvaluationRequest, VariantEvaluationResponse,
 };
 
 pub struct Evaluator<S>
@@ -17,6 +24,12 @@ where
     store: S,
     mtx: Arc<RwLock<i32>>,
     error: Option<Error>,
+    hooks: Hooks,
+    version: u64,
+    cache: Option<EvaluationCache>,
+    clock_dependent_flags: HashSet<String>,
+    clock: Arc<dyn Clock>,
+    resolution: BucketResolution,
 }
 
 impl Evaluator<snapshot::Snapshot> {
@@ -27,18 +40,85 @@ impl Evaluator<snapshot::Snapshot> {
             store: snap,
             mtx: Arc::new(RwLock::new(0)),
             error: None,
+            hooks: Hooks::new(),
+            version: 0,
+            cache: None,
+            clock_dependent_flags: HashSet::new(),
+            clock: Arc::new(SystemClock),
+            resolution: BucketResolution::default(),
+        }
+    }
+
+    /// Register hooks that are notified around every evaluation. They run after the evaluator's
+    /// lock is released.
+    pub fn with_hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Memoize variant and boolean evaluation results until the snapshot is replaced.
+    pub fn with_cache(mut self, opts: CacheOpts) -> Self {
+        self.cache = Some(EvaluationCache::new(opts));
+        self
+    }
+
+    /// Clock that relative and time-of-day constraints are evaluated against.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Bucket resolution used for threshold rollouts and variant distributions.
+    pub fn with_bucket_resolution(mut self, resolution: BucketResolution) -> Self {
+        self.resolution = resolution;
+        self
+    }
+
+    fn options(&self) -> EvaluationOptions<'_> {
+        EvaluationOptions {
+            clock: self.clock.as_ref(),
+            resolution: self.resolution,
         }
     }
 
-    pub fn replace_snapshot(&mut self, res: Result<snapshot::Snapshot, Error>) {
+    /// The cache for a flag, unless its result depends on the evaluation time.
+    fn cache_for(&self, flag_key: &str) -> Option<&EvaluationCache> {
+        self.cache
+            .as_ref()
+            .filter(|_| !self.clock_dependent_flags.contains(flag_key))
+    }
+
+    /// Statistics of the evaluation cache, if enabled.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|cache| cache.stats())
+    }
+
+    /// Serve evaluations from a new snapshot, or report an error, returning the snapshot that was
+    /// replaced.
+    pub fn replace_snapshot(
+        &mut self,
+        res: Result<snapshot::Snapshot, Error>,
+    ) -> Option<snapshot::Snapshot> {
         let _w_lock = self.mtx.write().unwrap();
+        self.version += 1;
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
         match res {
             Ok(snap) => {
-                self.store = snap;
+                if self.cache.is_some() {
+                    self.clock_dependent_flags = snap
+                        .namespace()
+                        .clock_dependent_flags()
+                        .into_iter()
+                        .collect();
+                }
                 self.error = None;
+                Some(std::mem::replace(&mut self.store, snap))
             }
             Err(err) => {
                 self.error = Some(err);
+                None
             }
         }
     }
@@ -51,6 +131,19 @@ impl Evaluator<snapshot::Snapshot> {
         Ok(self.store.clone())
     }
 
+    /// The snapshot evaluations are served from, also while a fetch error is reported.
+    pub fn current_snapshot(&self) -> &snapshot::Snapshot {
+        &self.store
+    }
+
+    pub fn get_snapshot_digest(&self) -> Result<String, Error> {
+        let _r_lock = self.mtx.read().unwrap();
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        Ok(self.store.digest().to_string())
+    }
+
     pub fn list_flags(&self) -> Result<Vec<flipt::Flag>, Error> {
         let _r_lock = self.mtx.read().unwrap();
         if let Some(error) = &self.error {
@@ -65,37 +158,124 @@ impl Evaluator<snapshot::Snapshot> {
         }
     }
 
+    /// Hooks notified around every evaluation.
+    pub fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
     pub fn variant(
         &self,
         evaluation_request: &EvaluationRequest,
+    ) -> Result<VariantEvaluationResponse, Error> {
+        hook::evaluate(&self.hooks, &self.namespace, evaluation_request, || {
+            self.evaluate_variant(evaluation_request)
+        })
+    }
+
+    /// Evaluate a variant flag without notifying the hooks.
+    pub fn evaluate_variant(
+        &self,
+        evaluation_request: &EvaluationRequest,
     ) -> Result<VariantEvaluationResponse, Error> {
         let _r_lock = self.mtx.read().unwrap();
         if let Some(error) = &self.error {
             return Err(error.clone());
         }
-        variant_evaluation(&self.store, &self.namespace, evaluation_request)
+        let evaluate = || {
+            variant_evaluation_with_options(
+                &self.store,
+                &self.namespace,
+                evaluation_request,
+                &self.options(),
+            )
+        };
+        match self.cache_for(&evaluation_request.flag_key) {
+            Some(cache) => cache.variant(
+                self.version,
+                self.clock.as_ref(),
+                evaluation_request,
+                evaluate,
+            ),
+            None => evaluate(),
+        }
     }
 
     pub fn boolean(
         &self,
         evaluation_request: &EvaluationRequest,
     ) -> Result<BooleanEvaluationResponse, Error> {
+        hook::evaluate(&self.hooks, &self.namespace, evaluation_request, || {
+            self.evaluate_boolean(evaluation_request)
+        })
+    }
+
+    /// Evaluate a boolean flag without notifying the hooks.
+    pub fn evaluate_boolean(
+        &self,
+        evaluation_request: &EvaluationRequest,
+    ) -> Result<BooleanEvaluationResponse, Error> {
+        let _r_lock = self.mtx.read().unwrap();
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        let evaluate = || {
+            boolean_evaluation_with_options(
+                &self.store,
+                &self.namespace,
+                evaluation_request,
+                &self.options(),
+            )
+        };
+        match self.cache_for(&evaluation_request.flag_key) {
+            Some(cache) => cache.boolean(
+                self.version,
+                self.clock.as_ref(),
+                evaluation_request,
+                evaluate,
+            ),
+            None => evaluate(),
+        }
+    }
+
+    pub fn simulate(&self, request: &SimulationRequest) -> Result<SimulationResponse, Error> {
+        let _r_lock = self.mtx.read().unwrap();
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        simulation::simulate_with_options(&self.store, &self.namespace, request, &self.options())
+    }
+
+    pub fn synthesize(&self, request: &SynthesisRequest) -> Result<SynthesisResponse, Error> {
         let _r_lock = self.mtx.read().unwrap();
         if let Some(error) = &self.error {
             return Err(error.clone());
         }
-        boolean_evaluation(&self.store, &self.namespace, evaluation_request)
+        synthesis::synthesize_with_options(&self.store, &self.namespace, request, &self.options())
     }
 
     pub fn batch(
         &self,
         requests: Vec<EvaluationRequest>,
+    ) -> Result<BatchEvaluationResponse, Error> {
+        hook::evaluate_batch(&self.hooks, &self.namespace, &requests, |requests| {
+            self.evaluate_batch(requests)
+        })
+    }
+
+    /// Evaluate a batch of flags without notifying the hooks.
+    pub fn evaluate_batch(
+        &self,
+        requests: &[EvaluationRequest],
     ) -> Result<BatchEvaluationResponse, Error> {
         let _r_lock = self.mtx.read().unwrap();
         if let Some(error) = &self.error {
             return Err(error.clone());
         }
-        batch_evaluation(&self.store, &self.namespace, requests)
+        batch_evaluation_with_options(&self.store, &self.namespace, requests, &self.options())
     }
 }
 
@@ -130,6 +310,7 @@ mod tests {
             flag_key: String::from("foo"),
             entity_id: String::from("user@flipt.io"),
             context: HashMap::new(),
+            list_context: HashMap::new(),
         }];
         let response = evaluator.batch(requests);
         assert!(response.is_ok());
@@ -140,6 +321,7 @@ mod tests {
             flag_key: String::from("foo"),
             entity_id: String::from("user@flipt.io"),
             context: HashMap::new(),
+            list_context: HashMap::new(),
         };
         let response = evaluator.boolean(request);
         assert_error_response(
@@ -157,14 +339,16 @@ mod tests {
     fn test_replace_snapshot() {
         let mut evaluator = Evaluator::new("namespace");
         let snapshot = snapshot::Snapshot::empty("namespace");
-        evaluator.replace_snapshot(Ok(snapshot.clone()));
+        let previous = evaluator.replace_snapshot(Ok(snapshot.clone()));
         assert_eq!(evaluator.store, snapshot);
+        assert_eq!(previous, Some(snapshot::Snapshot::empty("namespace")));
     }
 
     #[test]
     fn test_replace_snapshot_error() {
         let mut evaluator = Evaluator::new("namespace");
-        evaluator.replace_snapshot(Err(Error::Unknown("error".to_string())));
+        let previous = evaluator.replace_snapshot(Err(Error::Unknown("error".to_string())));
+        assert!(previous.is_none());
 
         let response = evaluator.list_flags();
         assert_error_response(response, "unknown error: error");
@@ -173,6 +357,7 @@ mod tests {
             flag_key: String::from("foo"),
             entity_id: String::from("user@flipt.io"),
             context: HashMap::new(),
+            list_context: HashMap::new(),
         };
         let response = evaluator.boolean(request);
         assert_error_response(response, "unknown error: error");
@@ -184,11 +369,167 @@ mod tests {
             flag_key: String::from("foo"),
             entity_id: String::from("user@flipt.io"),
             context: HashMap::new(),
+            list_context: HashMap::new(),
         }];
         let response = evaluator.batch(requests);
         assert_error_response(response, "unknown error: error");
     }
 
+    #[test]
+    fn test_hooks() {
+        use fliptevaluation::hook::{EvaluationHook, HookResponse};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[derive(Default)]
+        struct CountingHook {
+            before: AtomicUsize,
+            after: AtomicUsize,
+            error: AtomicUsize,
+        }
+
+        impl EvaluationHook for CountingHook {
+            fn before(&self, _: &str, _: &EvaluationRequest) {
+                self.before.fetch_add(1, Ordering::SeqCst);
+            }
+
+            fn after(&self, _: &str, _: &EvaluationRequest, _: &HookResponse<'_>) {
+                self.after.fetch_add(1, Ordering::SeqCst);
+            }
+
+            fn error(&self, _: &str, _: &EvaluationRequest, _: &Error) {
+                self.error.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let hook = Arc::new(CountingHook::default());
+        let evaluator = Evaluator::new("namespace").with_hooks(vec![hook.clone()]);
+
+        let request = EvaluationRequest {
+            flag_key: String::from("foo"),
+            entity_id: String::from("user@flipt.io"),
+            context: HashMap::new(),
+            list_context: HashMap::new(),
+        };
+        assert!(evaluator.boolean(&request).is_err());
+        assert!(evaluator.batch(vec![request]).is_ok());
+
+        assert_eq!(hook.before.load(Ordering::SeqCst), 2);
+        assert_eq!(hook.after.load(Ordering::SeqCst), 1);
+        assert_eq!(hook.error.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_cache_invalidated_on_replace_snapshot() {
+        let snapshot = |percentage: f32| -> snapshot::Snapshot {
+            serde_json::from_value(serde_json::json!({
+                "version": 1,
+                "namespace": {
+                    "key": "namespace",
+                    "flags": {
+                        "foo": {
+                            "key": "foo",
+                            "enabled": true,
+                            "type": "BOOLEAN_FLAG_TYPE"
+                        }
+                    },
+                    "eval_rules": {},
+                    "eval_rollouts": {
+                        "foo": [{
+                            "rollout_type": "THRESHOLD_ROLLOUT_TYPE",
+                            "rank": 1,
+                            "threshold": { "percentage": percentage, "value": false }
+                        }]
+                    },
+                    "eval_distributions": {}
+                }
+            }))
+            .unwrap()
+        };
+
+        let mut evaluator = Evaluator::new("namespace").with_cache(CacheOpts::default());
+        evaluator.replace_snapshot(Ok(snapshot(100.0)));
+
+        let request = EvaluationRequest {
+            flag_key: String::from("foo"),
+            entity_id: String::from("user@flipt.io"),
+            context: HashMap::new(),
+            list_context: HashMap::new(),
+        };
+        assert!(!evaluator.boolean(&request).unwrap().enabled);
+        assert!(!evaluator.boolean(&request).unwrap().enabled);
+
+        evaluator.replace_snapshot(Ok(snapshot(0.0)));
+        assert!(evaluator.boolean(&request).unwrap().enabled);
+
+        let stats = evaluator.cache_stats().unwrap();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.entries, 1);
+    }
+
+    #[test]
+    fn test_cache_bypassed_for_clock_dependent_flags() {
+        use chrono::{DateTime, TimeZone, Utc};
+        use fliptevaluation::models::source;
+        use std::sync::Mutex;
+
+        struct TestClock(Mutex<DateTime<Utc>>);
+
+        impl Clock for TestClock {
+            fn now(&self) -> DateTime<Utc> {
+                *self.0.lock().unwrap()
+            }
+        }
+
+        let document: source::Document = serde_json::from_value(serde_json::json!({
+            "namespace": { "key": "namespace" },
+            "flags": [{
+                "key": "business-hours",
+                "name": "business-hours",
+                "enabled": false,
+                "type": "BOOLEAN_FLAG_TYPE",
+                "rollouts": [{
+                    "segment": {
+                        "value": true,
+                        "segments": [{
+                            "key": "office",
+                            "matchType": "ALL_SEGMENT_MATCH_TYPE",
+                            "constraints": [{
+                                "type": "DATETIME_CONSTRAINT_COMPARISON_TYPE",
+                                "property": "now",
+                                "operator": "timeofday",
+                                "value": r#"{"start":"09:00","end":"17:00"}"#
+                            }]
+                        }]
+                    }
+                }]
+            }]
+        }))
+        .unwrap();
+
+        let clock = Arc::new(TestClock(Mutex::new(
+            Utc.with_ymd_and_hms(2024, 1, 2, 16, 59, 0).unwrap(),
+        )));
+        let mut evaluator = Evaluator::new("namespace")
+            .with_cache(CacheOpts::default())
+            .with_clock(clock.clone());
+        evaluator.replace_snapshot(Ok(snapshot::Snapshot::build(document)));
+
+        let request = EvaluationRequest {
+            flag_key: String::from("business-hours"),
+            entity_id: String::from("user@flipt.io"),
+            context: HashMap::new(),
+            list_context: HashMap::new(),
+        };
+        assert!(evaluator.boolean(&request).unwrap().enabled);
+
+        *clock.0.lock().unwrap() = Utc.with_ymd_and_hms(2024, 1, 2, 17, 1, 0).unwrap();
+        assert!(!evaluator.boolean(&request).unwrap().enabled);
+
+        let stats = evaluator.cache_stats().unwrap();
+        assert_eq!(stats.hits + stats.misses, 0);
+    }
+
     #[test]
     fn test_get_snapshot() {
         let mut evaluator = Evaluator::new("namespace");
@@ -0,0 +1,408 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use fliptevaluation::models::{flipt, snapshot};
+use fliptevaluation::store::Store;
+use libc::c_void;
+use serde::Serialize;
+
+/// Callback invoked with a JSON-encoded [`EngineEvent`] and the user data pointer it was
+/// registered with. The event string is only valid for the duration of the call.
+pub type SnapshotCallback = unsafe extern "C" fn(event: *const c_char, user_data: *mut c_void);
+
+/// Maximum number of undrained events kept per engine.
+const EVENT_QUEUE_CAPACITY: usize = 256;
+
+/// Notifications emitted by the engine's fetch loop.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EngineEvent {
+    /// A snapshot that differs from the one served before was applied.
+    SnapshotApplied(SnapshotChange),
+    /// Fetching a new snapshot failed.
+    FetchFailed { error: String },
+    /// The streaming connection was established.
+    StreamConnected,
+    /// The streaming connection was closed, with the error that closed it if any.
+    StreamDisconnected { error: Option<String> },
+    /// The authentication used for requests was replaced.
+    AuthUpdated,
+}
+
+/// Summary of the differences between two snapshots.
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct SnapshotChange {
+    pub digest: String,
+    pub previous_digest: String,
+    pub added_flags: Vec<String>,
+    pub removed_flags: Vec<String>,
+    /// Flags whose definition, rules, distributions, rollouts or referenced segments changed.
+    pub changed_flags: Vec<String>,
+}
+
+impl SnapshotChange {
+    pub fn between(previous: &snapshot::Snapshot, current: &snapshot::Snapshot) -> Self {
+        let previous_keys: BTreeSet<&String> = previous.namespace().flags.keys().collect();
+        let current_keys: BTreeSet<&String> = current.namespace().flags.keys().collect();
+
+        Self {
+            digest: current.digest().to_string(),
+            previous_digest: previous.digest().to_string(),
+            added_flags: current_keys
+                .difference(&previous_keys)
+                .map(|key| key.to_string())
+                .collect(),
+            removed_flags: previous_keys
+                .difference(&current_keys)
+                .map(|key| key.to_string())
+                .collect(),
+            changed_flags: current_keys
+                .intersection(&previous_keys)
+                .filter(|key| flag_state(previous, key) != flag_state(current, key))
+                .map(|key| key.to_string())
+                .collect(),
+        }
+    }
+}
+
+type FlagState = (
+    Option<flipt::Flag>,
+    Option<Vec<flipt::EvaluationRule>>,
+    Vec<Option<Vec<flipt::EvaluationDistribution>>>,
+    Option<Vec<flipt::EvaluationRollout>>,
+);
+
+/// Everything evaluating a flag depends on, with segments resolved.
+fn flag_state(snapshot: &snapshot::Snapshot, flag_key: &str) -> FlagState {
+    let namespace = &snapshot.namespace().key;
+    let rules = snapshot.get_evaluation_rules(namespace, flag_key);
+    let distributions = rules
+        .iter()
+        .flatten()
+        .map(|rule| snapshot.get_evaluation_distributions(namespace, &rule.id))
+        .collect();
+
+    (
+        snapshot.get_flag(namespace, flag_key),
+        rules,
+        distributions,
+        snapshot.get_evaluation_rollouts(namespace, flag_key),
+    )
+}
+
+#[derive(Clone, Copy)]
+struct Listener {
+    callback: SnapshotCallback,
+    user_data: *mut c_void,
+}
+
+// The user data pointer is only handed back to the callback; the host is responsible for it
+// being usable from the engine's threads.
+unsafe impl Send for Listener {}
+
+/// Bounded queue of [`EngineEvent`]s for hosts that poll rather than take callbacks. When the
+/// queue is full the oldest events are dropped.
+#[derive(Default)]
+pub struct EventQueue {
+    state: Mutex<QueueState>,
+    available: Condvar,
+}
+
+#[derive(Default)]
+struct QueueState {
+    events: VecDeque<EngineEvent>,
+    closed: bool,
+}
+
+impl EventQueue {
+    fn push(&self, event: EngineEvent) {
+        let mut state = self.lock();
+        if state.closed {
+            return;
+        }
+        if state.events.len() == EVENT_QUEUE_CAPACITY {
+            state.events.pop_front();
+        }
+        state.events.push_back(event);
+        self.available.notify_all();
+    }
+
+    /// Take all queued events, waiting up to `timeout` for one to arrive if the queue is empty.
+    pub fn drain(&self, timeout: Duration) -> Vec<EngineEvent> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.lock();
+
+        while state.events.is_empty() && !state.closed {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            state = self
+                .available
+                .wait_timeout(state, remaining)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+
+        state.events.drain(..).collect()
+    }
+
+    /// Stop queueing events and wake up any waiting [`EventQueue::drain`] call.
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.available.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[derive(Default)]
+struct Registration {
+    listener: Option<Listener>,
+    /// Threads currently running the callback, once per call in flight.
+    dispatching: Vec<ThreadId>,
+}
+
+/// Delivers [`EngineEvent`]s to the engine's [`EventQueue`] and to the callback registered by
+/// the host, if any.
+///
+/// The callback runs without any lock held, so it may evaluate flags, update authentication or
+/// register and unregister callbacks. [`Notifier::unregister`] waits for calls in flight on other
+/// threads, so once it returns the callback is not running elsewhere and will not be invoked
+/// again.
+#[derive(Clone, Default)]
+pub struct Notifier {
+    registration: Arc<(Mutex<Registration>, Condvar)>,
+    queue: Arc<EventQueue>,
+}
+
+impl Notifier {
+    pub fn register(&self, callback: SnapshotCallback, user_data: *mut c_void) {
+        self.lock().listener = Some(Listener {
+            callback,
+            user_data,
+        });
+    }
+
+    pub fn unregister(&self) {
+        let current = thread::current().id();
+        let mut registration = self.lock();
+        registration.listener = None;
+        // a callback unregistering itself must not wait for its own return
+        registration.dispatching.retain(|id| *id != current);
+        self.registration.1.notify_all();
+
+        while !registration.dispatching.is_empty() {
+            registration = self
+                .registration
+                .1
+                .wait(registration)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    pub fn queue(&self) -> Arc<EventQueue> {
+        self.queue.clone()
+    }
+
+    /// Unregister the callback and close the queue.
+    pub fn close(&self) {
+        self.unregister();
+        self.queue.close();
+    }
+
+    pub fn notify(&self, event: EngineEvent) {
+        self.queue.push(event.clone());
+
+        let current = thread::current().id();
+        let listener = {
+            let mut registration = self.lock();
+            let Some(listener) = registration.listener else {
+                return;
+            };
+            registration.dispatching.push(current);
+            listener
+        };
+
+        match serde_json::to_string(&event).map(CString::new) {
+            Ok(Ok(event)) => unsafe { (listener.callback)(event.as_ptr(), listener.user_data) },
+            _ => log::warn!("failed to encode engine event: {event:?}"),
+        }
+
+        let mut registration = self.lock();
+        if let Some(i) = registration
+            .dispatching
+            .iter()
+            .position(|id| *id == current)
+        {
+            registration.dispatching.swap_remove(i);
+            self.registration.1.notify_all();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Registration> {
+        self.registration
+            .0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::ffi::CStr;
+    use std::sync::Barrier;
+
+    use super::*;
+    use fliptevaluation::models::source;
+
+    fn snapshot(flags: serde_json::Value) -> snapshot::Snapshot {
+        let document: source::Document = serde_json::from_value(serde_json::json!({
+            "namespace": { "key": "default" },
+            "flags": flags
+        }))
+        .unwrap();
+        snapshot::Snapshot::build(document)
+    }
+
+    #[test]
+    fn test_snapshot_change_between() {
+        let previous = snapshot(serde_json::json!([
+            { "key": "kept", "name": "kept", "enabled": true, "type": "BOOLEAN_FLAG_TYPE" },
+            { "key": "toggled", "name": "toggled", "enabled": true, "type": "BOOLEAN_FLAG_TYPE" },
+            { "key": "removed", "name": "removed", "enabled": true, "type": "BOOLEAN_FLAG_TYPE" }
+        ]));
+        let current = snapshot(serde_json::json!([
+            { "key": "kept", "name": "kept", "enabled": true, "type": "BOOLEAN_FLAG_TYPE" },
+            { "key": "toggled", "name": "toggled", "enabled": false, "type": "BOOLEAN_FLAG_TYPE" },
+            { "key": "added", "name": "added", "enabled": true, "type": "BOOLEAN_FLAG_TYPE" }
+        ]));
+
+        let change = SnapshotChange::between(&previous, &current);
+
+        assert_eq!(change.digest, current.digest());
+        assert_eq!(change.previous_digest, previous.digest());
+        assert_eq!(change.added_flags, vec!["added"]);
+        assert_eq!(change.removed_flags, vec!["removed"]);
+        assert_eq!(change.changed_flags, vec!["toggled"]);
+
+        assert_eq!(
+            SnapshotChange::between(&current, &current).changed_flags,
+            Vec::<String>::new()
+        );
+    }
+
+    unsafe extern "C" fn record(event: *const c_char, user_data: *mut c_void) {
+        let events = &mut *(user_data as *mut Vec<String>);
+        events.push(CStr::from_ptr(event).to_str().unwrap().to_string());
+    }
+
+    #[test]
+    fn test_notifier() {
+        let mut events: Vec<String> = Vec::new();
+        let notifier = Notifier::default();
+        let event = EngineEvent::FetchFailed {
+            error: "boom".into(),
+        };
+
+        notifier.notify(event.clone());
+        notifier.register(record, &mut events as *mut Vec<String> as *mut c_void);
+        notifier.notify(event.clone());
+        notifier.unregister();
+        notifier.notify(event);
+
+        assert_eq!(events, vec![r#"{"type":"fetch_failed","error":"boom"}"#]);
+        assert_eq!(notifier.queue().drain(Duration::ZERO).len(), 3);
+    }
+
+    unsafe extern "C" fn reenter(_event: *const c_char, user_data: *mut c_void) {
+        let (notifier, calls) = &*(user_data as *const (Notifier, Cell<usize>));
+        calls.set(calls.get() + 1);
+        // the nested notification reaches this callback again, so only the first call re-enters
+        if calls.get() == 1 {
+            notifier.notify(EngineEvent::AuthUpdated);
+            notifier.unregister();
+        }
+    }
+
+    #[test]
+    fn test_notifier_reentrant_callback() {
+        let state = (Notifier::default(), Cell::new(0));
+        let notifier = &state.0;
+        notifier.register(reenter, &state as *const _ as *mut c_void);
+
+        notifier.notify(EngineEvent::StreamConnected);
+        notifier.notify(EngineEvent::StreamConnected);
+
+        // the callback unregistered itself, so the last notification was only queued
+        assert_eq!(state.1.get(), 2);
+        assert_eq!(notifier.queue().drain(Duration::ZERO).len(), 3);
+    }
+
+    unsafe extern "C" fn block(_event: *const c_char, user_data: *mut c_void) {
+        let (started, release) = &*(user_data as *const (Barrier, Barrier));
+        started.wait();
+        release.wait();
+    }
+
+    #[test]
+    fn test_notifier_unregister_waits_for_callback() {
+        let barriers: &'static (Barrier, Barrier) =
+            Box::leak(Box::new((Barrier::new(2), Barrier::new(2))));
+        let notifier = Notifier::default();
+        notifier.register(block, barriers as *const _ as *mut c_void);
+
+        let dispatching = notifier.clone();
+        let callback = std::thread::spawn(move || dispatching.notify(EngineEvent::AuthUpdated));
+        barriers.0.wait();
+
+        let unregistering = notifier.clone();
+        let unregister = std::thread::spawn(move || unregistering.unregister());
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!unregister.is_finished());
+
+        barriers.1.wait();
+        callback.join().unwrap();
+        unregister.join().unwrap();
+    }
+
+    #[test]
+    fn test_event_queue() {
+        let notifier = Notifier::default();
+        let queue = notifier.queue();
+
+        assert!(queue.drain(Duration::from_millis(10)).is_empty());
+
+        for _ in 0..EVENT_QUEUE_CAPACITY {
+            notifier.notify(EngineEvent::StreamConnected);
+        }
+        notifier.notify(EngineEvent::AuthUpdated);
+
+        let events = queue.drain(Duration::ZERO);
+        assert_eq!(events.len(), EVENT_QUEUE_CAPACITY);
+        assert_eq!(events.last(), Some(&EngineEvent::AuthUpdated));
+
+        // a waiting drain returns as soon as an event arrives
+        let waiting = std::thread::spawn(move || queue.drain(Duration::from_secs(30)));
+        std::thread::sleep(Duration::from_millis(50));
+        notifier.notify(EngineEvent::StreamDisconnected { error: None });
+        assert_eq!(
+            waiting.join().unwrap(),
+            vec![EngineEvent::StreamDisconnected { error: None }]
+        );
+
+        // and closing the queue wakes up waiters
+        let queue = notifier.queue();
+        let waiting = std::thread::spawn(move || queue.drain(Duration::from_secs(30)));
+        std::thread::sleep(Duration::from_millis(50));
+        notifier.close();
+        assert!(waiting.join().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,491 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use reqwest::header::HeaderMap;
+use reqwest_middleware::ClientWithMiddleware;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{watch, Notify};
+
+use fliptevaluation::error::Error;
+use fliptevaluation::hook::{EvaluationHook, HookResponse};
+use fliptevaluation::EvaluationRequest;
+
+use crate::http::{build_client, client_builder, ConnectionOpts, RetryOpts};
+use crate::TlsConfig;
+
+const DEFAULT_FLUSH_INTERVAL: u64 = 10;
+const DEFAULT_BATCH_SIZE: usize = 100;
+const DEFAULT_MAX_BUFFER_SIZE: usize = 10_000;
+const DEFAULT_DEDUP_WINDOW: u64 = 3600;
+/// Maximum number of recent exposures remembered for deduplication; the oldest are forgotten first.
+const MAX_DEDUP_ENTRIES: usize = 100_000;
+const FINAL_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Configuration for recording and flushing exposure events.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct ExposureOpts {
+    /// Analytics endpoint the batches are POSTed to.
+    pub url: String,
+    /// Seconds between periodic flushes.
+    pub flush_interval: Option<u64>,
+    /// Maximum number of events sent in a single request.
+    pub batch_size: Option<usize>,
+    /// Maximum number of events held in memory; further events are dropped until a flush succeeds.
+    pub max_buffer_size: Option<usize>,
+    /// Seconds during which repeated exposures of the same entity to the same variant of a flag
+    /// are ignored.
+    pub dedup_window: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ExposureEvent {
+    pub namespace_key: String,
+    pub flag_key: String,
+    pub entity_id: String,
+    pub variant_key: String,
+    pub r#match: bool,
+    pub timestamp: String,
+}
+
+#[derive(Serialize)]
+struct ExposureBatch<'a> {
+    exposures: &'a [ExposureEvent],
+}
+
+/// Flag, entity and variant of an exposure.
+type DedupKey = (String, String, String);
+
+#[derive(Default)]
+struct ExposureState {
+    buffer: VecDeque<ExposureEvent>,
+    seen: HashMap<DedupKey, Instant>,
+    /// Entries of `seen` in the order they were recorded.
+    seen_order: VecDeque<(DedupKey, Instant)>,
+    dropped: u64,
+}
+
+impl ExposureState {
+    /// Forget exposures recorded before the deduplication window.
+    fn forget_expired(&mut self, now: Instant, window: Duration) {
+        while let Some((_, seen)) = self.seen_order.front() {
+            if now.duration_since(*seen) < window {
+                break;
+            }
+            self.forget_oldest();
+        }
+    }
+
+    fn remember(&mut self, key: DedupKey, now: Instant, max_entries: usize) {
+        self.seen.insert(key.clone(), now);
+        self.seen_order.push_back((key, now));
+        while self.seen.len() > max_entries {
+            self.forget_oldest();
+        }
+    }
+
+    fn forget_oldest(&mut self) {
+        if let Some((key, seen)) = self.seen_order.pop_front() {
+            // the key may have been recorded again since
+            if self.seen.get(&key) == Some(&seen) {
+                self.seen.remove(&key);
+            }
+        }
+    }
+}
+
+/// Records an exposure each time an entity is evaluated into a variant and flushes them
+/// in batches to an analytics endpoint.
+pub struct ExposureRecorder {
+    url: String,
+    flush_interval: Duration,
+    batch_size: usize,
+    max_buffer_size: usize,
+    dedup_window: Duration,
+    max_dedup_entries: usize,
+    http_client: ClientWithMiddleware,
+    auth_receiver: watch::Receiver<HeaderMap>,
+    state: Mutex<ExposureState>,
+    flush_notify: Notify,
+}
+
+impl ExposureRecorder {
+    /// Recorder sending exposures with the same TLS, connection and retry settings as the
+    /// snapshot fetcher.
+    pub fn new(
+        opts: ExposureOpts,
+        tls_config: Option<&TlsConfig>,
+        connection: &ConnectionOpts,
+        retry: &RetryOpts,
+        auth_receiver: watch::Receiver<HeaderMap>,
+    ) -> Result<Self, Error> {
+        let http_client = build_client(
+            client_builder(connection).timeout(Duration::from_secs(30)),
+            tls_config,
+            retry,
+        )?;
+
+        Ok(Self {
+            url: opts.url,
+            flush_interval: Duration::from_secs(
+                opts.flush_interval
+                    .filter(|i| *i > 0)
+                    .unwrap_or(DEFAULT_FLUSH_INTERVAL),
+            ),
+            batch_size: opts
+                .batch_size
+                .filter(|s| *s > 0)
+                .unwrap_or(DEFAULT_BATCH_SIZE),
+            max_buffer_size: opts
+                .max_buffer_size
+                .filter(|s| *s > 0)
+                .unwrap_or(DEFAULT_MAX_BUFFER_SIZE),
+            dedup_window: Duration::from_secs(opts.dedup_window.unwrap_or(DEFAULT_DEDUP_WINDOW)),
+            max_dedup_entries: MAX_DEDUP_ENTRIES,
+            http_client,
+            auth_receiver,
+            state: Mutex::new(ExposureState::default()),
+            flush_notify: Notify::new(),
+        })
+    }
+
+    /// Buffer an exposure unless the entity was already exposed to the same variant of the flag
+    /// within the deduplication window.
+    pub fn record(&self, event: ExposureEvent) {
+        let mut state = self.state.lock().unwrap();
+
+        let key = (
+            event.flag_key.clone(),
+            event.entity_id.clone(),
+            event.variant_key.clone(),
+        );
+        let now = Instant::now();
+        state.forget_expired(now, self.dedup_window);
+        if state.seen.contains_key(&key) {
+            return;
+        }
+
+        if state.buffer.len() >= self.max_buffer_size {
+            state.dropped += 1;
+            log::debug!("exposure buffer full, dropping event");
+            self.flush_notify.notify_one();
+            return;
+        }
+
+        state.remember(key, now, self.max_dedup_entries);
+        state.buffer.push_back(event);
+
+        if state.buffer.len() >= self.batch_size {
+            self.flush_notify.notify_one();
+        }
+    }
+
+    /// Number of events currently buffered.
+    pub fn buffered(&self) -> usize {
+        self.state.lock().unwrap().buffer.len()
+    }
+
+    /// Number of events dropped because the buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.state.lock().unwrap().dropped
+    }
+
+    /// Send all buffered events. Events from a batch that cannot be delivered are put back
+    /// at the front of the buffer.
+    pub async fn flush(&self) -> Result<(), Error> {
+        loop {
+            let batch: Vec<ExposureEvent> = {
+                let mut state = self.state.lock().unwrap();
+                let len = state.buffer.len().min(self.batch_size);
+                state.buffer.drain(..len).collect()
+            };
+
+            if batch.is_empty() {
+                return Ok(());
+            }
+
+            if let Err(err) = self.send(&batch).await {
+                self.requeue(batch);
+                return Err(err);
+            }
+        }
+    }
+
+    fn requeue(&self, batch: Vec<ExposureEvent>) {
+        let mut state = self.state.lock().unwrap();
+        for event in batch.into_iter().rev() {
+            state.buffer.push_front(event);
+        }
+
+        // drop the oldest events if new ones arrived while the batch was in flight
+        while state.buffer.len() > self.max_buffer_size {
+            state.buffer.pop_front();
+            state.dropped += 1;
+        }
+    }
+
+    async fn send(&self, batch: &[ExposureEvent]) -> Result<(), Error> {
+        let mut headers = self.auth_receiver.borrow().clone();
+        headers.insert(
+            reqwest::header::CONTENT_TYPE,
+            reqwest::header::HeaderValue::from_static("application/json"),
+        );
+
+        let body = serde_json::to_vec(&ExposureBatch { exposures: batch })
+            .map_err(|e| Error::Internal(format!("failed to serialize exposures: {e}")))?;
+
+        let response = self
+            .http_client
+            .post(&self.url)
+            .headers(headers)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| Error::Server(format!("failed to send exposures: {e}")))?;
+
+        response
+            .error_for_status()
+            .map_err(|e| Error::Server(format!("response: {e}")))?;
+
+        log::debug!("flushed {} exposures", batch.len());
+        Ok(())
+    }
+
+    /// Start the background task that flushes the buffer periodically, when a batch is full
+    /// and a final time when the engine is stopped.
+    pub fn start(
+        self: Arc<Self>,
+        handle: &tokio::runtime::Handle,
+        stop_signal: Arc<AtomicBool>,
+        stop_notify: Arc<Notify>,
+    ) -> tokio::task::JoinHandle<()> {
+        handle.spawn(async move {
+            loop {
+                if stop_signal.load(Ordering::Relaxed) {
+                    if tokio::time::timeout(FINAL_FLUSH_TIMEOUT, self.flush())
+                        .await
+                        .is_err()
+                    {
+                        log::warn!("timed out flushing exposures on shutdown");
+                    }
+                    return;
+                }
+
+                tokio::select! {
+                    _ = tokio::time::sleep(self.flush_interval) => {},
+                    _ = self.flush_notify.notified() => {},
+                    _ = stop_notify.notified() => {},
+                }
+
+                if let Err(e) = self.flush().await {
+                    log::warn!("error flushing exposures: {e}");
+                }
+            }
+        })
+    }
+
+    /// Wake the flush task, e.g. so it can observe the stop signal.
+    pub fn wake(&self) {
+        self.flush_notify.notify_one();
+    }
+}
+
+impl EvaluationHook for ExposureRecorder {
+    fn after(&self, namespace: &str, request: &EvaluationRequest, response: &HookResponse<'_>) {
+        if let HookResponse::Variant(response) = response {
+            if response.variant_key.is_empty() {
+                return;
+            }
+
+            self.record(ExposureEvent {
+                namespace_key: namespace.to_string(),
+                flag_key: response.flag_key.clone(),
+                entity_id: request.entity_id.clone(),
+                variant_key: response.variant_key.clone(),
+                r#match: response.r#match,
+                timestamp: response.timestamp.to_rfc3339(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Matcher, Server};
+
+    fn event(flag_key: &str, entity_id: &str) -> ExposureEvent {
+        ExposureEvent {
+            namespace_key: "default".into(),
+            flag_key: flag_key.into(),
+            entity_id: entity_id.into(),
+            variant_key: "variant1".into(),
+            r#match: true,
+            timestamp: "2024-01-01T00:00:00+00:00".into(),
+        }
+    }
+
+    fn recorder(url: &str, batch_size: usize, max_buffer_size: usize) -> ExposureRecorder {
+        recorder_with_retry(url, batch_size, max_buffer_size, &RetryOpts::default())
+    }
+
+    fn recorder_with_retry(
+        url: &str,
+        batch_size: usize,
+        max_buffer_size: usize,
+        retry: &RetryOpts,
+    ) -> ExposureRecorder {
+        let (_, auth_receiver) = watch::channel(HeaderMap::new());
+        ExposureRecorder::new(
+            ExposureOpts {
+                url: url.to_string(),
+                flush_interval: None,
+                batch_size: Some(batch_size),
+                max_buffer_size: Some(max_buffer_size),
+                dedup_window: None,
+            },
+            None,
+            &ConnectionOpts::default(),
+            retry,
+            auth_receiver,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_record_deduplicates_and_bounds_buffer() {
+        let recorder = recorder("http://localhost:1", 10, 2);
+
+        recorder.record(event("flag1", "entity1"));
+        recorder.record(event("flag1", "entity1"));
+        assert_eq!(recorder.buffered(), 1);
+
+        recorder.record(event("flag2", "entity1"));
+        recorder.record(event("flag1", "entity2"));
+        assert_eq!(recorder.buffered(), 2);
+        assert_eq!(recorder.dropped(), 1);
+    }
+
+    #[test]
+    fn test_record_deduplicates_per_variant() {
+        let recorder = recorder("http://localhost:1", 10, 10);
+
+        recorder.record(event("flag1", "entity1"));
+        recorder.record(ExposureEvent {
+            variant_key: "variant2".into(),
+            ..event("flag1", "entity1")
+        });
+        recorder.record(event("flag1", "entity1"));
+        assert_eq!(recorder.buffered(), 2);
+    }
+
+    #[test]
+    fn test_record_bounds_dedup_entries() {
+        let mut recorder = recorder("http://localhost:1", 100, 100);
+        recorder.max_dedup_entries = 2;
+
+        recorder.record(event("flag1", "entity1"));
+        recorder.record(event("flag1", "entity2"));
+        recorder.record(event("flag1", "entity3"));
+        assert_eq!(recorder.state.lock().unwrap().seen.len(), 2);
+
+        // the oldest exposure was forgotten and is recorded again, unlike a recent one
+        recorder.record(event("flag1", "entity1"));
+        recorder.record(event("flag1", "entity3"));
+        assert_eq!(recorder.buffered(), 4);
+    }
+
+    #[test]
+    fn test_record_forgets_expired_exposures() {
+        let mut recorder = recorder("http://localhost:1", 100, 100);
+        recorder.dedup_window = Duration::ZERO;
+
+        recorder.record(event("flag1", "entity1"));
+        recorder.record(event("flag1", "entity1"));
+        assert_eq!(recorder.buffered(), 2);
+        assert_eq!(recorder.state.lock().unwrap().seen.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_flush_sends_batches() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/exposures")
+            .match_body(Matcher::PartialJsonString(
+                r#"{"exposures":[{"flag_key":"flag1","entity_id":"entity1"}]}"#.into(),
+            ))
+            .with_status(202)
+            .expect(1)
+            .create_async()
+            .await;
+        let mock_second = server
+            .mock("POST", "/exposures")
+            .match_body(Matcher::PartialJsonString(
+                r#"{"exposures":[{"flag_key":"flag2","entity_id":"entity1"}]}"#.into(),
+            ))
+            .with_status(202)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let recorder = recorder(&format!("{}/exposures", server.url()), 1, 10);
+        recorder.record(event("flag1", "entity1"));
+        recorder.record(event("flag2", "entity1"));
+
+        recorder.flush().await.unwrap();
+
+        mock.assert_async().await;
+        mock_second.assert_async().await;
+        assert_eq!(recorder.buffered(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_flush_failure_keeps_events() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/exposures")
+            .with_status(400)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let recorder = recorder(&format!("{}/exposures", server.url()), 10, 10);
+        recorder.record(event("flag1", "entity1"));
+
+        assert!(recorder.flush().await.is_err());
+
+        mock.assert_async().await;
+        assert_eq!(recorder.buffered(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_flush_uses_retry_opts() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/exposures")
+            .with_status(503)
+            .expect(3)
+            .create_async()
+            .await;
+
+        let recorder = recorder_with_retry(
+            &format!("{}/exposures", server.url()),
+            10,
+            10,
+            &RetryOpts {
+                max_retries: Some(2),
+                min_backoff_ms: Some(1),
+                max_backoff_ms: Some(1),
+                ..Default::default()
+            },
+        );
+        recorder.record(event("flag1", "entity1"));
+
+        assert!(recorder.flush().await.is_err());
+
+        mock.assert_async().await;
+        assert_eq!(recorder.buffered(), 1);
+    }
+}
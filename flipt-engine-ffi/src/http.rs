@@ -1,7 +1,8 @@
 use std::error::Error as StdError;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
 use async_trait::async_trait;
 use futures::TryStreamExt;
@@ -10,14 +11,24 @@ use reqwest::header::{self, HeaderMap};
 use reqwest::Response;
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware, Next};
 use reqwest_retry::policies::ExponentialBackoff;
-use reqwest_retry::{Jitter, RetryTransientMiddleware};
-use serde::Deserialize;
+use reqwest_retry::{
+    default_on_request_failure, default_on_request_success, Jitter, RetryTransientMiddleware,
+    Retryable, RetryableStrategy,
+};
+use serde::{Deserialize, Serialize};
 use tokio::sync::{mpsc, watch, Notify};
+use tokio_util::codec::LinesCodecError;
 use tokio_util::io::StreamReader;
 
+use fliptevaluation::declarative;
 use fliptevaluation::error::Error;
 use fliptevaluation::models::source;
+use fliptevaluation::signature::Verifier;
 
+use crate::backoff::{jitter, Backoff};
+use crate::events::{EngineEvent, Notifier};
+use crate::snapshot_cache::SnapshotCache;
+use crate::status::StatusTracker;
 use crate::tls::configure_tls;
 use crate::TlsConfig;
 
@@ -73,7 +84,7 @@ impl From<Authentication> for HeaderMap {
     }
 }
 
-#[derive(Debug, Clone, Default, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 #[cfg_attr(test, derive(PartialEq))]
 #[serde(rename_all = "snake_case")]
 #[non_exhaustive]
@@ -81,6 +92,9 @@ pub enum FetchMode {
     #[default]
     Polling,
     Streaming,
+    /// Load the snapshot document from a file and reload it whenever the file changes. Files with
+    /// a `.yml` or `.yaml` extension are read as Flipt declarative `features.yml` documents.
+    File(PathBuf),
 }
 
 #[derive(Debug, Clone, Default, Deserialize, PartialEq)]
@@ -94,6 +108,89 @@ pub enum ErrorStrategy {
     Fallback,
 }
 
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const DEFAULT_RETRY_MIN_BACKOFF: Duration = Duration::from_secs(1);
+const DEFAULT_RETRY_MAX_BACKOFF: Duration = Duration::from_secs(30);
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_POOL_MAX_IDLE_PER_HOST: usize = 10;
+const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Policy for retrying requests that failed transiently.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct RetryOpts {
+    /// Retries after the initial attempt; zero disables retrying.
+    pub max_retries: Option<u32>,
+    /// Milliseconds to wait before the first retry.
+    pub min_backoff_ms: Option<u64>,
+    /// Maximum milliseconds to wait between retries.
+    pub max_backoff_ms: Option<u64>,
+    pub jitter: Option<RetryJitter>,
+}
+
+/// Randomization applied to the delay between retries.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[cfg_attr(test, derive(PartialEq))]
+#[serde(rename_all = "snake_case")]
+pub enum RetryJitter {
+    None,
+    /// Between zero and the computed delay.
+    #[default]
+    Full,
+    /// Between half the minimum backoff and the computed delay.
+    Bounded,
+}
+
+impl From<RetryJitter> for Jitter {
+    fn from(value: RetryJitter) -> Self {
+        match value {
+            RetryJitter::None => Jitter::None,
+            RetryJitter::Full => Jitter::Full,
+            RetryJitter::Bounded => Jitter::Bounded,
+        }
+    }
+}
+
+/// Connection settings of the HTTP client.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct ConnectionOpts {
+    /// Milliseconds allowed for establishing a connection.
+    pub connect_timeout_ms: Option<u64>,
+    /// Maximum idle connections kept open per host.
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Milliseconds an idle connection is kept open.
+    pub pool_idle_timeout_ms: Option<u64>,
+}
+
+/// How often a snapshot file is checked for changes.
+const FILE_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest wait between polls while fetches keep failing, unless the update interval is longer.
+const MAX_POLL_BACKOFF: Duration = Duration::from_secs(600);
+/// Fraction of the update interval by which polls are spread out.
+const POLL_JITTER: f64 = 0.1;
+/// Longest delay honored from a `Retry-After` header.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(3600);
+
+const DEFAULT_RECONNECT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const DEFAULT_RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Configuration for keeping the streaming connection up.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct StreamingOpts {
+    /// Milliseconds to wait before reconnecting a dropped stream, doubled after each failed attempt.
+    pub reconnect_initial_backoff_ms: Option<u64>,
+    /// Maximum milliseconds to wait between reconnect attempts.
+    pub reconnect_max_backoff_ms: Option<u64>,
+    /// Poll for snapshots, at most once per update interval, while the stream is down.
+    pub fallback_to_polling: Option<bool>,
+    /// Milliseconds without a chunk or heartbeat after which the stream is considered stalled and
+    /// reconnected. Disabled by default.
+    pub idle_timeout_ms: Option<u64>,
+}
+
 pub struct HTTPFetcher {
     http_client: ClientWithMiddleware,
     base_url: String,
@@ -105,8 +202,27 @@ pub struct HTTPFetcher {
     reference: Option<String>,
     update_interval: Duration,
     mode: FetchMode,
+    verifier: Option<Verifier>,
+    notifier: Option<Notifier>,
+    status: StatusTracker,
+    reconnect: Backoff,
+    fallback_to_polling: bool,
+    last_fallback_poll: Option<Instant>,
+    idle_timeout: Option<Duration>,
+    poll_backoff: Backoff,
+    poll_failing: bool,
+    retry_after: Option<Duration>,
+    snapshot_cache: Option<SnapshotCache>,
+    file_version: Option<FileVersion>,
 }
 
+/// Modification time and size of a file.
+type FileStamp = (SystemTime, u64);
+
+/// Stamps of a snapshot file and, when signatures are verified, of its signature file, used to
+/// detect changes.
+type FileVersion = (FileStamp, Option<FileStamp>);
+
 impl Clone for HTTPFetcher {
     fn clone(&self) -> Self {
         Self {
@@ -120,6 +236,18 @@ impl Clone for HTTPFetcher {
             reference: self.reference.clone(),
             update_interval: self.update_interval,
             mode: self.mode.clone(),
+            verifier: self.verifier.clone(),
+            notifier: self.notifier.clone(),
+            status: self.status.clone(),
+            reconnect: self.reconnect.clone(),
+            fallback_to_polling: self.fallback_to_polling,
+            last_fallback_poll: self.last_fallback_poll,
+            idle_timeout: self.idle_timeout,
+            poll_backoff: self.poll_backoff.clone(),
+            poll_failing: self.poll_failing,
+            retry_after: self.retry_after,
+            snapshot_cache: self.snapshot_cache.clone(),
+            file_version: self.file_version,
         }
     }
 }
@@ -134,15 +262,54 @@ pub struct HTTPFetcherBuilder {
     update_interval: Duration,
     mode: FetchMode,
     tls_config: Option<TlsConfig>,
+    verifier: Option<Verifier>,
+    streaming: StreamingOpts,
+    retry: RetryOpts,
+    connection: ConnectionOpts,
+    etag: Option<String>,
+    snapshot_cache: Option<SnapshotCache>,
 }
 
+/// Response header carrying the base64-encoded signature of the snapshot document.
+const SIGNATURE_HEADER: &str = "X-Flipt-Signature";
+
 #[derive(Deserialize)]
 struct StreamChunk {
-    result: source::Document,
+    result: Box<serde_json::value::RawValue>,
+    #[serde(default)]
+    signature: Option<String>,
+}
+
+impl StreamChunk {
+    fn into_document(self, verifier: Option<&Verifier>) -> FetchResult {
+        if let Some(verifier) = verifier {
+            verifier.verify(self.result.get().as_bytes(), self.signature.as_deref())?;
+        }
+
+        serde_json::from_str(self.result.get())
+            .map_err(|e| Error::InvalidJSON(format!("failed to parse response body: {e}")))
+    }
 }
 
 static USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
 
+/// Retries transient failures like the default strategy, except responses carrying a
+/// `Retry-After` delay, which are left to the caller so the requested delay is honored.
+struct RetryAfterStrategy;
+
+impl RetryableStrategy for RetryAfterStrategy {
+    fn handle(
+        &self,
+        res: &Result<reqwest::Response, reqwest_middleware::Error>,
+    ) -> Option<Retryable> {
+        match res {
+            Ok(response) if retry_after(response).is_some() => Some(Retryable::Fatal),
+            Ok(response) => default_on_request_success(response),
+            Err(error) => default_on_request_failure(error),
+        }
+    }
+}
+
 /// Logging middleware that logs each request attempt
 #[derive(Debug, Clone, Default)]
 pub struct LoggingMiddleware {}
@@ -208,6 +375,12 @@ impl HTTPFetcherBuilder {
             update_interval: Duration::from_secs(120),
             mode: FetchMode::default(),
             tls_config: None,
+            verifier: None,
+            streaming: StreamingOpts::default(),
+            retry: RetryOpts::default(),
+            connection: ConnectionOpts::default(),
+            etag: None,
+            snapshot_cache: None,
         }
     }
 
@@ -255,18 +428,41 @@ impl HTTPFetcherBuilder {
         self
     }
 
+    /// Only accept documents signed for the given verifier.
+    pub fn verifier(mut self, verifier: Verifier) -> Self {
+        self.verifier = Some(verifier);
+        self
+    }
+
+    pub fn streaming(mut self, streaming: StreamingOpts) -> Self {
+        self.streaming = streaming;
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryOpts) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn connection(mut self, connection: ConnectionOpts) -> Self {
+        self.connection = connection;
+        self
+    }
+
+    /// Send the first request conditionally on the snapshot with the given ETag being outdated.
+    pub fn etag(mut self, etag: &str) -> Self {
+        self.etag = Some(etag.to_string());
+        self
+    }
+
+    /// Refresh the cached snapshot whenever the server reports it is not modified.
+    pub fn snapshot_cache(mut self, snapshot_cache: SnapshotCache) -> Self {
+        self.snapshot_cache = Some(snapshot_cache);
+        self
+    }
+
     pub fn build(self) -> Result<HTTPFetcher, Error> {
-        let retry_policy = ExponentialBackoff::builder()
-            .retry_bounds(Duration::from_secs(1), Duration::from_secs(30))
-            .jitter(Jitter::Full)
-            .build_with_max_retries(3);
-
-        let mut client_builder = reqwest::Client::builder()
-            .user_agent(USER_AGENT)
-            .tcp_keepalive(Duration::from_secs(5))
-            .pool_max_idle_per_host(10)
-            .pool_idle_timeout(Duration::from_secs(60))
-            .connect_timeout(Duration::from_secs(10));
+        let mut client_builder = client_builder(&self.connection);
 
         match self.mode {
             FetchMode::Polling => {
@@ -283,37 +479,113 @@ impl HTTPFetcherBuilder {
                     .http2_initial_connection_window_size(32 * 1024)
                     .http2_keep_alive_while_idle(true);
             }
+            FetchMode::File(_) => {}
         }
 
-        // Apply TLS configuration if provided
-        if let Some(tls_config) = &self.tls_config {
-            client_builder = configure_tls(client_builder, tls_config)?;
-        }
-
-        let client = client_builder
-            .build()
-            .map_err(|e| Error::Internal(format!("failed to create client: {e}")))?;
+        let http_client = build_client(client_builder, self.tls_config.as_ref(), &self.retry)?;
 
         let (auth_sender, auth_receiver) = watch::channel(self.authentication);
 
+        let reconnect = Backoff::new(
+            self.streaming
+                .reconnect_initial_backoff_ms
+                .map_or(DEFAULT_RECONNECT_INITIAL_BACKOFF, Duration::from_millis),
+            self.streaming
+                .reconnect_max_backoff_ms
+                .map_or(DEFAULT_RECONNECT_MAX_BACKOFF, Duration::from_millis),
+        );
+
         Ok(HTTPFetcher {
             base_url: self.base_url,
             environment: self.environment.unwrap_or("default".to_string()),
             namespace: self.namespace.unwrap_or("default".to_string()),
-            http_client: ClientBuilder::new(client)
-                .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-                .with(LoggingMiddleware::default())
-                .build(),
+            http_client,
             auth_receiver,
             auth_sender: Some(auth_sender),
-            etag: None,
+            etag: self.etag,
             reference: self.reference,
             update_interval: self.update_interval,
+            status: StatusTracker::new(self.mode.clone()),
             mode: self.mode,
+            verifier: self.verifier,
+            notifier: None,
+            reconnect,
+            fallback_to_polling: self.streaming.fallback_to_polling.unwrap_or(false),
+            last_fallback_poll: None,
+            idle_timeout: self
+                .streaming
+                .idle_timeout_ms
+                .filter(|timeout| *timeout > 0)
+                .map(Duration::from_millis),
+            poll_backoff: Backoff::new(self.update_interval * 2, MAX_POLL_BACKOFF),
+            poll_failing: false,
+            retry_after: None,
+            snapshot_cache: self.snapshot_cache,
+            file_version: None,
         })
     }
 }
 
+/// Base client configuration shared by every HTTP client created by the engine.
+pub(crate) fn client_builder(connection: &ConnectionOpts) -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .tcp_keepalive(Duration::from_secs(5))
+        .pool_max_idle_per_host(
+            connection
+                .pool_max_idle_per_host
+                .unwrap_or(DEFAULT_POOL_MAX_IDLE_PER_HOST),
+        )
+        .pool_idle_timeout(
+            connection
+                .pool_idle_timeout_ms
+                .map_or(DEFAULT_POOL_IDLE_TIMEOUT, Duration::from_millis),
+        )
+        .connect_timeout(
+            connection
+                .connect_timeout_ms
+                .filter(|timeout| *timeout > 0)
+                .map_or(DEFAULT_CONNECT_TIMEOUT, Duration::from_millis),
+        )
+}
+
+/// Finish building a client, applying TLS configuration and the retry and logging middleware.
+pub(crate) fn build_client(
+    mut client_builder: reqwest::ClientBuilder,
+    tls_config: Option<&TlsConfig>,
+    retry: &RetryOpts,
+) -> Result<ClientWithMiddleware, Error> {
+    let min_backoff = retry
+        .min_backoff_ms
+        .map_or(DEFAULT_RETRY_MIN_BACKOFF, Duration::from_millis);
+    let max_backoff = retry
+        .max_backoff_ms
+        .map_or(DEFAULT_RETRY_MAX_BACKOFF, Duration::from_millis)
+        .max(min_backoff);
+
+    let retry_policy = ExponentialBackoff::builder()
+        .retry_bounds(min_backoff, max_backoff)
+        .jitter(retry.jitter.unwrap_or_default().into())
+        .build_with_max_retries(retry.max_retries.unwrap_or(DEFAULT_MAX_RETRIES));
+
+    // Apply TLS configuration if provided
+    if let Some(tls_config) = tls_config {
+        client_builder = configure_tls(client_builder, tls_config)?;
+    }
+
+    let client = client_builder
+        .build()
+        .map_err(|e| Error::Internal(format!("failed to create client: {e}")))?;
+
+    Ok(ClientBuilder::new(client)
+        .with(RetryTransientMiddleware::new_with_policy_and_strategy(
+            retry_policy,
+            RetryAfterStrategy,
+        ))
+        .with(LoggingMiddleware::default())
+        .build())
+}
+
 type FetchResult = Result<source::Document, Error>;
 
 impl HTTPFetcher {
@@ -322,6 +594,45 @@ impl HTTPFetcher {
         self.auth_sender.take()
     }
 
+    /// Report connection changes of the fetcher to the given notifier.
+    pub fn set_notifier(&mut self, notifier: Notifier) {
+        self.notifier = Some(notifier);
+    }
+
+    fn notify(&self, event: EngineEvent) {
+        if let Some(notifier) = &self.notifier {
+            notifier.notify(event);
+        }
+    }
+
+    pub fn snapshot_cache(&self) -> Option<SnapshotCache> {
+        self.snapshot_cache.clone()
+    }
+
+    /// The server confirmed that the current snapshot is up to date.
+    fn not_modified(&mut self) {
+        self.poll_failing = false;
+        self.status.record_success();
+        if let Some(cache) = &self.snapshot_cache {
+            cache.touch();
+        }
+    }
+
+    /// Fetch health recorded by the fetcher.
+    pub fn status(&self) -> StatusTracker {
+        self.status.clone()
+    }
+
+    fn set_etag(&mut self, etag: Option<String>) {
+        self.status.set_etag(etag.clone());
+        self.etag = etag;
+    }
+
+    /// Subscribe to the authentication headers used by the fetcher, including later updates.
+    pub fn auth_receiver(&self) -> watch::Receiver<HeaderMap> {
+        self.auth_receiver.clone()
+    }
+
     /// Start the fetcher and return a channel to receive updates on the snapshot changes
     pub fn start(
         &mut self,
@@ -331,7 +642,6 @@ impl HTTPFetcher {
         let (tx, rx) = mpsc::channel(100);
 
         let mut fetcher = self.clone();
-        let update_interval = fetcher.update_interval;
         // Clone for move into async
         let stop_notify_clone = stop_notify.clone();
 
@@ -343,24 +653,9 @@ impl HTTPFetcher {
                             log::warn!("error fetching polling: {e}");
                             break;
                         }
-                        let mut elapsed = Duration::ZERO;
-                        let interval = update_interval;
-                        let check = Duration::from_millis(500);
-
-                        while elapsed < interval {
-                            if stop_signal.load(Ordering::Relaxed) {
-                                return;
-                            }
-                            let sleep = tokio::time::sleep(check);
-                            tokio::pin!(sleep);
-                            tokio::select! {
-                                _ = &mut sleep => {
-                                    elapsed += check;
-                                },
-                                _ = stop_notify_clone.notified() => {
-                                    return;
-                                },
-                            }
+                        let delay = fetcher.next_poll_delay();
+                        if !sleep_unless_stopped(delay, &stop_signal, &stop_notify_clone).await {
+                            return;
                         }
                     }
                     FetchMode::Streaming => {
@@ -375,6 +670,34 @@ impl HTTPFetcher {
                         if stop_signal.load(Ordering::Relaxed) {
                             return;
                         }
+
+                        if fetcher.fallback_poll_due() {
+                            if let Err(e) = fetcher.handle_polling(&tx).await {
+                                log::warn!("error fetching polling: {e}");
+                                break;
+                            }
+                        }
+
+                        let delay = fetcher.reconnect.next_delay();
+                        log::info!("stream closed, reconnecting in {delay:?}");
+                        if !sleep_unless_stopped(delay, &stop_signal, &stop_notify_clone).await {
+                            return;
+                        }
+                    }
+                    FetchMode::File(_) => {
+                        if let Err(e) = fetcher.handle_file(&tx).await {
+                            log::warn!("error reading snapshot file: {e}");
+                            break;
+                        }
+                        if !sleep_unless_stopped(
+                            FILE_WATCH_INTERVAL,
+                            &stop_signal,
+                            &stop_notify_clone,
+                        )
+                        .await
+                        {
+                            return;
+                        }
                     }
                 }
             }
@@ -384,18 +707,82 @@ impl HTTPFetcher {
         rx
     }
 
-    pub async fn initial_fetch(&mut self) -> FetchResult {
-        let response = self.fetch().await?;
+    /// How long to wait before the next poll: the update interval with some jitter, backing off
+    /// while fetches fail, and never sooner than the server asked for with `Retry-After`.
+    fn next_poll_delay(&mut self) -> Duration {
+        let delay = if self.poll_failing {
+            self.poll_backoff.next_delay()
+        } else {
+            self.poll_backoff.reset();
+            jitter(self.update_interval, POLL_JITTER)
+        };
+
+        match self.retry_after.take() {
+            Some(retry_after) => delay.max(retry_after),
+            None => delay,
+        }
+    }
+
+    /// Whether to poll while the stream is down, which happens at most once per update interval.
+    fn fallback_poll_due(&mut self) -> bool {
+        if !self.fallback_to_polling {
+            return false;
+        }
+
+        let due = self
+            .last_fallback_poll
+            .is_none_or(|last| last.elapsed() >= self.update_interval);
+        if due {
+            self.last_fallback_poll = Some(Instant::now());
+        }
+        due
+    }
+
+    /// Fetch the current snapshot document, or `None` if it was not modified since the ETag the
+    /// fetcher was built with.
+    pub async fn initial_fetch(&mut self) -> Result<Option<source::Document>, Error> {
+        if let FetchMode::File(path) = &self.mode {
+            let path = path.clone();
+            let version = file_version(&path, self.verifier.is_some()).await;
+            return self.load_file(&path, version).await.map(Some);
+        }
 
-        match response {
-            Some(resp) => {
-                let document = resp.json::<source::Document>().await.map_err(|e| {
-                    Error::InvalidJSON(format!("failed to parse response body: {e}"))
-                })?;
-                Ok(document)
+        let result = match self.fetch().await {
+            Ok(Some(resp)) => self.read_document(resp).await,
+            Ok(None) => {
+                self.not_modified();
+                return Ok(None);
+            }
+            Err(e) => Err(e),
+        };
+
+        self.status.record(&result);
+        result.map(Some)
+    }
+
+    /// Read the snapshot document from a response, verifying its signature when required.
+    async fn read_document(&mut self, response: Response) -> FetchResult {
+        let signature = response
+            .headers()
+            .get(SIGNATURE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| Error::Server(format!("failed to read response body: {e}")))?;
+
+        if let Some(verifier) = &self.verifier {
+            if let Err(e) = verifier.verify(&body, signature.as_deref()) {
+                // Forget the etag so the rejected document is not skipped as unmodified next time
+                self.set_etag(None);
+                return Err(e);
             }
-            None => Err(Error::Server("no data received from server".into())),
         }
+
+        serde_json::from_slice(&body)
+            .map_err(|e| Error::InvalidJSON(format!("failed to parse response body: {e}")))
     }
 
     fn build_headers(&self) -> HeaderMap {
@@ -455,29 +842,32 @@ impl HTTPFetcher {
             .send()
             .await
         {
-            Ok(response) => match response.error_for_status() {
-                Ok(response) => match response.status() {
-                    reqwest::StatusCode::NOT_MODIFIED => Ok(None),
-                    reqwest::StatusCode::OK => {
-                        if let Some(etag) = response.headers().get(reqwest::header::ETAG) {
-                            self.etag = Some(etag.to_str().unwrap().to_string());
-                        }
+            Ok(response) => {
+                self.retry_after = retry_after(&response);
+                match response.error_for_status() {
+                    Ok(response) => match response.status() {
+                        reqwest::StatusCode::NOT_MODIFIED => Ok(None),
+                        reqwest::StatusCode::OK => {
+                            if let Some(etag) = response.headers().get(reqwest::header::ETAG) {
+                                self.set_etag(Some(etag.to_str().unwrap().to_string()));
+                            }
 
-                        Ok(Some(response))
-                    }
-                    _ => {
-                        self.etag = None;
-                        let status = response.status();
-                        Err(Error::Server(format!("unexpected http response: {status}")))
+                            Ok(Some(response))
+                        }
+                        _ => {
+                            self.set_etag(None);
+                            let status = response.status();
+                            Err(Error::Server(format!("unexpected http response: {status}")))
+                        }
+                    },
+                    Err(e) => {
+                        self.set_etag(None);
+                        Err(Error::Server(format!("response: {e}")))
                     }
-                },
-                Err(e) => {
-                    self.etag = None;
-                    Err(Error::Server(format!("response: {e}")))
                 }
-            },
+            }
             Err(e) => {
-                self.etag = None;
+                self.set_etag(None);
                 Err(Error::Server(format!("failed to make request: {e}")))
             }
         }
@@ -509,22 +899,75 @@ impl HTTPFetcher {
         sender: &mpsc::Sender<Result<source::Document, Error>>,
     ) -> Result<(), Error> {
         let result = match self.fetch().await {
-            Ok(Some(resp)) => match resp.json::<source::Document>().await {
-                Ok(doc) => Ok(doc),
-                Err(e) => Err(Error::InvalidJSON(format!(
-                    "failed to parse response body: {e}"
-                ))),
-            },
-            Ok(None) => return Ok(()),
+            Ok(Some(resp)) => self.read_document(resp).await,
+            Ok(None) => {
+                self.not_modified();
+                return Ok(());
+            }
             Err(e) => Err(e),
         };
 
+        self.poll_failing = result.is_err();
+        self.status.record(&result);
         sender
             .send(result)
             .await
             .map_err(|_| Error::Internal("failed to send result".into()))
     }
 
+    /// Reload the snapshot file if it changed since it was last read.
+    async fn handle_file(
+        &mut self,
+        sender: &mpsc::Sender<Result<source::Document, Error>>,
+    ) -> Result<(), Error> {
+        let FetchMode::File(path) = &self.mode else {
+            return Ok(());
+        };
+        let path = path.clone();
+
+        let version = file_version(&path, self.verifier.is_some()).await;
+        if version.is_some() && version == self.file_version {
+            return Ok(());
+        }
+        // A missing file is reported once rather than on every check
+        if version.is_none() && self.file_version.is_none() && self.poll_failing {
+            return Ok(());
+        }
+
+        let result = self.load_file(&path, version).await;
+        sender
+            .send(result)
+            .await
+            .map_err(|_| Error::Internal("failed to send result".into()))
+    }
+
+    /// Read the snapshot file and record the outcome. The version is only recorded once the
+    /// signature verified, so a snapshot is verified again until its signature file catches up.
+    async fn load_file(&mut self, path: &PathBuf, version: Option<FileVersion>) -> FetchResult {
+        let result = read_file(path, &self.namespace, self.verifier.as_ref()).await;
+        self.file_version = match result {
+            Err(Error::InvalidSignature(_)) => None,
+            _ => version,
+        };
+        self.poll_failing = result.is_err();
+        self.status.record(&result);
+        result
+    }
+
+    /// Read the document carried by a stream frame. Blank lines are heartbeats and carry none.
+    fn read_frame(&self, frame: Result<String, LinesCodecError>) -> Option<FetchResult> {
+        let result = match frame {
+            Ok(frame) if frame.trim().is_empty() => return None,
+            Ok(frame) => serde_json::from_str::<StreamChunk>(&frame)
+                .map_err(|e| Error::InvalidJSON(format!("failed to parse response body: {e}")))
+                .and_then(|chunk| chunk.into_document(self.verifier.as_ref())),
+            Err(e) => Err(Error::Server(format!("failed to read stream chunk: {e}"))),
+        };
+
+        self.status.record(&result);
+        Some(result)
+    }
+
     async fn handle_streaming(
         &mut self,
         sender: &mpsc::Sender<Result<source::Document, Error>>,
@@ -538,60 +981,188 @@ impl HTTPFetcher {
                 let reader = StreamReader::new(resp.bytes_stream().map_err(std::io::Error::other));
                 let codec = tokio_util::codec::LinesCodec::new();
                 let frame_reader = tokio_util::codec::FramedRead::new(reader, codec);
+                let mut frames = frame_reader.into_stream();
+
+                self.status.set_stream_connected(true);
+                self.notify(EngineEvent::StreamConnected);
 
-                let mut stream = frame_reader
-                    .into_stream()
-                    .map(|frame| match frame {
-                        Ok(frame) => match serde_json::from_str::<StreamChunk>(&frame) {
-                            Ok(result) => Ok(result),
-                            Err(e) => Err(Error::InvalidJSON(format!(
-                                "failed to parse response body: {e}"
-                            ))),
-                        },
-                        Err(e) => Err(Error::Server(format!("failed to read stream chunk: {e}"))),
-                    })
-                    .map(|result| match result {
-                        Ok(result) => Ok(result.result),
-                        Err(err) => Err(err),
-                    });
-
-                loop {
+                let mut stalled = None;
+                let result = loop {
                     if stop_signal.load(Ordering::Relaxed) {
-                        return Ok(());
+                        break Ok(());
                     }
 
                     let stop_notified = stop_notify.notified();
                     tokio::pin!(stop_notified);
 
+                    // Restarted with every frame, so only a stream that goes quiet times out
+                    let idle_timeout = self.idle_timeout;
+                    let idle = async move {
+                        match idle_timeout {
+                            Some(timeout) => tokio::time::sleep(timeout).await,
+                            None => std::future::pending().await,
+                        }
+                    };
+
                     tokio::select! {
-                        value = stream.next() => {
-                            match value {
-                                Some(result) => {
-                                    match sender.send(result).await {
-                                        Ok(_) => continue,
-                                        Err(e) => {
-                                            return Err(Error::Internal(format!(
-                                                "failed to send result to engine {e}"
-                                            )))
-                                        }
-                                    }
+                        frame = frames.next() => {
+                            let Some(frame) = frame else {
+                                break Ok(());
+                            };
+                            let Some(result) = self.read_frame(frame) else {
+                                continue;
+                            };
+                            if result.is_ok() {
+                                self.reconnect.reset();
+                            }
+                            match sender.send(result).await {
+                                Ok(_) => continue,
+                                Err(e) => {
+                                    break Err(Error::Internal(format!(
+                                        "failed to send result to engine {e}"
+                                    )))
                                 }
-                                None => return Ok(()),
                             }
                         }
+                        _ = idle => {
+                            let error = Error::Server(format!(
+                                "stream received no data for {:?}",
+                                idle_timeout.unwrap_or_default()
+                            ));
+                            log::warn!("{error}, reconnecting");
+                            self.status.record_stream_stall(&error);
+                            stalled = Some(error);
+                            break Ok(());
+                        }
                         _ = &mut stop_notified => {
-                            return Ok(());
+                            break Ok(());
                         }
                     }
-                }
+                };
+
+                self.status.set_stream_connected(false);
+                self.notify(EngineEvent::StreamDisconnected {
+                    error: result
+                        .as_ref()
+                        .err()
+                        .or(stalled.as_ref())
+                        .map(|e| e.to_string()),
+                });
+                result
             }
             Ok(None) => Ok(()),
-            Err(e) => sender
-                .send(Err(e.clone()))
-                .await
-                .map_err(|_| Error::Internal("failed to send error".into())),
+            Err(e) => {
+                self.status.record_failure(&e);
+                sender
+                    .send(Err(e.clone()))
+                    .await
+                    .map_err(|_| Error::Internal("failed to send error".into()))
+            }
+        }
+    }
+}
+
+async fn file_version(path: &PathBuf, signed: bool) -> Option<FileVersion> {
+    let snapshot = file_stamp(path).await?;
+    let signature = if signed {
+        file_stamp(&signature_path(path)).await
+    } else {
+        None
+    };
+    Some((snapshot, signature))
+}
+
+async fn file_stamp(path: &PathBuf) -> Option<FileStamp> {
+    let metadata = tokio::fs::metadata(path).await.ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+fn signature_path(path: &Path) -> PathBuf {
+    let mut signature_path = path.as_os_str().to_owned();
+    signature_path.push(".sig");
+    signature_path.into()
+}
+
+/// Read a snapshot document from a file. When signatures are verified the signature is read from
+/// the file of the same name with a `.sig` extension appended.
+async fn read_file(path: &PathBuf, namespace: &str, verifier: Option<&Verifier>) -> FetchResult {
+    let bytes = tokio::fs::read(path)
+        .await
+        .map_err(|e| Error::Server(format!("failed to read {}: {e}", path.display())))?;
+
+    if let Some(verifier) = verifier {
+        let signature = tokio::fs::read_to_string(signature_path(path)).await.ok();
+        verifier.verify(&bytes, signature.as_deref())?;
+    }
+
+    let declarative = path
+        .extension()
+        .is_some_and(|extension| extension == "yml" || extension == "yaml");
+    if declarative {
+        let yaml = String::from_utf8(bytes)
+            .map_err(|e| Error::InvalidYAML(format!("failed to parse {}: {e}", path.display())))?;
+        return declarative::parse_document(&yaml, namespace);
+    }
+
+    serde_json::from_slice(&bytes)
+        .map_err(|e| Error::InvalidJSON(format!("failed to parse {}: {e}", path.display())))
+}
+
+/// The delay requested by a `Retry-After` header on a rate limited or unavailable response, given
+/// either in seconds or as an HTTP date, and capped to `MAX_RETRY_AFTER`.
+fn retry_after(response: &Response) -> Option<Duration> {
+    if !matches!(
+        response.status(),
+        reqwest::StatusCode::TOO_MANY_REQUESTS | reqwest::StatusCode::SERVICE_UNAVAILABLE
+    ) {
+        return None;
+    }
+
+    let value = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+
+    let delay = match value.parse::<u64>() {
+        Ok(seconds) => Duration::from_secs(seconds),
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()?
+            .duration_since(SystemTime::now())
+            .unwrap_or_default(),
+    };
+
+    Some(delay.min(MAX_RETRY_AFTER))
+}
+
+/// Sleep for `duration`, returning false if the fetcher was stopped in the meantime.
+async fn sleep_unless_stopped(
+    duration: Duration,
+    stop_signal: &AtomicBool,
+    stop_notify: &Notify,
+) -> bool {
+    let check = Duration::from_millis(500);
+    let mut elapsed = Duration::ZERO;
+
+    while elapsed < duration {
+        if stop_signal.load(Ordering::Relaxed) {
+            return false;
+        }
+        let step = check.min(duration - elapsed);
+        let sleep = tokio::time::sleep(step);
+        tokio::pin!(sleep);
+        tokio::select! {
+            _ = &mut sleep => {
+                elapsed += step;
+            },
+            _ = stop_notify.notified() => {
+                return false;
+            },
         }
     }
+
+    !stop_signal.load(Ordering::Relaxed)
 }
 
 #[cfg(test)]
@@ -600,12 +1171,16 @@ mod tests {
     use mockito::{Matcher, Server};
     use std::sync::atomic::{AtomicBool, Ordering};
     use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
 
     use reqwest::header::HeaderMap;
 
     use crate::http::Authentication;
     use crate::http::FetchMode;
     use crate::http::HTTPFetcherBuilder;
+    use crate::http::{ConnectionOpts, FetchResult, RetryOpts, StreamingOpts, MAX_RETRY_AFTER};
+    use fliptevaluation::error::Error;
+    use fliptevaluation::signature::Verifier;
     use tokio::sync::{mpsc, Notify};
 
     #[tokio::test]
@@ -633,6 +1208,7 @@ mod tests {
         mock.assert();
 
         assert_eq!(fetcher.etag, Some("etag".to_string()));
+        assert_eq!(fetcher.status().status().etag, Some("etag".to_string()));
     }
 
     #[tokio::test]
@@ -846,7 +1422,13 @@ mod tests {
         assert_eq!(1, result.flags.len());
         // check third result
         let result = rx.recv().map(|r| r.expect("valid record")).await;
-        assert!(result.is_err())
+        assert!(result.is_err());
+
+        let status = fetcher.status().status();
+        assert!(status.last_success_at.is_some());
+        assert_eq!(status.consecutive_failures, 1);
+        // the stream ended
+        assert!(!status.stream_connected);
     }
 
     #[tokio::test]
@@ -872,6 +1454,513 @@ mod tests {
         mock.assert();
     }
 
+    fn sign(payload: &str) -> String {
+        use base64::prelude::*;
+        use hmac::{Hmac, Mac};
+
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(payload.as_bytes());
+        BASE64_STANDARD.encode(mac.finalize().into_bytes())
+    }
+
+    #[tokio::test]
+    async fn test_http_fetch_verifies_signature() {
+        let body = r#"{"namespace": {"key": "default"}, "flags":[]}"#;
+        let mut server = Server::new_async().await;
+        let signed = server
+            .mock("GET", "/internal/v1/evaluation/snapshot/namespace/default")
+            .with_status(200)
+            .with_header("etag", "signed")
+            .with_header("x-flipt-signature", &sign(body))
+            .with_body(body)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let mut fetcher = HTTPFetcherBuilder::new(&server.url())
+            .verifier(Verifier::Hmac("secret".into()))
+            .build()
+            .unwrap();
+
+        let result = fetcher.initial_fetch().await;
+        assert_eq!("default", result.unwrap().unwrap().namespace.key);
+        signed.assert_async().await;
+
+        signed.remove_async().await;
+        let tampered = server
+            .mock("GET", "/internal/v1/evaluation/snapshot/namespace/default")
+            .with_status(200)
+            .with_header("etag", "tampered")
+            .with_header("x-flipt-signature", &sign(body))
+            .with_body(r#"{"namespace": {"key": "default"}, "flags":[{"key": "evil", "name": "evil", "enabled": true}]}"#)
+            .create_async()
+            .await;
+
+        let result = fetcher.initial_fetch().await;
+        assert_eq!(
+            result.unwrap_err(),
+            Error::InvalidSignature("signature does not match".into())
+        );
+        assert_eq!(fetcher.etag, None);
+        tampered.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_http_fetch_stream_verifies_signature() {
+        let document = r#"{"namespace": {"key": "default"}, "flags":[]}"#;
+        let signed = format!(
+            "{{\"result\":{document},\"signature\":\"{}\"}}\n",
+            sign(document)
+        );
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock(
+                "GET",
+                "/client/v2/environments/default/namespaces/default/stream",
+            )
+            .with_status(200)
+            .with_chunked_body(move |w| {
+                w.write_all(signed.as_bytes())?;
+                w.write_all(
+                    b"{\"result\":{\"namespace\": {\"key\": \"default\"}, \"flags\":[]}}\n",
+                )?;
+                Ok(())
+            })
+            .create_async()
+            .await;
+
+        let mut fetcher = HTTPFetcherBuilder::new(&server.url())
+            .mode(FetchMode::Streaming)
+            .verifier(Verifier::Hmac("secret".into()))
+            .build()
+            .unwrap();
+
+        let (tx, mut rx) = mpsc::channel(4);
+        let stop_signal = Arc::new(AtomicBool::new(false));
+        let stop_notify = Arc::new(Notify::new());
+
+        let result = fetcher
+            .handle_streaming(&tx, &stop_signal, &stop_notify)
+            .await;
+        assert!(result.is_ok());
+        mock.assert();
+
+        let result = rx.recv().await.expect("valid record");
+        assert_eq!("default", result.unwrap().namespace.key);
+        let result = rx.recv().await.expect("valid record");
+        assert_eq!(
+            result.unwrap_err(),
+            Error::InvalidSignature("payload is not signed".into())
+        );
+    }
+
+    fn fast_reconnect(fallback_to_polling: bool) -> StreamingOpts {
+        StreamingOpts {
+            reconnect_initial_backoff_ms: Some(10),
+            reconnect_max_backoff_ms: Some(20),
+            fallback_to_polling: Some(fallback_to_polling),
+            idle_timeout_ms: None,
+        }
+    }
+
+    async fn next_result(rx: &mut mpsc::Receiver<FetchResult>) -> FetchResult {
+        tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("fetch result")
+            .expect("valid record")
+    }
+
+    #[tokio::test]
+    async fn test_http_fetch_stream_reconnects() {
+        let mut server = Server::new_async().await;
+        // every connection delivers a single snapshot before the server closes it
+        let mock = server
+            .mock(
+                "GET",
+                "/client/v2/environments/default/namespaces/default/stream",
+            )
+            .with_status(200)
+            .with_body("{\"result\":{\"namespace\": {\"key\": \"default\"}, \"flags\":[]}}\n")
+            .expect_at_least(3)
+            .create_async()
+            .await;
+
+        let mut fetcher = HTTPFetcherBuilder::new(&server.url())
+            .mode(FetchMode::Streaming)
+            .streaming(fast_reconnect(false))
+            .build()
+            .unwrap();
+
+        let stop_signal = Arc::new(AtomicBool::new(false));
+        let stop_notify = Arc::new(Notify::new());
+        let mut rx = fetcher.start(stop_signal.clone(), stop_notify.clone());
+
+        for _ in 0..3 {
+            let result = next_result(&mut rx).await;
+            assert_eq!("default", result.unwrap().namespace.key);
+        }
+
+        stop_signal.store(true, Ordering::Relaxed);
+        stop_notify.notify_waiters();
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_http_fetch_stream_falls_back_to_polling() {
+        let mut server = Server::new_async().await;
+        let stream_mock = server
+            .mock(
+                "GET",
+                "/client/v2/environments/default/namespaces/default/stream",
+            )
+            .with_status(401)
+            .expect_at_least(2)
+            .create_async()
+            .await;
+        let poll_mock = server
+            .mock("GET", "/internal/v1/evaluation/snapshot/namespace/default")
+            .with_status(200)
+            .with_body(r#"{"namespace": {"key": "default"}, "flags":[]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let mut fetcher = HTTPFetcherBuilder::new(&server.url())
+            .mode(FetchMode::Streaming)
+            .streaming(fast_reconnect(true))
+            .build()
+            .unwrap();
+
+        let stop_signal = Arc::new(AtomicBool::new(false));
+        let stop_notify = Arc::new(Notify::new());
+        let mut rx = fetcher.start(stop_signal.clone(), stop_notify.clone());
+
+        assert!(next_result(&mut rx).await.is_err());
+        assert_eq!("default", next_result(&mut rx).await.unwrap().namespace.key);
+        // polled at most once per update interval while the stream keeps failing
+        assert!(next_result(&mut rx).await.is_err());
+        assert!(next_result(&mut rx).await.is_err());
+
+        stop_signal.store(true, Ordering::Relaxed);
+        stop_notify.notify_waiters();
+        stream_mock.assert_async().await;
+        poll_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_http_fetch_stream_idle_timeout() {
+        let mut server = Server::new_async().await;
+        let _mock = server
+            .mock(
+                "GET",
+                "/client/v2/environments/default/namespaces/default/stream",
+            )
+            .with_status(200)
+            .with_chunked_body(|w| {
+                w.write_all(
+                    b"{\"result\":{\"namespace\": {\"key\": \"default\"}, \"flags\":[]}}\n",
+                )?;
+                // heartbeats keep the stream alive
+                for _ in 0..3 {
+                    std::thread::sleep(Duration::from_millis(100));
+                    w.write_all(b"\n")?;
+                }
+                // then it goes quiet
+                std::thread::sleep(Duration::from_secs(2));
+                Ok(())
+            })
+            .create_async()
+            .await;
+
+        let mut fetcher = HTTPFetcherBuilder::new(&server.url())
+            .mode(FetchMode::Streaming)
+            .streaming(StreamingOpts {
+                idle_timeout_ms: Some(250),
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+
+        let (tx, mut rx) = mpsc::channel(4);
+        let stop_signal = Arc::new(AtomicBool::new(false));
+        let stop_notify = Arc::new(Notify::new());
+
+        let started = std::time::Instant::now();
+        let result = fetcher
+            .handle_streaming(&tx, &stop_signal, &stop_notify)
+            .await;
+        assert!(result.is_ok());
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // only the snapshot was delivered, not the heartbeats
+        assert_eq!("default", rx.recv().await.unwrap().unwrap().namespace.key);
+        assert!(rx.is_empty());
+
+        let status = fetcher.status().status();
+        assert_eq!(status.stream_stalls, 1);
+        assert!(!status.stream_connected);
+        assert_eq!(
+            status.last_error,
+            Some("server error: stream received no data for 250ms".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_fetch_retry_policy() {
+        let mut server = Server::new_async().await;
+
+        for max_retries in [0, 2] {
+            let mock = server
+                .mock("GET", "/internal/v1/evaluation/snapshot/namespace/default")
+                .with_status(503)
+                .expect(max_retries as usize + 1)
+                .create_async()
+                .await;
+
+            let retry: RetryOpts = serde_json::from_value(serde_json::json!({
+                "max_retries": max_retries,
+                "min_backoff_ms": 1,
+                "max_backoff_ms": 5,
+                "jitter": "none"
+            }))
+            .unwrap();
+            let mut fetcher = HTTPFetcherBuilder::new(&server.url())
+                .retry(retry)
+                .connection(ConnectionOpts {
+                    connect_timeout_ms: Some(500),
+                    ..Default::default()
+                })
+                .build()
+                .unwrap();
+
+            assert!(fetcher.fetch().await.is_err());
+            mock.assert_async().await;
+            mock.remove_async().await;
+        }
+    }
+
+    #[test]
+    fn test_next_poll_delay() {
+        let mut fetcher = HTTPFetcherBuilder::new("http://localhost:8080")
+            .update_interval(Duration::from_secs(10))
+            .build()
+            .unwrap();
+
+        let delay = fetcher.next_poll_delay();
+        assert!(delay >= Duration::from_secs(9) && delay <= Duration::from_secs(11));
+
+        // backs off while fetches fail
+        fetcher.poll_failing = true;
+        let delay = fetcher.next_poll_delay();
+        assert!(delay >= Duration::from_secs(10) && delay <= Duration::from_secs(20));
+        let delay = fetcher.next_poll_delay();
+        assert!(delay >= Duration::from_secs(20) && delay <= Duration::from_secs(40));
+
+        // and waits at least as long as the server asked for
+        fetcher.retry_after = Some(Duration::from_secs(300));
+        assert!(fetcher.next_poll_delay() >= Duration::from_secs(300));
+        assert!(fetcher.retry_after.is_none());
+
+        fetcher.poll_failing = false;
+        let delay = fetcher.next_poll_delay();
+        assert!(delay >= Duration::from_secs(9) && delay <= Duration::from_secs(11));
+    }
+
+    #[tokio::test]
+    async fn test_http_fetch_retry_after() {
+        let mut server = Server::new_async().await;
+        let in_a_minute = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(60));
+
+        for (status, retry_after, expected, attempts) in [
+            (429, "120", Some(Duration::from_secs(120)), 1),
+            (503, in_a_minute.as_str(), Some(Duration::from_secs(60)), 1),
+            (429, "99999999", Some(MAX_RETRY_AFTER), 1),
+            (500, "120", None, 2),
+        ] {
+            let mock = server
+                .mock("GET", "/internal/v1/evaluation/snapshot/namespace/default")
+                .with_status(status)
+                .with_header("retry-after", retry_after)
+                .expect(attempts)
+                .create_async()
+                .await;
+
+            // responses asking for a delay are not retried by the client
+            let mut fetcher = HTTPFetcherBuilder::new(&server.url())
+                .retry(RetryOpts {
+                    max_retries: Some(1),
+                    min_backoff_ms: Some(1),
+                    max_backoff_ms: Some(5),
+                    ..Default::default()
+                })
+                .build()
+                .unwrap();
+
+            let (tx, mut rx) = mpsc::channel(1);
+            fetcher.handle_polling(&tx).await.unwrap();
+            assert!(rx.recv().await.unwrap().is_err());
+            assert!(fetcher.poll_failing);
+
+            match expected {
+                // the HTTP date has a resolution of one second
+                Some(expected) => {
+                    let retry_after = fetcher.retry_after.unwrap();
+                    assert!(retry_after <= expected);
+                    assert!(retry_after >= expected - Duration::from_secs(2));
+                }
+                None => assert_eq!(fetcher.retry_after, None),
+            }
+
+            mock.assert_async().await;
+            mock.remove_async().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_fetch() {
+        let path =
+            std::env::temp_dir().join(format!("flipt-file-fetch-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"namespace":{"key":"default"},"flags":[]}"#).unwrap();
+
+        let mut fetcher = HTTPFetcherBuilder::new("http://localhost:1")
+            .mode(FetchMode::File(path.clone()))
+            .build()
+            .unwrap();
+
+        let document = fetcher.initial_fetch().await.unwrap().unwrap();
+        assert_eq!("default", document.namespace.key);
+
+        // nothing is sent while the file is unchanged
+        let (tx, mut rx) = mpsc::channel(1);
+        fetcher.handle_file(&tx).await.unwrap();
+        assert!(rx.try_recv().is_err());
+
+        std::fs::write(
+            &path,
+            r#"{"namespace":{"key":"default"},"flags":[{"key":"flag1","name":"flag1","enabled":true,"type":"BOOLEAN_FLAG_TYPE"}]}"#,
+        )
+        .unwrap();
+        fetcher.handle_file(&tx).await.unwrap();
+        assert_eq!(1, rx.recv().await.unwrap().unwrap().flags.len());
+
+        std::fs::write(&path, "{\"namespace\":").unwrap();
+        fetcher.handle_file(&tx).await.unwrap();
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            Err(Error::InvalidJSON(_))
+        ));
+        assert_eq!(fetcher.status().status().consecutive_failures, 1);
+
+        // a missing file is reported once
+        std::fs::remove_file(&path).unwrap();
+        fetcher.handle_file(&tx).await.unwrap();
+        assert!(rx.recv().await.unwrap().is_err());
+        fetcher.handle_file(&tx).await.unwrap();
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_fetch_missing_at_start() {
+        let path = std::env::temp_dir().join(format!(
+            "flipt-file-fetch-missing-{}.json",
+            std::process::id()
+        ));
+
+        let mut fetcher = HTTPFetcherBuilder::new("http://localhost:1")
+            .mode(FetchMode::File(path))
+            .build()
+            .unwrap();
+
+        // the failed initial fetch is the one report of the missing file
+        assert!(fetcher.initial_fetch().await.is_err());
+        let (tx, mut rx) = mpsc::channel(1);
+        fetcher.handle_file(&tx).await.unwrap();
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_fetch_signed() {
+        let path = std::env::temp_dir().join(format!(
+            "flipt-file-fetch-signed-{}.json",
+            std::process::id()
+        ));
+        let signature_path = super::signature_path(&path);
+        let body = r#"{"namespace":{"key":"default"},"flags":[]}"#;
+        std::fs::write(&path, body).unwrap();
+        std::fs::write(&signature_path, "stale").unwrap();
+
+        let mut fetcher = HTTPFetcherBuilder::new("http://localhost:1")
+            .mode(FetchMode::File(path.clone()))
+            .verifier(Verifier::Hmac("secret".into()))
+            .build()
+            .unwrap();
+
+        assert!(matches!(
+            fetcher.initial_fetch().await,
+            Err(Error::InvalidSignature(_))
+        ));
+
+        // a snapshot that failed verification is checked again
+        let (tx, mut rx) = mpsc::channel(1);
+        fetcher.handle_file(&tx).await.unwrap();
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            Err(Error::InvalidSignature(_))
+        ));
+
+        // and a new signature alone is enough to reload it
+        std::fs::write(&signature_path, sign(body)).unwrap();
+        fetcher.handle_file(&tx).await.unwrap();
+        assert_eq!("default", rx.recv().await.unwrap().unwrap().namespace.key);
+
+        fetcher.handle_file(&tx).await.unwrap();
+        assert!(rx.try_recv().is_err());
+
+        // as is a change to the signature file alone
+        std::fs::write(&signature_path, "tampered").unwrap();
+        fetcher.handle_file(&tx).await.unwrap();
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            Err(Error::InvalidSignature(_))
+        ));
+
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(signature_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_fetch_declarative() {
+        let path =
+            std::env::temp_dir().join(format!("flipt-file-fetch-{}.yml", std::process::id()));
+        std::fs::write(
+            &path,
+            "namespace: team\nflags:\n  - key: flag1\n    enabled: true\n    rollouts:\n      - segment:\n          key: everyone\n          value: true\nsegments:\n  - key: everyone\n",
+        )
+        .unwrap();
+
+        let mut fetcher = HTTPFetcherBuilder::new("http://localhost:1")
+            .namespace("team")
+            .mode(FetchMode::File(path.clone()))
+            .build()
+            .unwrap();
+
+        let document = fetcher.initial_fetch().await.unwrap().unwrap();
+        assert_eq!("team", document.namespace.key);
+        let rollouts = document.flags[0].rollouts.as_ref().unwrap();
+        assert_eq!(
+            "everyone",
+            rollouts[0].segment.as_ref().unwrap().segments[0].key
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_deserialize_file_mode() {
+        let mode: FetchMode = serde_json::from_str(r#"{"file":"/tmp/snapshot.json"}"#).unwrap();
+        assert_eq!(mode, FetchMode::File("/tmp/snapshot.json".into()));
+    }
+
     #[test]
     fn test_deserialize_no_auth() {
         let json = r#""#;
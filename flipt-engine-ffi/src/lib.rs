@@ -1,21 +1,42 @@
+pub mod backoff;
+pub mod cache;
 pub mod evaluator;
+pub mod events;
+pub mod exposure;
 pub mod http;
+pub mod snapshot_cache;
+pub mod status;
 pub mod tls;
 use crate::tls::TlsConfig;
 use base64::prelude::BASE64_STANDARD;
 use base64::Engine as Base64Engine;
+use cache::{CacheOpts, CacheStats};
 use evaluator::Evaluator;
+use events::{EngineEvent, Notifier, SnapshotCallback, SnapshotChange};
+use exposure::{ExposureOpts, ExposureRecorder};
+use fliptevaluation::codec::{self, SnapshotFormat};
+use fliptevaluation::declarative;
 use fliptevaluation::error::Error;
+use fliptevaluation::hook::{self, Hooks};
 use fliptevaluation::models::{flipt, snapshot};
+use fliptevaluation::signature::Verifier;
+use fliptevaluation::simulation::{SimulationRequest, SimulationResponse};
+use fliptevaluation::synthesis::{SynthesisRequest, SynthesisResponse};
 use fliptevaluation::{
-    BatchEvaluationResponse, BooleanEvaluationResponse, EvaluationRequest,
-    VariantEvaluationResponse,
+    BatchEvaluationResponse, BooleanEvaluationResponse, BucketResolution, ContextList,
+    EvaluationRequest, VariantEvaluationResponse,
+};
+use http::{
+    Authentication, ConnectionOpts, ErrorStrategy, FetchMode, HTTPFetcher, HTTPFetcherBuilder,
+    RetryOpts, StreamingOpts,
 };
-use http::{Authentication, ErrorStrategy, FetchMode, HTTPFetcher, HTTPFetcherBuilder};
 use libc::c_void;
 use reqwest::header::HeaderMap;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
+use snapshot_cache::{SnapshotCache, SnapshotCacheOpts};
+use status::{EngineStatus, StatusTracker};
 use std::collections::HashMap;
 use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
@@ -35,6 +56,38 @@ struct FFIEvaluationRequest {
     context: Option<Map<String, Value>>,
 }
 
+impl TryFrom<FFIEvaluationRequest> for EvaluationRequest {
+    type Error = Error;
+
+    fn try_from(req: FFIEvaluationRequest) -> Result<Self, Error> {
+        let mut context = HashMap::new();
+        let mut list_context = HashMap::new();
+        for (key, value) in req.context.unwrap_or_default() {
+            match value {
+                Value::String(val) => {
+                    context.insert(key, val);
+                }
+                Value::Array(_) => {
+                    let list = serde_json::from_value::<ContextList>(value).map_err(|_| {
+                        Error::InvalidRequest(format!(
+                            "context property {key} must be a list of strings or numbers"
+                        ))
+                    })?;
+                    list_context.insert(key, list);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(EvaluationRequest {
+            flag_key: req.flag_key,
+            entity_id: req.entity_id,
+            context,
+            list_context,
+        })
+    }
+}
+
 #[derive(Serialize)]
 struct FFIResponse<T>
 where
@@ -97,10 +150,21 @@ pub struct EngineOpts {
     request_timeout: Option<u64>,
     update_interval: Option<u64>,
     fetch_mode: Option<FetchMode>,
+    streaming: Option<StreamingOpts>,
+    retry: Option<RetryOpts>,
+    connection: Option<ConnectionOpts>,
     reference: Option<String>,
     error_strategy: Option<ErrorStrategy>,
     snapshot: Option<String>,
+    /// Flipt declarative `features.yml` seeding the engine when no encoded `snapshot` is given.
+    snapshot_yaml: Option<String>,
+    snapshot_signature: Option<String>,
+    verification: Option<Verifier>,
     tls_config: Option<TlsConfig>,
+    snapshot_cache: Option<SnapshotCacheOpts>,
+    exposures: Option<ExposureOpts>,
+    cache: Option<CacheOpts>,
+    bucket_resolution: Option<BucketResolution>,
 }
 
 impl Default for EngineOpts {
@@ -114,19 +178,37 @@ impl Default for EngineOpts {
             update_interval: Some(120),
             reference: None,
             fetch_mode: Some(FetchMode::default()),
+            streaming: None,
+            retry: None,
+            connection: None,
             error_strategy: Some(ErrorStrategy::Fail),
             snapshot: None,
+            snapshot_yaml: None,
+            snapshot_signature: None,
+            verification: None,
             tls_config: None,
+            snapshot_cache: None,
+            exposures: None,
+            cache: None,
+            bucket_resolution: None,
         }
     }
 }
 
 pub struct Engine {
     evaluator: Arc<RwLock<Evaluator<snapshot::Snapshot>>>,
+    // Copied out of the evaluator so hooks run without its lock held and may call into the engine.
+    hooks: Hooks,
+    namespace: String,
     stop_signal: Arc<AtomicBool>,
     fetcher_handle: Option<tokio::task::JoinHandle<()>>,
     stop_notify: Arc<Notify>,
     auth_sender: Option<watch::Sender<HeaderMap>>,
+    exposures: Option<Arc<ExposureRecorder>>,
+    exposure_handle: Option<tokio::task::JoinHandle<()>>,
+    notifier: Notifier,
+    status: StatusTracker,
+    error_strategy: ErrorStrategy,
 }
 
 static RUNTIME: OnceLock<Runtime> = OnceLock::new();
@@ -157,12 +239,44 @@ fn init_logging() {
     });
 }
 
+/// Write the snapshot to the cache on the blocking pool, as the write is synced to disk.
+async fn store_snapshot(
+    cache: &SnapshotCache,
+    snapshot: &snapshot::Snapshot,
+    status: &StatusTracker,
+) {
+    let cache = cache.clone();
+    let snapshot = snapshot.clone();
+    let etag = status.status().etag;
+    match tokio::task::spawn_blocking(move || cache.store(&snapshot, etag)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => log::warn!("{e}"),
+        Err(e) => log::warn!("failed to write snapshot cache: {e}"),
+    }
+}
+
+/// Snapshot served until the first fetch completes.
+pub enum InitialSnapshot {
+    /// A snapshot passed by the host, or an empty one. With [`ErrorStrategy::Fail`] a failed
+    /// first fetch is reported instead.
+    Seed(snapshot::Snapshot),
+    /// A snapshot loaded from the on-disk cache, still served when fetching fails until the server
+    /// has been reached.
+    Cached(snapshot::Snapshot),
+}
+
+impl From<snapshot::Snapshot> for InitialSnapshot {
+    fn from(snapshot: snapshot::Snapshot) -> Self {
+        InitialSnapshot::Seed(snapshot)
+    }
+}
+
 impl Engine {
     pub fn new(
         mut fetcher: HTTPFetcher,
         evaluator: Evaluator<snapshot::Snapshot>,
         error_strategy: ErrorStrategy,
-        initial_snapshot: snapshot::Snapshot,
+        initial_snapshot: impl Into<InitialSnapshot>,
     ) -> Self {
         let auth_sender = fetcher.take_auth_sender();
         let stop_signal = Arc::new(AtomicBool::new(false));
@@ -171,12 +285,26 @@ impl Engine {
         let stop_notify = Arc::new(Notify::new());
         let stop_notify_clone = stop_notify.clone();
 
+        let hooks = evaluator.hooks().clone();
+        let namespace = evaluator.namespace().to_string();
         let evaluator = Arc::new(RwLock::new(evaluator));
         let evaluator_clone = evaluator.clone();
 
+        let notifier = Notifier::default();
+        let notifier_clone = notifier.clone();
+        fetcher.set_notifier(notifier.clone());
+        let status = fetcher.status();
+        let status_clone = status.clone();
+        let snapshot_cache = fetcher.snapshot_cache();
+
         let handle = get_or_create_runtime();
 
         // Set the initial snapshot
+        // A cached snapshot is served rather than failed fetches until the server is reached
+        let (initial_snapshot, mut serving_cache) = match initial_snapshot.into() {
+            InitialSnapshot::Seed(snapshot) => (snapshot, false),
+            InitialSnapshot::Cached(snapshot) => (snapshot, true),
+        };
         if let Ok(mut lock) = evaluator.write() {
             lock.replace_snapshot(Ok(initial_snapshot));
         }
@@ -184,16 +312,29 @@ impl Engine {
         // Block on initial fetch
         handle.block_on(async {
             match fetcher.initial_fetch().await {
-                Ok(doc) => {
+                Ok(Some(doc)) => {
                     log::debug!("initial fetch succeeded");
+                    serving_cache = false;
                     let snap = snapshot::Snapshot::build(doc);
+                    if let Some(cache) = &snapshot_cache {
+                        store_snapshot(cache, &snap, &status).await;
+                    }
                     if let Ok(mut lock) = evaluator.write() {
                         lock.replace_snapshot(Ok(snap));
                     }
                 }
+                Ok(None) => {
+                    log::debug!("initial snapshot is up to date");
+                    serving_cache = false;
+                }
                 Err(err) => {
-                    log::warn!("initial fetch failed: {err:?}");
-                    if error_strategy == ErrorStrategy::Fail {
+                    match &err {
+                        Error::InvalidSignature(_) => {
+                            log::error!("rejected fetched snapshot: {err}")
+                        }
+                        _ => log::warn!("initial fetch failed: {err:?}"),
+                    }
+                    if error_strategy == ErrorStrategy::Fail && !serving_cache {
                         if let Ok(mut lock) = evaluator.write() {
                             lock.replace_snapshot(Err(err));
                         }
@@ -203,20 +344,48 @@ impl Engine {
         });
 
         // Spawn the continuous polling task and store the JoinHandle
+        let error_strategy_clone = error_strategy.clone();
         let fetcher_handle = handle.spawn(async move {
             let mut rx = fetcher.start(stop_signal_clone, stop_notify_clone);
             while let Some(res) = rx.recv().await {
                 match res {
                     Ok(doc) => {
                         log::debug!("fetch succeeded");
+                        serving_cache = false;
                         let snap = snapshot::Snapshot::build(doc);
-                        if let Ok(mut lock) = evaluator_clone.write() {
-                            lock.replace_snapshot(Ok(snap));
+                        if let Some(cache) = &snapshot_cache {
+                            store_snapshot(cache, &snap, &status_clone).await;
                         }
+                        let previous = match evaluator_clone.write() {
+                            Ok(mut lock) => lock.replace_snapshot(Ok(snap)),
+                            Err(_) => None,
+                        };
+                        // Diff under a read lock so evaluations are not held up, and notify after
+                        // releasing it so callbacks can evaluate flags. Refetching an unchanged
+                        // snapshot is not reported.
+                        let change = previous.and_then(|previous| {
+                            let lock = evaluator_clone.read().ok()?;
+                            let current = lock.current_snapshot();
+                            (current.digest() != previous.digest())
+                                .then(|| SnapshotChange::between(&previous, current))
+                        });
+                        if let Some(change) = change {
+                            notifier_clone.notify(EngineEvent::SnapshotApplied(change));
+                        }
+                    }
+                    // Keep serving the current snapshot rather than one that may have been tampered with
+                    Err(err @ Error::InvalidSignature(_)) => {
+                        log::error!("rejected fetched snapshot: {err}");
+                        notifier_clone.notify(EngineEvent::FetchFailed {
+                            error: err.to_string(),
+                        });
                     }
                     Err(err) => {
                         log::warn!("fetch failed: {err:?}");
-                        if error_strategy == ErrorStrategy::Fail {
+                        notifier_clone.notify(EngineEvent::FetchFailed {
+                            error: err.to_string(),
+                        });
+                        if error_strategy_clone == ErrorStrategy::Fail && !serving_cache {
                             if let Ok(mut lock) = evaluator_clone.write() {
                                 lock.replace_snapshot(Err(err));
                             }
@@ -228,13 +397,33 @@ impl Engine {
 
         Self {
             evaluator,
+            hooks,
+            namespace,
             stop_signal,
             fetcher_handle: Some(fetcher_handle),
             stop_notify,
             auth_sender,
+            exposures: None,
+            exposure_handle: None,
+            notifier,
+            status,
+            error_strategy,
         }
     }
 
+    /// Start flushing exposures recorded by the given recorder. The recorder must also be
+    /// registered as a hook on the evaluator for exposures to be recorded.
+    pub fn with_exposures(mut self, exposures: Arc<ExposureRecorder>) -> Self {
+        let handle = exposures.clone().start(
+            get_or_create_runtime(),
+            self.stop_signal.clone(),
+            self.stop_notify.clone(),
+        );
+        self.exposures = Some(exposures);
+        self.exposure_handle = Some(handle);
+        self
+    }
+
     /// Helper to lock the evaluator for reading and run a closure, mapping lock errors to Error::Internal
     fn with_evaluator_read_lock<F, R>(&self, f: F) -> Result<R, Error>
     where
@@ -251,21 +440,38 @@ impl Engine {
         &self,
         evaluation_request: &EvaluationRequest,
     ) -> Result<VariantEvaluationResponse, Error> {
-        self.with_evaluator_read_lock(|lock| lock.variant(evaluation_request))
+        hook::evaluate(&self.hooks, &self.namespace, evaluation_request, || {
+            self.with_evaluator_read_lock(|lock| lock.evaluate_variant(evaluation_request))
+        })
     }
 
     pub fn boolean(
         &self,
         evaluation_request: &EvaluationRequest,
     ) -> Result<BooleanEvaluationResponse, Error> {
-        self.with_evaluator_read_lock(|lock| lock.boolean(evaluation_request))
+        hook::evaluate(&self.hooks, &self.namespace, evaluation_request, || {
+            self.with_evaluator_read_lock(|lock| lock.evaluate_boolean(evaluation_request))
+        })
     }
 
     pub fn batch(
         &self,
         batch_evaluation_request: Vec<EvaluationRequest>,
     ) -> Result<BatchEvaluationResponse, Error> {
-        self.with_evaluator_read_lock(|lock| lock.batch(batch_evaluation_request))
+        hook::evaluate_batch(
+            &self.hooks,
+            &self.namespace,
+            &batch_evaluation_request,
+            |requests| self.with_evaluator_read_lock(|lock| lock.evaluate_batch(requests)),
+        )
+    }
+
+    pub fn simulate(&self, request: &SimulationRequest) -> Result<SimulationResponse, Error> {
+        self.with_evaluator_read_lock(|lock| lock.simulate(request))
+    }
+
+    pub fn synthesize(&self, request: &SynthesisRequest) -> Result<SynthesisResponse, Error> {
+        self.with_evaluator_read_lock(|lock| lock.synthesize(request))
     }
 
     pub fn list_flags(&self) -> Result<Vec<flipt::Flag>, Error> {
@@ -275,6 +481,31 @@ impl Engine {
     pub fn get_snapshot(&self) -> Result<snapshot::Snapshot, Error> {
         self.with_evaluator_read_lock(|lock| lock.get_snapshot())
     }
+
+    pub fn get_snapshot_digest(&self) -> Result<String, Error> {
+        self.with_evaluator_read_lock(|lock| lock.get_snapshot_digest())
+    }
+
+    pub fn get_status(&self) -> Result<EngineStatus, Error> {
+        let mut status = self.status.status();
+        status.serving_fallback =
+            self.error_strategy == ErrorStrategy::Fallback && status.consecutive_failures > 0;
+
+        self.with_evaluator_read_lock(|lock| {
+            if let Ok(digest) = lock.get_snapshot_digest() {
+                status.snapshot_digest = Some(digest);
+                status.flag_count = lock.current_snapshot().namespace().flags.len();
+            }
+            Ok(status)
+        })
+    }
+
+    pub fn cache_stats(&self) -> Result<CacheStats, Error> {
+        self.with_evaluator_read_lock(|lock| {
+            lock.cache_stats()
+                .ok_or_else(|| Error::InvalidRequest("evaluation cache is not enabled".to_string()))
+        })
+    }
 }
 
 // Public FFI functions
@@ -374,6 +605,151 @@ pub unsafe extern "C" fn get_snapshot(engine_ptr: *mut c_void) -> *const c_char
     }
 }
 
+/// # Safety
+///
+/// This function will take in a pointer to the engine and a JSON snapshot format and return the
+/// current snapshot base64-encoded in that format.
+#[no_mangle]
+#[cfg(all(target_feature = "crt-static", target_os = "linux"))]
+pub unsafe extern "C" fn get_snapshot_with_format_ffi(
+    engine_ptr: *mut c_void,
+    format: *const c_char,
+) -> *const c_char {
+    match std::panic::catch_unwind(|| {
+        log::trace!(
+            "get_snapshot_with_format_ffi called: engine ptr=0x{:x}",
+            engine_ptr as usize
+        );
+        _get_snapshot_with_format(engine_ptr, format)
+    }) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            log::error!("PANIC in get_snapshot_with_format_ffi: {e:?}");
+            result_to_json_ptr::<(), _>(Err(Error::Internal(
+                "panic in get_snapshot_with_format_ffi".to_string(),
+            )))
+        }
+    }
+}
+
+/// # Safety
+///
+/// This function will take in a pointer to the engine and a JSON snapshot format and return the
+/// current snapshot base64-encoded in that format.
+#[no_mangle]
+#[cfg(not(all(target_feature = "crt-static", target_os = "linux")))]
+pub unsafe extern "C" fn get_snapshot_with_format(
+    engine_ptr: *mut c_void,
+    format: *const c_char,
+) -> *const c_char {
+    match std::panic::catch_unwind(|| {
+        log::trace!(
+            "get_snapshot_with_format called: engine ptr=0x{:x}",
+            engine_ptr as usize
+        );
+        _get_snapshot_with_format(engine_ptr, format)
+    }) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            log::error!("PANIC in get_snapshot_with_format: {e:?}");
+            result_to_json_ptr::<(), _>(Err(Error::Internal(
+                "panic in get_snapshot_with_format".to_string(),
+            )))
+        }
+    }
+}
+
+/// # Safety
+///
+/// This function will take in a pointer to the engine and return the content digest of the
+/// current snapshot.
+#[no_mangle]
+#[cfg(all(target_feature = "crt-static", target_os = "linux"))]
+pub unsafe extern "C" fn get_snapshot_digest_ffi(engine_ptr: *mut c_void) -> *const c_char {
+    match std::panic::catch_unwind(|| {
+        log::trace!(
+            "get_snapshot_digest_ffi called: engine ptr=0x{:x}",
+            engine_ptr as usize
+        );
+        _get_snapshot_digest(engine_ptr)
+    }) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            log::error!("PANIC in get_snapshot_digest_ffi: {e:?}");
+            result_to_json_ptr::<(), _>(Err(Error::Internal(
+                "panic in get_snapshot_digest_ffi".to_string(),
+            )))
+        }
+    }
+}
+
+/// # Safety
+///
+/// This function will take in a pointer to the engine and return the content digest of the
+/// current snapshot.
+#[no_mangle]
+#[cfg(not(all(target_feature = "crt-static", target_os = "linux")))]
+pub unsafe extern "C" fn get_snapshot_digest(engine_ptr: *mut c_void) -> *const c_char {
+    match std::panic::catch_unwind(|| {
+        log::trace!(
+            "get_snapshot_digest called: engine ptr=0x{:x}",
+            engine_ptr as usize
+        );
+        _get_snapshot_digest(engine_ptr)
+    }) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            log::error!("PANIC in get_snapshot_digest: {e:?}");
+            result_to_json_ptr::<(), _>(Err(Error::Internal(
+                "panic in get_snapshot_digest".to_string(),
+            )))
+        }
+    }
+}
+
+/// # Safety
+///
+/// This function will take in a pointer to the engine and return its fetch health: the last
+/// successful fetch, the last error, consecutive failures, the current ETag, the fetch mode, the
+/// stream connection and the snapshot being served.
+#[no_mangle]
+#[cfg(all(target_feature = "crt-static", target_os = "linux"))]
+pub unsafe extern "C" fn get_status_ffi(engine_ptr: *mut c_void) -> *const c_char {
+    match std::panic::catch_unwind(|| {
+        log::trace!(
+            "get_status_ffi called: engine ptr=0x{:x}",
+            engine_ptr as usize
+        );
+        _get_status(engine_ptr)
+    }) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            log::error!("PANIC in get_status_ffi: {e:?}");
+            result_to_json_ptr::<(), _>(Err(Error::Internal("panic in get_status_ffi".to_string())))
+        }
+    }
+}
+
+/// # Safety
+///
+/// This function will take in a pointer to the engine and return its fetch health: the last
+/// successful fetch, the last error, consecutive failures, the current ETag, the fetch mode, the
+/// stream connection and the snapshot being served.
+#[no_mangle]
+#[cfg(not(all(target_feature = "crt-static", target_os = "linux")))]
+pub unsafe extern "C" fn get_status(engine_ptr: *mut c_void) -> *const c_char {
+    match std::panic::catch_unwind(|| {
+        log::trace!("get_status called: engine ptr=0x{:x}", engine_ptr as usize);
+        _get_status(engine_ptr)
+    }) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            log::error!("PANIC in get_status: {e:?}");
+            result_to_json_ptr::<(), _>(Err(Error::Internal("panic in get_status".to_string())))
+        }
+    }
+}
+
 /// # Safety
 ///
 /// This function will take in a pointer to the engine and return a variant evaluation response.
@@ -534,6 +910,112 @@ pub unsafe extern "C" fn evaluate_batch(
     }
 }
 
+/// # Safety
+///
+/// This function will take in a pointer to the engine and return the distribution of the simulated entities.
+/// Simulations of more than 100,000 entities are rejected.
+#[no_mangle]
+#[cfg(all(target_feature = "crt-static", target_os = "linux"))]
+pub unsafe extern "C" fn simulate_rollout_ffi(
+    engine_ptr: *mut c_void,
+    simulation_request: *const c_char,
+) -> *const c_char {
+    match std::panic::catch_unwind(|| {
+        log::trace!(
+            "simulate_rollout_ffi called: engine ptr=0x{:x}",
+            engine_ptr as usize
+        );
+        _simulate_rollout(engine_ptr, simulation_request)
+    }) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            log::error!("PANIC in simulate_rollout_ffi: {e:?}");
+            result_to_json_ptr::<(), _>(Err(Error::Internal(
+                "panic in simulate_rollout_ffi".to_string(),
+            )))
+        }
+    }
+}
+
+/// # Safety
+///
+/// This function will take in a pointer to the engine and return the distribution of the simulated entities.
+/// Simulations of more than 100,000 entities are rejected.
+#[no_mangle]
+#[cfg(not(all(target_feature = "crt-static", target_os = "linux")))]
+pub unsafe extern "C" fn simulate_rollout(
+    engine_ptr: *mut c_void,
+    simulation_request: *const c_char,
+) -> *const c_char {
+    match std::panic::catch_unwind(|| {
+        log::trace!(
+            "simulate_rollout called: engine ptr=0x{:x}",
+            engine_ptr as usize
+        );
+        _simulate_rollout(engine_ptr, simulation_request)
+    }) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            log::error!("PANIC in simulate_rollout: {e:?}");
+            result_to_json_ptr::<(), _>(Err(Error::Internal(
+                "panic in simulate_rollout".to_string(),
+            )))
+        }
+    }
+}
+
+/// # Safety
+///
+/// This function will take in a pointer to the engine and return a context that reaches the requested target.
+#[no_mangle]
+#[cfg(all(target_feature = "crt-static", target_os = "linux"))]
+pub unsafe extern "C" fn synthesize_context_ffi(
+    engine_ptr: *mut c_void,
+    synthesis_request: *const c_char,
+) -> *const c_char {
+    match std::panic::catch_unwind(|| {
+        log::trace!(
+            "synthesize_context_ffi called: engine ptr=0x{:x}",
+            engine_ptr as usize
+        );
+        _synthesize_context(engine_ptr, synthesis_request)
+    }) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            log::error!("PANIC in synthesize_context_ffi: {e:?}");
+            result_to_json_ptr::<(), _>(Err(Error::Internal(
+                "panic in synthesize_context_ffi".to_string(),
+            )))
+        }
+    }
+}
+
+/// # Safety
+///
+/// This function will take in a pointer to the engine and return a context that reaches the requested target.
+#[no_mangle]
+#[cfg(not(all(target_feature = "crt-static", target_os = "linux")))]
+pub unsafe extern "C" fn synthesize_context(
+    engine_ptr: *mut c_void,
+    synthesis_request: *const c_char,
+) -> *const c_char {
+    match std::panic::catch_unwind(|| {
+        log::trace!(
+            "synthesize_context called: engine ptr=0x{:x}",
+            engine_ptr as usize
+        );
+        _synthesize_context(engine_ptr, synthesis_request)
+    }) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            log::error!("PANIC in synthesize_context: {e:?}");
+            result_to_json_ptr::<(), _>(Err(Error::Internal(
+                "panic in synthesize_context".to_string(),
+            )))
+        }
+    }
+}
+
 /// # Safety
 ///
 /// This function will take in a pointer to the engine and return a list of flags.
@@ -573,6 +1055,52 @@ pub unsafe extern "C" fn list_flags(engine_ptr: *mut c_void) -> *const c_char {
     }
 }
 
+/// # Safety
+///
+/// This function will take in a pointer to the engine and return the evaluation cache statistics.
+#[no_mangle]
+#[cfg(all(target_feature = "crt-static", target_os = "linux"))]
+pub unsafe extern "C" fn get_cache_stats_ffi(engine_ptr: *mut c_void) -> *const c_char {
+    match std::panic::catch_unwind(|| {
+        log::trace!(
+            "get_cache_stats_ffi called: engine ptr=0x{:x}",
+            engine_ptr as usize
+        );
+        _get_cache_stats(engine_ptr)
+    }) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            log::error!("PANIC in get_cache_stats_ffi: {e:?}");
+            result_to_json_ptr::<(), _>(Err(Error::Internal(
+                "panic in get_cache_stats_ffi".to_string(),
+            )))
+        }
+    }
+}
+
+/// # Safety
+///
+/// This function will take in a pointer to the engine and return the evaluation cache statistics.
+#[no_mangle]
+#[cfg(not(all(target_feature = "crt-static", target_os = "linux")))]
+pub unsafe extern "C" fn get_cache_stats(engine_ptr: *mut c_void) -> *const c_char {
+    match std::panic::catch_unwind(|| {
+        log::trace!(
+            "get_cache_stats called: engine ptr=0x{:x}",
+            engine_ptr as usize
+        );
+        _get_cache_stats(engine_ptr)
+    }) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            log::error!("PANIC in get_cache_stats: {e:?}");
+            result_to_json_ptr::<(), _>(Err(Error::Internal(
+                "panic in get_cache_stats".to_string(),
+            )))
+        }
+    }
+}
+
 /// # Safety
 ///
 /// This function will take in a pointer to the engine and destroy it.
@@ -669,10 +1197,172 @@ pub unsafe extern "C" fn update_authentication(
 
 /// # Safety
 ///
-/// This function will take in a pointer to a string and destroy it.
+/// This function will register a callback that is invoked with a JSON event whenever a changed
+/// snapshot is applied, a fetch fails, the stream connects or disconnects, or authentication is
+/// updated. Events from fetching arrive on the engine's background thread; `auth_updated` is
+/// delivered on the thread calling `update_authentication`. It replaces any previously registered
+/// callback. The callback may call back into the engine, but must not destroy it.
 #[no_mangle]
 #[cfg(all(target_feature = "crt-static", target_os = "linux"))]
-pub unsafe extern "C" fn destroy_string_ffi(ptr: *mut c_char) {
+pub unsafe extern "C" fn register_snapshot_callback_ffi(
+    engine_ptr: *mut c_void,
+    callback: Option<unsafe extern "C" fn(event: *const c_char, user_data: *mut c_void)>,
+    user_data: *mut c_void,
+) -> *const c_char {
+    match std::panic::catch_unwind(|| {
+        log::trace!(
+            "register_snapshot_callback_ffi called: engine ptr=0x{:x}",
+            engine_ptr as usize
+        );
+        _register_snapshot_callback(engine_ptr, callback, user_data)
+    }) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            log::error!("PANIC in register_snapshot_callback_ffi: {e:?}");
+            result_to_json_ptr::<(), _>(Err(Error::Internal(
+                "panic in register_snapshot_callback_ffi".to_string(),
+            )))
+        }
+    }
+}
+
+/// # Safety
+///
+/// This function will register a callback that is invoked with a JSON event whenever a changed
+/// snapshot is applied, a fetch fails, the stream connects or disconnects, or authentication is
+/// updated. Events from fetching arrive on the engine's background thread; `auth_updated` is
+/// delivered on the thread calling `update_authentication`. It replaces any previously registered
+/// callback. The callback may call back into the engine, but must not destroy it.
+#[no_mangle]
+#[cfg(not(all(target_feature = "crt-static", target_os = "linux")))]
+pub unsafe extern "C" fn register_snapshot_callback(
+    engine_ptr: *mut c_void,
+    callback: Option<unsafe extern "C" fn(event: *const c_char, user_data: *mut c_void)>,
+    user_data: *mut c_void,
+) -> *const c_char {
+    match std::panic::catch_unwind(|| {
+        log::trace!(
+            "register_snapshot_callback called: engine ptr=0x{:x}",
+            engine_ptr as usize
+        );
+        _register_snapshot_callback(engine_ptr, callback, user_data)
+    }) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            log::error!("PANIC in register_snapshot_callback: {e:?}");
+            result_to_json_ptr::<(), _>(Err(Error::Internal(
+                "panic in register_snapshot_callback".to_string(),
+            )))
+        }
+    }
+}
+
+/// # Safety
+///
+/// This function will unregister the snapshot callback. Once it returns the callback is not running
+/// on any other thread and will not be invoked again, so the user data may be released when it is
+/// not called from within the callback. `destroy_engine` unregisters the callback as well.
+#[no_mangle]
+#[cfg(all(target_feature = "crt-static", target_os = "linux"))]
+pub unsafe extern "C" fn unregister_snapshot_callback_ffi(
+    engine_ptr: *mut c_void,
+) -> *const c_char {
+    match std::panic::catch_unwind(|| {
+        log::trace!(
+            "unregister_snapshot_callback_ffi called: engine ptr=0x{:x}",
+            engine_ptr as usize
+        );
+        _unregister_snapshot_callback(engine_ptr)
+    }) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            log::error!("PANIC in unregister_snapshot_callback_ffi: {e:?}");
+            result_to_json_ptr::<(), _>(Err(Error::Internal(
+                "panic in unregister_snapshot_callback_ffi".to_string(),
+            )))
+        }
+    }
+}
+
+/// # Safety
+///
+/// This function will unregister the snapshot callback. Once it returns the callback is not running
+/// on any other thread and will not be invoked again, so the user data may be released when it is
+/// not called from within the callback. `destroy_engine` unregisters the callback as well.
+#[no_mangle]
+#[cfg(not(all(target_feature = "crt-static", target_os = "linux")))]
+pub unsafe extern "C" fn unregister_snapshot_callback(engine_ptr: *mut c_void) -> *const c_char {
+    match std::panic::catch_unwind(|| {
+        log::trace!(
+            "unregister_snapshot_callback called: engine ptr=0x{:x}",
+            engine_ptr as usize
+        );
+        _unregister_snapshot_callback(engine_ptr)
+    }) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            log::error!("PANIC in unregister_snapshot_callback: {e:?}");
+            result_to_json_ptr::<(), _>(Err(Error::Internal(
+                "panic in unregister_snapshot_callback".to_string(),
+            )))
+        }
+    }
+}
+
+/// # Safety
+///
+/// This function will take all engine events queued since the last call and return them as a JSON
+/// array. If none are queued it waits up to `timeout_ms` milliseconds for one to arrive; a timeout of
+/// zero returns immediately. `destroy_engine` wakes up any waiting call.
+#[no_mangle]
+#[cfg(all(target_feature = "crt-static", target_os = "linux"))]
+pub unsafe extern "C" fn poll_events_ffi(
+    engine_ptr: *mut c_void,
+    timeout_ms: u64,
+) -> *const c_char {
+    match std::panic::catch_unwind(|| {
+        log::trace!(
+            "poll_events_ffi called: engine ptr=0x{:x}",
+            engine_ptr as usize
+        );
+        _poll_events(engine_ptr, timeout_ms)
+    }) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            log::error!("PANIC in poll_events_ffi: {e:?}");
+            result_to_json_ptr::<(), _>(Err(Error::Internal(
+                "panic in poll_events_ffi".to_string(),
+            )))
+        }
+    }
+}
+
+/// # Safety
+///
+/// This function will take all engine events queued since the last call and return them as a JSON
+/// array. If none are queued it waits up to `timeout_ms` milliseconds for one to arrive; a timeout of
+/// zero returns immediately. `destroy_engine` wakes up any waiting call.
+#[no_mangle]
+#[cfg(not(all(target_feature = "crt-static", target_os = "linux")))]
+pub unsafe extern "C" fn poll_events(engine_ptr: *mut c_void, timeout_ms: u64) -> *const c_char {
+    match std::panic::catch_unwind(|| {
+        log::trace!("poll_events called: engine ptr=0x{:x}", engine_ptr as usize);
+        _poll_events(engine_ptr, timeout_ms)
+    }) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            log::error!("PANIC in poll_events: {e:?}");
+            result_to_json_ptr::<(), _>(Err(Error::Internal("panic in poll_events".to_string())))
+        }
+    }
+}
+
+/// # Safety
+///
+/// This function will take in a pointer to a string and destroy it.
+#[no_mangle]
+#[cfg(all(target_feature = "crt-static", target_os = "linux"))]
+pub unsafe extern "C" fn destroy_string_ffi(ptr: *mut c_char) {
     match std::panic::catch_unwind(|| {
         log::trace!("destroy_string_ffi called: ptr=0x{:x}", ptr as usize);
         _destroy_string(ptr)
@@ -726,8 +1416,8 @@ unsafe extern "C" fn _initialize_engine(opts: *const c_char) -> *mut c_void {
                 .unwrap_or("http://localhost:8080"),
         );
 
-        if let Some(environment) = engine_opts.environment {
-            fetcher_builder = fetcher_builder.environment(&environment);
+        if let Some(environment) = &engine_opts.environment {
+            fetcher_builder = fetcher_builder.environment(environment);
         }
 
         let namespace = engine_opts
@@ -754,40 +1444,142 @@ unsafe extern "C" fn _initialize_engine(opts: *const c_char) -> *mut c_void {
             fetcher_builder = fetcher_builder.mode(fetch_mode);
         }
 
+        if let Some(streaming) = engine_opts.streaming {
+            fetcher_builder = fetcher_builder.streaming(streaming);
+        }
+
+        if let Some(retry) = engine_opts.retry.clone() {
+            fetcher_builder = fetcher_builder.retry(retry);
+        }
+
+        if let Some(connection) = engine_opts.connection.clone() {
+            fetcher_builder = fetcher_builder.connection(connection);
+        }
+
         if let Some(reference) = &engine_opts.reference {
             fetcher_builder = fetcher_builder.reference(reference);
         }
 
-        if let Some(tls_config) = engine_opts.tls_config {
+        if let Some(tls_config) = engine_opts.tls_config.clone() {
             fetcher_builder = fetcher_builder.tls_config(tls_config);
         }
 
+        if let Some(verifier) = engine_opts.verification.clone() {
+            fetcher_builder = fetcher_builder.verifier(verifier);
+        }
+
+        // An explicit seed takes priority over the cached snapshot
+        let verify_seed = |payload: &[u8]| match &engine_opts.verification {
+            Some(verifier) => {
+                match verifier.verify(payload, engine_opts.snapshot_signature.as_deref()) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        log::error!("rejected initial snapshot: {e}");
+                        false
+                    }
+                }
+            }
+            None => true,
+        };
+        let seed = match (&engine_opts.snapshot, &engine_opts.snapshot_yaml) {
+            (Some(snapshot_b64), _) => BASE64_STANDARD
+                .decode(snapshot_b64)
+                .ok()
+                .filter(|decoded| verify_seed(decoded))
+                .and_then(|decoded| codec::decode(&decoded).ok()),
+            (None, Some(yaml)) if verify_seed(yaml.as_bytes()) => {
+                match declarative::parse_snapshot(yaml, &namespace) {
+                    Ok(snapshot) => Some(snapshot),
+                    Err(e) => {
+                        log::error!("failed to parse initial snapshot: {e}");
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        // A cached snapshot lets the engine start without reaching the server. The cache holds
+        // no signatures, so it can't be trusted when snapshots must be verified.
+        let mut cached_snapshot = None;
+        let snapshot_cache = match (&engine_opts.snapshot_cache, &engine_opts.verification) {
+            (Some(_), Some(_)) => {
+                log::warn!("snapshot cache is disabled because snapshots are verified");
+                None
+            }
+            (snapshot_cache, _) => snapshot_cache.as_ref(),
+        };
+        if let Some(opts) = snapshot_cache {
+            let cache = SnapshotCache::new(
+                opts,
+                engine_opts.environment.as_deref().unwrap_or("default"),
+                &namespace,
+                engine_opts.reference.as_deref(),
+            );
+            // the cached ETag only describes the cached snapshot, not a seed served instead
+            if seed.is_none() {
+                cached_snapshot = cache.load();
+            }
+            if let Some(etag) = cached_snapshot.as_ref().and_then(|c| c.etag.as_deref()) {
+                fetcher_builder = fetcher_builder.etag(etag);
+            }
+            fetcher_builder = fetcher_builder.snapshot_cache(cache);
+        }
+
         let fetcher = fetcher_builder.build().unwrap_or_else(|e| {
             log::warn!("failed to build custom fetcher: {e}");
             HTTPFetcherBuilder::default().build().unwrap()
         });
 
-        let evaluator = Evaluator::new(&namespace);
-
-        // Handle initial snapshot if provided
-        let initial_snapshot = engine_opts
-            .snapshot
-            .as_ref()
-            .and_then(|snapshot_b64| {
-                BASE64_STANDARD
-                    .decode(snapshot_b64)
-                    .ok()
-                    .and_then(|decoded| serde_json::from_slice::<snapshot::Snapshot>(&decoded).ok())
-            })
-            .unwrap_or_default();
+        let mut hooks = Hooks::new();
+
+        let exposures = engine_opts.exposures.and_then(|opts| {
+            match ExposureRecorder::new(
+                opts,
+                engine_opts.tls_config.as_ref(),
+                &engine_opts.connection.unwrap_or_default(),
+                &engine_opts.retry.unwrap_or_default(),
+                fetcher.auth_receiver(),
+            ) {
+                Ok(recorder) => Some(Arc::new(recorder)),
+                Err(e) => {
+                    log::warn!("failed to build exposure recorder: {e}");
+                    None
+                }
+            }
+        });
 
-        let engine = Engine::new(
+        if let Some(exposures) = &exposures {
+            hooks.push(exposures.clone());
+        }
+
+        let mut evaluator = Evaluator::new(&namespace).with_hooks(hooks);
+
+        if let Some(cache) = engine_opts.cache {
+            evaluator = evaluator.with_cache(cache);
+        }
+
+        if let Some(resolution) = engine_opts.bucket_resolution {
+            evaluator = evaluator.with_bucket_resolution(resolution);
+        }
+
+        let initial_snapshot = match (seed, cached_snapshot) {
+            (Some(seed), _) => InitialSnapshot::Seed(seed),
+            (None, Some(cached)) => InitialSnapshot::Cached(cached.snapshot),
+            (None, None) => InitialSnapshot::Seed(snapshot::Snapshot::default()),
+        };
+
+        let mut engine = Engine::new(
             fetcher,
             evaluator,
             engine_opts.error_strategy.unwrap_or_default(),
             initial_snapshot,
         );
 
+        if let Some(exposures) = exposures {
+            engine = engine.with_exposures(exposures);
+        }
+
         // Convert to raw pointer
         Box::into_raw(Box::new(engine)) as *mut c_void
     });
@@ -796,23 +1588,52 @@ unsafe extern "C" fn _initialize_engine(opts: *const c_char) -> *mut c_void {
 }
 
 unsafe extern "C" fn _get_snapshot(engine_ptr: *mut c_void) -> *const c_char {
+    encode_snapshot(engine_ptr, &SnapshotFormat::default())
+}
+
+unsafe extern "C" fn _get_snapshot_with_format(
+    engine_ptr: *mut c_void,
+    format: *const c_char,
+) -> *const c_char {
+    match get_json_request::<SnapshotFormat>(format) {
+        Ok(format) => encode_snapshot(engine_ptr, &format),
+        Err(e) => result_to_json_ptr::<(), _>(Err(e)),
+    }
+}
+
+unsafe extern "C" fn _get_snapshot_digest(engine_ptr: *mut c_void) -> *const c_char {
+    let res = match get_engine(engine_ptr) {
+        Ok(e) => e.get_snapshot_digest(),
+        Err(e) => return result_to_json_ptr::<(), _>(Err(e)),
+    };
+
+    result_to_json_ptr(res)
+}
+
+unsafe extern "C" fn _get_status(engine_ptr: *mut c_void) -> *const c_char {
+    let res = match get_engine(engine_ptr) {
+        Ok(e) => e.get_status(),
+        Err(e) => return result_to_json_ptr::<(), _>(Err(e)),
+    };
+
+    result_to_json_ptr(res)
+}
+
+unsafe fn encode_snapshot(engine_ptr: *mut c_void, format: &SnapshotFormat) -> *const c_char {
     let e = match get_engine(engine_ptr) {
         Ok(e) => e,
         Err(e) => return result_to_json_ptr::<(), _>(Err(e)),
     };
 
-    let snapshot_result = e.get_snapshot();
-
-    let json = match &snapshot_result {
-        Ok(snapshot) => match serde_json::to_string(snapshot) {
-            Ok(s) => s,
-            Err(e) => return result_to_json_ptr::<(), _>(Err(e)),
-        },
+    let bytes = match e
+        .get_snapshot()
+        .and_then(|snapshot| codec::encode(&snapshot, format))
+    {
+        Ok(bytes) => bytes,
         Err(e) => return result_to_json_ptr::<(), _>(Err(e)),
     };
 
-    // Base64 encode the JSON string
-    let encoded = BASE64_STANDARD.encode(json);
+    let encoded = BASE64_STANDARD.encode(bytes);
 
     match CString::new(encoded) {
         Ok(cstr) => cstr.into_raw(),
@@ -841,7 +1662,7 @@ unsafe extern "C" fn _evaluate_variant(
     };
     let e_req = get_evaluation_request(evaluation_request);
 
-    result_to_json_ptr(e.variant(&e_req))
+    result_to_json_ptr(e_req.and_then(|e_req| e.variant(&e_req)))
 }
 
 unsafe extern "C" fn _evaluate_boolean(
@@ -854,7 +1675,7 @@ unsafe extern "C" fn _evaluate_boolean(
     };
     let e_req = get_evaluation_request(evaluation_request);
 
-    result_to_json_ptr(e.boolean(&e_req))
+    result_to_json_ptr(e_req.and_then(|e_req| e.boolean(&e_req)))
 }
 
 unsafe extern "C" fn _evaluate_batch(
@@ -867,7 +1688,39 @@ unsafe extern "C" fn _evaluate_batch(
     };
     let req = get_batch_evaluation_request(batch_evaluation_request);
 
-    result_to_json_ptr(e.batch(req))
+    result_to_json_ptr(req.and_then(|req| e.batch(req)))
+}
+
+unsafe extern "C" fn _simulate_rollout(
+    engine_ptr: *mut c_void,
+    simulation_request: *const c_char,
+) -> *const c_char {
+    let e = match get_engine(engine_ptr) {
+        Ok(e) => e,
+        Err(e) => return result_to_json_ptr::<(), _>(Err(e)),
+    };
+    let req: SimulationRequest = match get_json_request(simulation_request) {
+        Ok(req) => req,
+        Err(e) => return result_to_json_ptr::<(), _>(Err(e)),
+    };
+
+    result_to_json_ptr(e.simulate(&req))
+}
+
+unsafe extern "C" fn _synthesize_context(
+    engine_ptr: *mut c_void,
+    synthesis_request: *const c_char,
+) -> *const c_char {
+    let e = match get_engine(engine_ptr) {
+        Ok(e) => e,
+        Err(e) => return result_to_json_ptr::<(), _>(Err(e)),
+    };
+    let req: SynthesisRequest = match get_json_request(synthesis_request) {
+        Ok(req) => req,
+        Err(e) => return result_to_json_ptr::<(), _>(Err(e)),
+    };
+
+    result_to_json_ptr(e.synthesize(&req))
 }
 
 unsafe extern "C" fn _list_flags(engine_ptr: *mut c_void) -> *const c_char {
@@ -879,6 +1732,15 @@ unsafe extern "C" fn _list_flags(engine_ptr: *mut c_void) -> *const c_char {
     result_to_json_ptr(res)
 }
 
+unsafe extern "C" fn _get_cache_stats(engine_ptr: *mut c_void) -> *const c_char {
+    let res = match get_engine(engine_ptr) {
+        Ok(e) => e.cache_stats(),
+        Err(e) => return result_to_json_ptr::<(), _>(Err(e)),
+    };
+
+    result_to_json_ptr(res)
+}
+
 unsafe extern "C" fn _update_authentication(
     engine_ptr: *mut c_void,
     auth_json: *const c_char,
@@ -915,7 +1777,10 @@ unsafe extern "C" fn _update_authentication(
 
     match &e.auth_sender {
         Some(sender) => match sender.send(header_map) {
-            Ok(_) => result_to_json_ptr(Ok::<(), Error>(())),
+            Ok(_) => {
+                e.notifier.notify(EngineEvent::AuthUpdated);
+                result_to_json_ptr(Ok::<(), Error>(()))
+            }
             Err(_) => result_to_json_ptr::<(), Error>(Err(Error::Internal(
                 "auth channel closed".to_string(),
             ))),
@@ -926,11 +1791,54 @@ unsafe extern "C" fn _update_authentication(
     }
 }
 
+unsafe extern "C" fn _register_snapshot_callback(
+    engine_ptr: *mut c_void,
+    callback: Option<SnapshotCallback>,
+    user_data: *mut c_void,
+) -> *const c_char {
+    let Some(callback) = callback else {
+        return result_to_json_ptr::<(), _>(Err(Error::InvalidRequest(
+            "callback must not be null".to_string(),
+        )));
+    };
+
+    match get_engine(engine_ptr) {
+        Ok(e) => {
+            e.notifier.register(callback, user_data);
+            result_to_json_ptr(Ok::<(), Error>(()))
+        }
+        Err(e) => result_to_json_ptr::<(), _>(Err(e)),
+    }
+}
+
+unsafe extern "C" fn _unregister_snapshot_callback(engine_ptr: *mut c_void) -> *const c_char {
+    match get_engine(engine_ptr) {
+        Ok(e) => {
+            e.notifier.unregister();
+            result_to_json_ptr(Ok::<(), Error>(()))
+        }
+        Err(e) => result_to_json_ptr::<(), _>(Err(e)),
+    }
+}
+
+unsafe extern "C" fn _poll_events(engine_ptr: *mut c_void, timeout_ms: u64) -> *const c_char {
+    // Hold on to the queue rather than the engine while waiting, so the engine can be destroyed
+    let queue = match get_engine(engine_ptr) {
+        Ok(e) => e.notifier.queue(),
+        Err(e) => return result_to_json_ptr::<(), _>(Err(e)),
+    };
+
+    let events = queue.drain(Duration::from_millis(timeout_ms));
+    result_to_json_ptr(Ok::<Vec<EngineEvent>, Error>(events))
+}
+
 unsafe extern "C" fn _destroy_engine(engine_ptr: *mut c_void) {
     if engine_ptr.is_null() {
         return;
     }
     let engine = Box::from_raw(engine_ptr as *mut Engine);
+    // Waits for a callback in flight so none runs once the engine is gone, and wakes up pollers
+    engine.notifier.close();
     engine.stop_signal.store(true, Ordering::Relaxed);
     // Notify the fetcher task to stop
     engine.stop_notify.notify_waiters();
@@ -939,6 +1847,14 @@ unsafe extern "C" fn _destroy_engine(engine_ptr: *mut c_void) {
         let rt = get_or_create_runtime();
         let _ = rt.block_on(handle);
     }
+    // Wait for the final exposure flush
+    if let Some(exposures) = &engine.exposures {
+        exposures.wake();
+    }
+    if let Some(handle) = engine.exposure_handle {
+        let rt = get_or_create_runtime();
+        let _ = rt.block_on(handle);
+    }
 
     // Engine is dropped here
 }
@@ -959,56 +1875,31 @@ unsafe fn get_engine<'a>(engine_ptr: *mut c_void) -> Result<&'a Engine, FFIError
     }
 }
 
-unsafe fn get_evaluation_request(evaluation_request: *const c_char) -> EvaluationRequest {
-    let evaluation_request_bytes = CStr::from_ptr(evaluation_request).to_bytes();
-    let bytes_str_repr = std::str::from_utf8(evaluation_request_bytes).unwrap();
-    let client_eval_request: FFIEvaluationRequest = serde_json::from_str(bytes_str_repr).unwrap();
+unsafe fn get_evaluation_request(
+    evaluation_request: *const c_char,
+) -> Result<EvaluationRequest, Error> {
+    get_json_request::<FFIEvaluationRequest>(evaluation_request)?.try_into()
+}
 
-    let mut context_map: HashMap<String, String> = HashMap::new();
-    if let Some(context_value) = client_eval_request.context {
-        for (key, value) in context_value {
-            if let serde_json::Value::String(val) = value {
-                context_map.insert(key, val);
-            }
-        }
+unsafe fn get_json_request<T: DeserializeOwned>(request: *const c_char) -> Result<T, Error> {
+    if request.is_null() {
+        return Err(Error::InvalidRequest("request is null".to_string()));
     }
 
-    EvaluationRequest {
-        flag_key: client_eval_request.flag_key,
-        entity_id: client_eval_request.entity_id,
-        context: context_map,
-    }
+    let bytes = CStr::from_ptr(request).to_bytes();
+    let str_repr = std::str::from_utf8(bytes)
+        .map_err(|e| Error::InvalidJSON(format!("invalid UTF-8: {e}")))?;
+
+    serde_json::from_str(str_repr).map_err(|e| Error::InvalidJSON(e.to_string()))
 }
 
 unsafe fn get_batch_evaluation_request(
     batch_evaluation_request: *const c_char,
-) -> Vec<EvaluationRequest> {
-    let evaluation_request_bytes = CStr::from_ptr(batch_evaluation_request).to_bytes();
-    let bytes_str_repr = std::str::from_utf8(evaluation_request_bytes).unwrap();
-
-    let batch_eval_request: Vec<FFIEvaluationRequest> =
-        serde_json::from_str(bytes_str_repr).unwrap();
-
-    let mut evaluation_requests: Vec<EvaluationRequest> =
-        Vec::with_capacity(batch_eval_request.len());
-    for req in batch_eval_request {
-        let mut context_map: HashMap<String, String> = HashMap::new();
-        if let Some(context_value) = req.context {
-            for (key, value) in context_value {
-                if let serde_json::Value::String(val) = value {
-                    context_map.insert(key, val);
-                }
-            }
-        }
-
-        evaluation_requests.push(EvaluationRequest {
-            flag_key: req.flag_key,
-            entity_id: req.entity_id,
-            context: context_map,
-        });
-    }
-
-    evaluation_requests
+) -> Result<Vec<EvaluationRequest>, Error> {
+    get_json_request::<Vec<FFIEvaluationRequest>>(batch_evaluation_request)?
+        .into_iter()
+        .map(EvaluationRequest::try_from)
+        .collect()
 }
 
 #[cfg(test)]
@@ -1016,6 +1907,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::single_match)]
     fn test_engine_opts_with_invalid_snapshot() {
         let json = r#"{\"url\":\"http://localhost:8080\",\"snapshot\":\"eyJmb28iOiJiYXIifQ==\",\"error_strategy\":\"fallback\"}"#;
 
@@ -1132,6 +2024,70 @@ mod tests {
         assert_eq!(opts.error_strategy, Some(ErrorStrategy::Fallback));
     }
 
+    #[test]
+    fn test_get_evaluation_request_list_context() {
+        let request = CString::new(
+            r#"{"flag_key":"foo","entity_id":"1","context":{"plan":"pro","roles":["admin","viewer"],"age":42}}"#,
+        )
+        .unwrap();
+
+        let e_req = unsafe { get_evaluation_request(request.as_ptr()) }.unwrap();
+
+        assert_eq!(e_req.context.len(), 1);
+        assert_eq!(e_req.context.get("plan").unwrap(), "pro");
+        assert_eq!(
+            e_req.list_context.get("roles"),
+            Some(&ContextList::Strings(vec![
+                "admin".to_string(),
+                "viewer".to_string()
+            ]))
+        );
+
+        let request =
+            CString::new(r#"[{"flag_key":"foo","entity_id":"1","context":{"roles":["admin",1]}}]"#)
+                .unwrap();
+
+        assert!(matches!(
+            unsafe { get_batch_evaluation_request(request.as_ptr()) },
+            Err(Error::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_get_json_request() {
+        let request = CString::new(
+            r#"{"flag_key":"flag1","entities":[{"entity_id":"user1","context":{"plan":"pro"}}],"sample_size":100}"#,
+        )
+        .unwrap();
+
+        let req: SimulationRequest = unsafe { get_json_request(request.as_ptr()) }.unwrap();
+        assert_eq!(req.flag_key, "flag1");
+        assert_eq!(req.entities.len(), 1);
+        assert_eq!(req.entities[0].context.get("plan").unwrap(), "pro");
+        assert_eq!(req.sample_size, Some(100));
+        assert!(req.context.is_empty());
+
+        let invalid = CString::new("{").unwrap();
+        assert!(unsafe { get_json_request::<SimulationRequest>(invalid.as_ptr()) }.is_err());
+        assert!(unsafe { get_json_request::<SimulationRequest>(std::ptr::null()) }.is_err());
+    }
+
+    #[test]
+    fn test_get_synthesis_request() {
+        let request =
+            CString::new(r#"{"flag_key":"flag1","target":{"type":"variant","key":"variant1"}}"#)
+                .unwrap();
+
+        let req: SynthesisRequest = unsafe { get_json_request(request.as_ptr()) }.unwrap();
+        assert_eq!(req.flag_key, "flag1");
+        assert_eq!(
+            req.target,
+            fliptevaluation::synthesis::SynthesisTarget::Variant {
+                key: "variant1".into()
+            }
+        );
+    }
+
     #[test]
     fn test_engine_opts_default() {
         let opts: EngineOpts = EngineOpts::default();
@@ -1144,6 +2100,52 @@ mod tests {
         assert_eq!(opts.error_strategy, Some(ErrorStrategy::Fail));
         assert_eq!(opts.snapshot, None);
         assert_eq!(opts.tls_config, None);
+        assert_eq!(opts.exposures, None);
+        assert_eq!(opts.cache, None);
+        assert_eq!(opts.bucket_resolution, None);
+    }
+
+    #[test]
+    fn test_engine_opts_with_bucket_resolution() {
+        let json = r#"{"url":"http://localhost:8080","bucket_resolution":"basis_point"}"#;
+
+        let opts: EngineOpts = serde_json::from_str(json).unwrap();
+        assert_eq!(opts.bucket_resolution, Some(BucketResolution::BasisPoint));
+    }
+
+    #[test]
+    fn test_engine_opts_with_cache() {
+        let json = r#"{"url":"http://localhost:8080","cache":{"max_entries":500}}"#;
+
+        let opts: EngineOpts = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            opts.cache,
+            Some(CacheOpts {
+                max_entries: Some(500)
+            })
+        );
+    }
+
+    #[test]
+    fn test_engine_opts_with_exposures() {
+        let json = r#"{"url":"http://localhost:8080","exposures":{"url":"http://localhost:9090/exposures","flush_interval":5,"batch_size":50}}"#;
+
+        let opts: EngineOpts = serde_json::from_str(json).unwrap();
+        let exposures = opts.exposures.expect("exposures should be set");
+        assert_eq!(exposures.url, "http://localhost:9090/exposures");
+        assert_eq!(exposures.flush_interval, Some(5));
+        assert_eq!(exposures.batch_size, Some(50));
+        assert_eq!(exposures.max_buffer_size, None);
+        assert_eq!(exposures.dedup_window, None);
+    }
+
+    #[test]
+    fn test_engine_opts_debug_redacts_verification_secret() {
+        let json = r#"{"url":"http://localhost:8080","verification":{"hmac":"hunter2"}}"#;
+
+        let opts: EngineOpts = serde_json::from_str(json).unwrap();
+        assert_eq!(opts.verification, Some(Verifier::Hmac("hunter2".into())));
+        assert!(!format!("{:?}", opts).contains("hunter2"));
     }
 
     #[test]
@@ -1352,4 +2354,542 @@ mod tests {
             _destroy_engine(engine_ptr);
         }
     }
+
+    #[test]
+    fn test_hooks_run_without_evaluator_lock() {
+        use fliptevaluation::hook::{EvaluationHook, HookResponse};
+        use std::sync::atomic::AtomicUsize;
+
+        #[derive(Default)]
+        struct LockProbe {
+            evaluator: OnceLock<Arc<RwLock<Evaluator<snapshot::Snapshot>>>>,
+            unlocked: AtomicUsize,
+        }
+
+        impl LockProbe {
+            fn probe(&self) {
+                if self.evaluator.get().unwrap().try_write().is_ok() {
+                    self.unlocked.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        }
+
+        impl EvaluationHook for LockProbe {
+            fn before(&self, _: &str, _: &EvaluationRequest) {
+                self.probe();
+            }
+
+            fn after(&self, _: &str, _: &EvaluationRequest, _: &HookResponse<'_>) {
+                self.probe();
+            }
+
+            fn error(&self, _: &str, _: &EvaluationRequest, _: &Error) {
+                self.probe();
+            }
+        }
+
+        let hook = Arc::new(LockProbe::default());
+        let fetcher = HTTPFetcherBuilder::new("http://localhost:1")
+            .update_interval(Duration::from_secs(9999))
+            .build()
+            .unwrap();
+        let evaluator = Evaluator::new("default").with_hooks(vec![hook.clone()]);
+        let engine = Engine::new(
+            fetcher,
+            evaluator,
+            ErrorStrategy::Fallback,
+            snapshot::Snapshot::empty("default"),
+        );
+        hook.evaluator.set(engine.evaluator.clone()).ok().unwrap();
+
+        let request = EvaluationRequest {
+            flag_key: "missing".into(),
+            entity_id: "entity".into(),
+            context: HashMap::new(),
+            list_context: HashMap::new(),
+        };
+        assert!(engine.boolean(&request).is_err());
+        assert!(engine.variant(&request).is_err());
+        assert!(engine.batch(vec![request]).is_ok());
+
+        assert_eq!(hook.unlocked.load(Ordering::SeqCst), 6);
+    }
+
+    #[test]
+    fn test_seed_and_get_snapshot_with_format() {
+        let snapshot: snapshot::Snapshot = serde_json::from_value(serde_json::json!({
+            "version": 1,
+            "namespace": {
+                "key": "default",
+                "flags": {
+                    "flag1": { "key": "flag1", "enabled": true, "type": "VARIANT_FLAG_TYPE" }
+                },
+                "eval_rules": { "flag1": [] },
+                "eval_rollouts": { "flag1": [] },
+                "eval_distributions": {}
+            }
+        }))
+        .unwrap();
+
+        let format = SnapshotFormat {
+            encoding: codec::SnapshotEncoding::MessagePack,
+            compression: codec::SnapshotCompression::Deflate,
+        };
+        let encoded_snapshot = BASE64_STANDARD.encode(codec::encode(&snapshot, &format).unwrap());
+        let opts = CString::new(format!(
+            r#"{{"url":"http://localhost:1","error_strategy":"fallback","update_interval":9999,"snapshot":"{encoded_snapshot}"}}"#
+        ))
+        .unwrap();
+
+        let decode = |ptr: *const c_char| -> snapshot::Snapshot {
+            let encoded = unsafe { CStr::from_ptr(ptr) }.to_str().unwrap().to_string();
+            unsafe { _destroy_string(ptr as *mut c_char) };
+            codec::decode(&BASE64_STANDARD.decode(encoded).unwrap()).unwrap()
+        };
+
+        unsafe {
+            let engine_ptr = _initialize_engine(opts.as_ptr());
+            assert!(!engine_ptr.is_null());
+
+            // the default export stays base64-encoded JSON
+            let json = decode(_get_snapshot(engine_ptr));
+            assert_eq!(json, snapshot);
+
+            let format =
+                CString::new(r#"{"encoding":"message_pack","compression":"deflate"}"#).unwrap();
+            let compact = decode(_get_snapshot_with_format(engine_ptr, format.as_ptr()));
+            assert_eq!(compact, snapshot);
+
+            let digest_ptr = _get_snapshot_digest(engine_ptr);
+            let response: Value =
+                serde_json::from_str(CStr::from_ptr(digest_ptr).to_str().unwrap()).unwrap();
+            _destroy_string(digest_ptr as *mut c_char);
+            assert_eq!(response["result"], snapshot.digest());
+
+            _destroy_engine(engine_ptr);
+        }
+    }
+
+    #[test]
+    fn test_seed_snapshot_verifies_signature() {
+        use hmac::{Hmac, Mac};
+
+        let snapshot = r#"{"version":2,"namespace":{"key":"default","flags":{"flag1":{"key":"flag1","enabled":true,"type":"VARIANT_FLAG_TYPE"}},"segments":{},"eval_rules":{"flag1":[]},"eval_rollouts":{"flag1":[]},"eval_distributions":{}}}"#;
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(snapshot.as_bytes());
+        let signature = BASE64_STANDARD.encode(mac.finalize().into_bytes());
+
+        let seeded_flags = |signature: &str| -> usize {
+            let opts = CString::new(format!(
+                r#"{{"url":"http://localhost:1","error_strategy":"fallback","update_interval":9999,"snapshot":"{}","snapshot_signature":"{signature}","verification":{{"hmac":"secret"}}}}"#,
+                BASE64_STANDARD.encode(snapshot)
+            ))
+            .unwrap();
+
+            unsafe {
+                let engine_ptr = _initialize_engine(opts.as_ptr());
+                assert!(!engine_ptr.is_null());
+
+                let flags = get_engine(engine_ptr)
+                    .unwrap()
+                    .get_snapshot()
+                    .unwrap()
+                    .namespace()
+                    .flags
+                    .len();
+                _destroy_engine(engine_ptr);
+                flags
+            }
+        };
+
+        assert_eq!(seeded_flags(&signature), 1);
+        // a snapshot that fails verification is discarded
+        assert_eq!(seeded_flags("c2lnbmF0dXJl"), 0);
+    }
+
+    #[test]
+    fn test_seed_snapshot_yaml() {
+        let yaml = r#"
+namespace: team
+flags:
+  - key: flag_boolean
+    type: BOOLEAN_FLAG_TYPE
+    rollouts:
+      - segment:
+          key: everyone
+          value: true
+segments:
+  - key: everyone
+"#;
+
+        let seeded = |yaml: &str| -> snapshot::Snapshot {
+            let opts = CString::new(
+                serde_json::json!({
+                    "url": "http://localhost:1",
+                    "namespace": "team",
+                    "error_strategy": "fallback",
+                    "update_interval": 9999,
+                    "snapshot_yaml": yaml,
+                })
+                .to_string(),
+            )
+            .unwrap();
+
+            unsafe {
+                let engine_ptr = _initialize_engine(opts.as_ptr());
+                assert!(!engine_ptr.is_null());
+
+                let snapshot = get_engine(engine_ptr).unwrap().get_snapshot().unwrap();
+                _destroy_engine(engine_ptr);
+                snapshot
+            }
+        };
+
+        let snapshot = seeded(yaml);
+        assert_eq!(snapshot, declarative::parse_snapshot(yaml, "team").unwrap());
+        let rollouts = &snapshot.namespace().eval_rollouts["flag_boolean"];
+        assert_eq!(
+            rollouts[0].segment.as_ref().unwrap().segment_keys,
+            vec!["everyone".to_string()]
+        );
+
+        // a document that fails to resolve is discarded
+        let unresolved = seeded(&yaml.replace("  - key: everyone", "  - key: nobody"));
+        assert!(unresolved.namespace().flags.is_empty());
+    }
+
+    #[test]
+    fn test_initial_fetch_signature_failure_fails() {
+        // not pooled: pooled servers may be bound to the runtime of a finished async test
+        let mut server = mockito::Server::new_with_opts(mockito::ServerOpts::default());
+        let _mock = server
+            .mock("GET", "/internal/v1/evaluation/snapshot/namespace/default")
+            .with_status(200)
+            .with_body(r#"{"namespace":{"key":"default"},"flags":[]}"#)
+            .create();
+
+        let opts = CString::new(format!(
+            r#"{{"url":"{}","error_strategy":"fail","update_interval":9999,"verification":{{"hmac":"secret"}}}}"#,
+            server.url()
+        ))
+        .unwrap();
+
+        unsafe {
+            let engine_ptr = _initialize_engine(opts.as_ptr());
+            assert!(!engine_ptr.is_null());
+
+            let err = get_engine(engine_ptr).unwrap().list_flags().unwrap_err();
+            assert!(matches!(err, Error::InvalidSignature(_)), "{err:?}");
+
+            _destroy_engine(engine_ptr);
+        }
+    }
+
+    unsafe extern "C" fn forward_event(event: *const c_char, user_data: *mut c_void) {
+        let sender = &*(user_data as *const std::sync::mpsc::Sender<String>);
+        let _ = sender.send(CStr::from_ptr(event).to_str().unwrap().to_string());
+    }
+
+    #[test]
+    fn test_snapshot_callback() {
+        // not pooled: pooled servers may be bound to the runtime of a finished async test
+        let mut server = mockito::Server::new_with_opts(mockito::ServerOpts::default());
+        let _initial = server
+            .mock("GET", "/internal/v1/evaluation/snapshot/namespace/default")
+            .with_status(200)
+            .with_body(r#"{"namespace":{"key":"default"},"flags":[{"key":"flag1","name":"flag1","enabled":true,"type":"BOOLEAN_FLAG_TYPE"}]}"#)
+            .create();
+
+        let opts = CString::new(format!(
+            r#"{{"url":"{}","update_interval":1}}"#,
+            server.url()
+        ))
+        .unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel::<String>();
+
+        unsafe {
+            let engine_ptr = _initialize_engine(opts.as_ptr());
+            assert!(!engine_ptr.is_null());
+
+            let result = _register_snapshot_callback(engine_ptr, None, std::ptr::null_mut());
+            assert!(CStr::from_ptr(result).to_str().unwrap().contains("failure"));
+            _destroy_string(result as *mut c_char);
+
+            let result = _register_snapshot_callback(
+                engine_ptr,
+                Some(forward_event),
+                &sender as *const _ as *mut c_void,
+            );
+            assert!(CStr::from_ptr(result).to_str().unwrap().contains("success"));
+            _destroy_string(result as *mut c_char);
+
+            // refetching the initial snapshot is not reported
+            assert!(receiver.recv_timeout(Duration::from_millis(1500)).is_err());
+
+            // the most recent mock serves the following polls
+            let _changed = server
+                .mock("GET", "/internal/v1/evaluation/snapshot/namespace/default")
+                .with_status(200)
+                .with_body(r#"{"namespace":{"key":"default"},"flags":[{"key":"flag1","name":"flag1","enabled":false,"type":"BOOLEAN_FLAG_TYPE"}]}"#)
+                .create();
+
+            let event: Value = serde_json::from_str(
+                &receiver
+                    .recv_timeout(Duration::from_secs(10))
+                    .expect("snapshot event"),
+            )
+            .unwrap();
+            assert_eq!(event["type"], "snapshot_applied");
+            assert_eq!(
+                event["digest"],
+                get_engine(engine_ptr)
+                    .unwrap()
+                    .get_snapshot_digest()
+                    .unwrap()
+            );
+            assert_eq!(event["changed_flags"], serde_json::json!(["flag1"]));
+
+            let result = _unregister_snapshot_callback(engine_ptr);
+            _destroy_string(result as *mut c_char);
+            while receiver.try_recv().is_ok() {}
+
+            _destroy_engine(engine_ptr);
+        }
+
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_poll_events() {
+        // not pooled: pooled servers may be bound to the runtime of a finished async test
+        let mut server = mockito::Server::new_with_opts(mockito::ServerOpts::default());
+        let _mock = server
+            .mock("GET", "/internal/v1/evaluation/snapshot/namespace/default")
+            .with_status(200)
+            .with_body(r#"{"namespace":{"key":"default"},"flags":[]}"#)
+            .create();
+
+        let opts = CString::new(format!(
+            r#"{{"url":"{}","update_interval":1}}"#,
+            server.url()
+        ))
+        .unwrap();
+
+        unsafe {
+            let engine_ptr = _initialize_engine(opts.as_ptr());
+            assert!(!engine_ptr.is_null());
+
+            let auth_json = CString::new(r#"{"client_token":"new-secret"}"#).unwrap();
+            let result = _update_authentication(engine_ptr, auth_json.as_ptr());
+            _destroy_string(result as *mut c_char);
+
+            let _changed = server
+                .mock("GET", "/internal/v1/evaluation/snapshot/namespace/default")
+                .with_status(200)
+                .with_body(r#"{"namespace":{"key":"default"},"flags":[{"key":"flag1","name":"flag1","enabled":true,"type":"BOOLEAN_FLAG_TYPE"}]}"#)
+                .create();
+
+            let result = _poll_events(engine_ptr, 0);
+            let response: Value =
+                serde_json::from_str(CStr::from_ptr(result).to_str().unwrap()).unwrap();
+            _destroy_string(result as *mut c_char);
+            assert_eq!(response["status"], "success");
+            assert_eq!(response["result"][0]["type"], "auth_updated");
+
+            // waits for the next poll of the fetch loop
+            let result = _poll_events(engine_ptr, 10_000);
+            let response: Value =
+                serde_json::from_str(CStr::from_ptr(result).to_str().unwrap()).unwrap();
+            _destroy_string(result as *mut c_char);
+            assert_eq!(response["result"][0]["type"], "snapshot_applied");
+
+            _destroy_engine(engine_ptr);
+        }
+    }
+
+    #[test]
+    fn test_get_status() {
+        // not pooled: pooled servers may be bound to the runtime of a finished async test
+        let mut server = mockito::Server::new_with_opts(mockito::ServerOpts::default());
+        let _mock = server
+            .mock("GET", "/internal/v1/evaluation/snapshot/namespace/default")
+            .with_status(200)
+            .with_header("etag", "etag")
+            .with_body(r#"{"namespace":{"key":"default"},"flags":[{"key":"flag1","name":"flag1","enabled":true,"type":"BOOLEAN_FLAG_TYPE"}]}"#)
+            .create();
+
+        let get_status = |opts: String| unsafe {
+            let opts = CString::new(opts).unwrap();
+            let engine_ptr = _initialize_engine(opts.as_ptr());
+            assert!(!engine_ptr.is_null());
+
+            let result = _get_status(engine_ptr);
+            let response: Value =
+                serde_json::from_str(CStr::from_ptr(result).to_str().unwrap()).unwrap();
+            _destroy_string(result as *mut c_char);
+            _destroy_engine(engine_ptr);
+
+            assert_eq!(response["status"], "success");
+            response["result"].clone()
+        };
+
+        let status = get_status(format!(r#"{{"url":"{}"}}"#, server.url()));
+        assert_eq!(status["fetch_mode"], "polling");
+        assert!(status["last_success_at"].is_string());
+        assert_eq!(status["last_error"], Value::Null);
+        assert_eq!(status["consecutive_failures"], 0);
+        assert_eq!(status["etag"], "etag");
+        assert_eq!(status["stream_connected"], false);
+        assert_eq!(status["serving_fallback"], false);
+        assert_eq!(status["flag_count"], 1);
+        assert!(status["snapshot_digest"].is_string());
+
+        let status = get_status(
+            r#"{"url":"http://localhost:1","error_strategy":"fallback","update_interval":9999}"#
+                .to_string(),
+        );
+        assert_eq!(status["last_success_at"], Value::Null);
+        assert!(status["last_error"].is_string());
+        assert!(status["last_error_at"].is_string());
+        assert_eq!(status["consecutive_failures"], 1);
+        assert_eq!(status["serving_fallback"], true);
+        assert_eq!(status["flag_count"], 0);
+
+        let status =
+            get_status(r#"{"url":"http://localhost:1","update_interval":9999}"#.to_string());
+        assert_eq!(status["serving_fallback"], false);
+        assert_eq!(status["snapshot_digest"], Value::Null);
+    }
+
+    #[test]
+    fn test_snapshot_cache() {
+        let dir = std::env::temp_dir().join(format!("flipt-engine-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // not pooled: pooled servers may be bound to the runtime of a finished async test
+        let mut server = mockito::Server::new_with_opts(mockito::ServerOpts::default());
+        let fetched = server
+            .mock("GET", "/internal/v1/evaluation/snapshot/namespace/default")
+            .match_header("if-none-match", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_header("etag", "v1")
+            .with_body(r#"{"namespace":{"key":"default"},"flags":[{"key":"flag1","name":"flag1","enabled":true,"type":"BOOLEAN_FLAG_TYPE"}]}"#)
+            .expect(2)
+            .create();
+        let not_modified = server
+            .mock("GET", "/internal/v1/evaluation/snapshot/namespace/default")
+            .match_header("if-none-match", "v1")
+            .with_status(304)
+            .expect_at_least(1)
+            .create();
+
+        let flag_keys_with = |url: &str, extra: &str| unsafe {
+            let opts = CString::new(format!(
+                r#"{{"url":"{url}","update_interval":9999,"retry":{{"max_retries":0}},"snapshot_cache":{{"dir":"{}"}}{extra}}}"#,
+                dir.display()
+            ))
+            .unwrap();
+            let engine_ptr = _initialize_engine(opts.as_ptr());
+            assert!(!engine_ptr.is_null());
+
+            let result = _list_flags(engine_ptr);
+            let response: Value =
+                serde_json::from_str(CStr::from_ptr(result).to_str().unwrap()).unwrap();
+            _destroy_string(result as *mut c_char);
+            _destroy_engine(engine_ptr);
+
+            response["result"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|flag| flag["key"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        let flag_keys = |url: &str| flag_keys_with(url, r#","error_strategy":"fallback""#);
+        let seed = BASE64_STANDARD.encode(
+            serde_json::to_string(&snapshot::Snapshot::build(
+                serde_json::from_str(r#"{"namespace":{"key":"default"},"flags":[{"key":"seeded","name":"seeded","enabled":true,"type":"BOOLEAN_FLAG_TYPE"}]}"#)
+                    .unwrap(),
+            ))
+            .unwrap(),
+        );
+        let seeded = format!(r#","error_strategy":"fallback","snapshot":"{seed}""#);
+
+        // the fetched snapshot is written to the cache
+        assert_eq!(flag_keys(&server.url()), vec!["flag1"]);
+        assert!(dir.join("default_default.json").exists());
+
+        // and served when the server can't be reached
+        assert_eq!(flag_keys("http://localhost:1"), vec!["flag1"]);
+
+        // also when failed fetches are reported as errors
+        assert_eq!(
+            flag_keys_with("http://localhost:1", r#","error_strategy":"fail""#),
+            vec!["flag1"]
+        );
+
+        // or when the server confirms it is still current
+        assert_eq!(flag_keys(&server.url()), vec!["flag1"]);
+
+        // an explicit seed takes priority over the cache
+        assert_eq!(
+            flag_keys_with("http://localhost:1", &seeded),
+            vec!["seeded"]
+        );
+
+        // so the cached ETag isn't sent and the seed is replaced by the fetched snapshot
+        assert_eq!(flag_keys_with(&server.url(), &seeded), vec!["flag1"]);
+
+        // but not when snapshots must be verified, as the cache holds no signature
+        assert!(flag_keys_with(
+            "http://localhost:1",
+            r#","error_strategy":"fallback","verification":{"hmac":"secret"}"#
+        )
+        .is_empty());
+
+        fetched.assert();
+        not_modified.assert();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_fetch_mode() {
+        let path =
+            std::env::temp_dir().join(format!("flipt-engine-file-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"namespace":{"key":"default"},"flags":[{"key":"flag1","name":"flag1","enabled":true,"type":"BOOLEAN_FLAG_TYPE"}]}"#,
+        )
+        .unwrap();
+
+        unsafe {
+            let opts = CString::new(format!(
+                r#"{{"fetch_mode":{{"file":"{}"}},"error_strategy":"fail"}}"#,
+                path.display()
+            ))
+            .unwrap();
+            let engine_ptr = _initialize_engine(opts.as_ptr());
+            assert!(!engine_ptr.is_null());
+
+            let result = _list_flags(engine_ptr);
+            let response: Value =
+                serde_json::from_str(CStr::from_ptr(result).to_str().unwrap()).unwrap();
+            _destroy_string(result as *mut c_char);
+            assert_eq!(response["status"], "success");
+            assert_eq!(response["result"][0]["key"], "flag1");
+
+            let result = _get_status(engine_ptr);
+            let response: Value =
+                serde_json::from_str(CStr::from_ptr(result).to_str().unwrap()).unwrap();
+            _destroy_string(result as *mut c_char);
+            assert_eq!(
+                response["result"]["fetch_mode"]["file"],
+                path.display().to_string()
+            );
+
+            _destroy_engine(engine_ptr);
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
 }
@@ -0,0 +1,294 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use fliptevaluation::error::Error;
+use fliptevaluation::models::snapshot;
+use serde::{Deserialize, Serialize};
+
+/// Configuration for persisting applied snapshots so an engine can start without reaching Flipt.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct SnapshotCacheOpts {
+    /// Directory the snapshots are written to; created if missing.
+    pub dir: String,
+    /// Seconds since it was last confirmed by the server after which a cached snapshot is no
+    /// longer loaded. Unlimited by default.
+    pub max_staleness: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    etag: Option<String>,
+    snapshot: snapshot::Snapshot,
+}
+
+/// A snapshot loaded from the cache, with the ETag it was served with.
+#[derive(Debug)]
+pub struct CachedSnapshot {
+    pub snapshot: snapshot::Snapshot,
+    pub etag: Option<String>,
+}
+
+/// On-disk copy of the last snapshot applied for an environment and namespace.
+///
+/// The file's modification time records when the server last confirmed the snapshot, either by
+/// serving it or by answering that it was not modified.
+#[derive(Debug, Clone)]
+pub struct SnapshotCache {
+    path: PathBuf,
+    namespace: String,
+    max_staleness: Option<Duration>,
+}
+
+impl SnapshotCache {
+    pub fn new(
+        opts: &SnapshotCacheOpts,
+        environment: &str,
+        namespace: &str,
+        reference: Option<&str>,
+    ) -> Self {
+        let mut name = format!("{}_{}", escape(environment), escape(namespace));
+        if let Some(reference) = reference {
+            name = format!("{name}_{}", escape(reference));
+        }
+
+        Self {
+            path: PathBuf::from(&opts.dir).join(format!("{name}.json")),
+            namespace: namespace.to_string(),
+            max_staleness: opts.max_staleness.map(Duration::from_secs),
+        }
+    }
+
+    /// The cached snapshot, unless there is none, it can't be read, it is too stale or it holds
+    /// another namespace.
+    pub fn load(&self) -> Option<CachedSnapshot> {
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        if let Some(max_staleness) = self.max_staleness {
+            if age > max_staleness {
+                log::info!(
+                    "ignoring snapshot cached {}s ago at {}",
+                    age.as_secs(),
+                    self.path.display()
+                );
+                return None;
+            }
+        }
+
+        let entry = fs::read(&self.path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| {
+                serde_json::from_slice::<CacheEntry>(&bytes).map_err(|e| e.to_string())
+            });
+        match entry {
+            Ok(entry) if entry.snapshot.namespace().key != self.namespace => {
+                log::warn!(
+                    "ignoring cached snapshot {} of namespace {}",
+                    self.path.display(),
+                    entry.snapshot.namespace().key
+                );
+                None
+            }
+            Ok(entry) => Some(CachedSnapshot {
+                snapshot: entry.snapshot,
+                etag: entry.etag,
+            }),
+            Err(e) => {
+                log::warn!(
+                    "failed to read cached snapshot {}: {e}",
+                    self.path.display()
+                );
+                None
+            }
+        }
+    }
+
+    /// Write the snapshot, replacing the cached one atomically.
+    pub fn store(&self, snapshot: &snapshot::Snapshot, etag: Option<String>) -> Result<(), Error> {
+        let io_error =
+            |e: std::io::Error| Error::Internal(format!("failed to write snapshot cache: {e}"));
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(io_error)?;
+        }
+
+        let bytes = serde_json::to_vec(&CacheEntry {
+            etag,
+            snapshot: snapshot.clone(),
+        })
+        .map_err(|e| Error::Internal(format!("failed to encode snapshot cache: {e}")))?;
+
+        // Write next to the target and rename, so readers never see a partial file. The name is
+        // unique so processes sharing the directory don't write to the same temporary file.
+        let tmp = self.path.with_extension(format!(
+            "json.{}-{:x}.tmp",
+            std::process::id(),
+            fastrand::u64(..)
+        ));
+        let result = File::create(&tmp)
+            .and_then(|mut file| {
+                file.write_all(&bytes)?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&tmp, &self.path));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        result.map_err(io_error)
+    }
+
+    /// Mark the cached snapshot as confirmed by the server now.
+    pub fn touch(&self) {
+        let result = File::options()
+            .write(true)
+            .open(&self.path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        if let Err(e) = result {
+            log::debug!(
+                "failed to refresh cached snapshot {}: {e}",
+                self.path.display()
+            );
+        }
+    }
+}
+
+/// Percent-encode everything but ASCII alphanumerics and `-`, so distinct values never map to
+/// the same file name and `_` can separate them.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fliptevaluation::models::source;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "flipt-snapshot-cache-{name}-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn cache(dir: &std::path::Path, max_staleness: Option<u64>) -> SnapshotCache {
+        SnapshotCache::new(
+            &SnapshotCacheOpts {
+                dir: dir.to_string_lossy().into_owned(),
+                max_staleness,
+            },
+            "production",
+            "team/a",
+            None,
+        )
+    }
+
+    #[test]
+    fn test_store_and_load() {
+        let dir = temp_dir("store");
+        let cache = cache(&dir, None);
+        assert!(cache.load().is_none());
+
+        let document: source::Document = serde_json::from_str(
+            r#"{"namespace":{"key":"team/a"},"flags":[{"key":"flag1","name":"flag1","enabled":true,"type":"BOOLEAN_FLAG_TYPE"}]}"#,
+        )
+        .unwrap();
+        let snapshot = snapshot::Snapshot::build(document);
+        cache.store(&snapshot, Some("etag".into())).unwrap();
+
+        assert!(dir.join("production_team%2Fa.json").exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        let cached = cache.load().unwrap();
+        assert_eq!(cached.snapshot, snapshot);
+        assert_eq!(cached.snapshot.digest(), snapshot.digest());
+        assert_eq!(cached.etag, Some("etag".into()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_respects_max_staleness() {
+        let dir = temp_dir("staleness");
+        cache(&dir, None)
+            .store(&snapshot::Snapshot::empty("team/a"), None)
+            .unwrap();
+
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        File::options()
+            .write(true)
+            .open(dir.join("production_team%2Fa.json"))
+            .and_then(|file| file.set_modified(an_hour_ago))
+            .unwrap();
+
+        assert!(cache(&dir, Some(60)).load().is_none());
+        assert!(cache(&dir, Some(7200)).load().is_some());
+
+        // confirming the snapshot makes it fresh again
+        cache(&dir, Some(60)).touch();
+        assert!(cache(&dir, Some(60)).load().is_some());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_names_do_not_collide() {
+        let opts = SnapshotCacheOpts {
+            dir: "cache".into(),
+            max_staleness: None,
+        };
+        let paths: Vec<PathBuf> = [
+            ("production", "team/a", None),
+            ("production", "team.a", None),
+            ("production", "team_a", None),
+            ("a_b", "c", None),
+            ("a", "b_c", None),
+            ("a", "b", Some("c")),
+        ]
+        .iter()
+        .map(|(environment, namespace, reference)| {
+            SnapshotCache::new(&opts, environment, namespace, *reference).path
+        })
+        .collect();
+
+        for (i, path) in paths.iter().enumerate() {
+            assert!(!paths[i + 1..].contains(path), "{path:?}");
+        }
+    }
+
+    #[test]
+    fn test_load_rejects_other_namespace() {
+        let dir = temp_dir("namespace");
+        cache(&dir, None)
+            .store(&snapshot::Snapshot::empty("team/b"), None)
+            .unwrap();
+
+        assert!(cache(&dir, None).load().is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_ignores_corrupt_cache() {
+        let dir = temp_dir("corrupt");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("production_team%2Fa.json"), b"{\"etag\":").unwrap();
+
+        assert!(cache(&dir, None).load().is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use fliptevaluation::clock::{Clock, SystemClock};
+use fliptevaluation::error::Error;
+use serde::Serialize;
+
+use crate::http::FetchMode;
+
+/// Health of the engine's fetching, as returned by `get_status`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EngineStatus {
+    pub fetch_mode: FetchMode,
+    /// RFC 3339 time of the last fetch that succeeded, including unmodified responses.
+    pub last_success_at: Option<String>,
+    pub last_error: Option<String>,
+    /// RFC 3339 time of the last fetch that failed.
+    pub last_error_at: Option<String>,
+    /// Failed fetches since the last successful one.
+    pub consecutive_failures: u64,
+    pub etag: Option<String>,
+    pub stream_connected: bool,
+    /// Streams torn down because no data arrived within the idle timeout.
+    pub stream_stalls: u64,
+    /// Whether evaluations are served from the previous snapshot because fetching failed under
+    /// the fallback error strategy.
+    pub serving_fallback: bool,
+    /// Digest of the snapshot evaluations are served from, unset while they fail.
+    pub snapshot_digest: Option<String>,
+    pub flag_count: usize,
+}
+
+/// Fetch health shared between the fetcher and the engine.
+#[derive(Clone, Default)]
+pub struct StatusTracker {
+    status: Arc<Mutex<EngineStatus>>,
+}
+
+impl StatusTracker {
+    pub fn new(fetch_mode: FetchMode) -> Self {
+        Self {
+            status: Arc::new(Mutex::new(EngineStatus {
+                fetch_mode,
+                ..Default::default()
+            })),
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut status = self.lock();
+        status.last_success_at = Some(SystemClock.now().to_rfc3339());
+        status.consecutive_failures = 0;
+    }
+
+    pub fn record_failure(&self, error: &Error) {
+        let mut status = self.lock();
+        status.last_error = Some(error.to_string());
+        status.last_error_at = Some(SystemClock.now().to_rfc3339());
+        status.consecutive_failures += 1;
+    }
+
+    pub fn record<T>(&self, result: &Result<T, Error>) {
+        match result {
+            Ok(_) => self.record_success(),
+            Err(e) => self.record_failure(e),
+        }
+    }
+
+    /// Record a stream that stopped delivering data, which counts as a failed fetch.
+    pub fn record_stream_stall(&self, error: &Error) {
+        self.record_failure(error);
+        self.lock().stream_stalls += 1;
+    }
+
+    pub fn set_etag(&self, etag: Option<String>) {
+        self.lock().etag = etag;
+    }
+
+    pub fn set_stream_connected(&self, connected: bool) {
+        self.lock().stream_connected = connected;
+    }
+
+    /// The recorded fetch health; snapshot details are left for the engine to fill in.
+    pub fn status(&self) -> EngineStatus {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, EngineStatus> {
+        self.status.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_tracker() {
+        let tracker = StatusTracker::new(FetchMode::Streaming);
+        let error = Error::Server("boom".into());
+
+        tracker.record_failure(&error);
+        tracker.record::<()>(&Err(error));
+        tracker.set_etag(Some("etag".into()));
+        tracker.set_stream_connected(true);
+
+        let status = tracker.status();
+        assert_eq!(status.fetch_mode, FetchMode::Streaming);
+        assert_eq!(status.consecutive_failures, 2);
+        assert_eq!(status.last_error, Some("server error: boom".into()));
+        assert!(status.last_error_at.is_some());
+        assert!(status.last_success_at.is_none());
+        assert_eq!(status.etag, Some("etag".into()));
+        assert!(status.stream_connected);
+
+        tracker.record(&Ok(()));
+
+        let status = tracker.status();
+        assert_eq!(status.consecutive_failures, 0);
+        assert!(status.last_success_at.is_some());
+        // the last error is kept for diagnosis
+        assert_eq!(status.last_error, Some("server error: boom".into()));
+    }
+}
@@ -1,7 +1,7 @@
 use crate::Error;
 use serde::Deserialize;
 
-#[derive(Deserialize, Debug, PartialEq, Default)]
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct TlsConfig {
     /// Path to custom CA certificate file (PEM format)
     ca_cert_file: Option<String>,
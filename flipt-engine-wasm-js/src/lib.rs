@@ -4,8 +4,16 @@ use base64::Engine as Base64Engine;
 use wasm_bindgen::prelude::*;
 
 use fliptevaluation::{
-    batch_evaluation, boolean_evaluation, error::Error, models::snapshot, models::source,
-    store::Store, variant_evaluation,
+    batch_evaluation_with_options, boolean_evaluation,
+    codec::{self, SnapshotFormat},
+    declarative,
+    error::Error,
+    hook::{self, Hooks},
+    models::snapshot,
+    models::source,
+    signature::Verifier,
+    store::Store,
+    variant_evaluation, EvaluationOptions, EvaluationRequest,
 };
 use serde::{Deserialize, Serialize};
 
@@ -32,6 +40,8 @@ enum Status {
 pub struct Engine {
     namespace: String,
     store: snapshot::Snapshot,
+    hooks: Hooks,
+    verifier: Option<Verifier>,
 }
 
 impl<T, E> From<Result<T, E>> for JsResponse<T>
@@ -65,10 +75,20 @@ impl Engine {
         Self {
             namespace: namespace.to_string(),
             store,
+            hooks: Hooks::new(),
+            verifier: None,
         }
     }
 
+    /// Replace the snapshot with a fetched document. Rejected once a verifier is set, as the
+    /// signature covers the serialized document; use `signed_snapshot` instead.
     pub fn snapshot(&mut self, data: JsValue) -> Result<(), JsValue> {
+        if self.verifier.is_some() {
+            return Err(JsValue::from_str(
+                "snapshots must be signed when a verifier is set",
+            ));
+        }
+
         let doc: source::Document = match serde_wasm_bindgen::from_value(data) {
             Ok(document) => document,
             Err(e) => {
@@ -80,36 +100,71 @@ impl Engine {
         Ok(())
     }
 
-    pub fn seed_snapshot(&mut self, snapshot_b64: &str) -> Result<(), JsValue> {
-        let decoded = BASE64_STANDARD
-            .decode(snapshot_b64)
-            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    /// Replace the snapshot with a serialized document along with its base64-encoded signature.
+    pub fn signed_snapshot(&mut self, data: &str, signature: &str) -> Result<(), JsValue> {
+        self.verify(data, Some(signature))?;
+        let doc: source::Document =
+            serde_json::from_str(data).map_err(|e| JsValue::from_str(&e.to_string()))?;
 
-        let snapshot: snapshot::Snapshot =
-            serde_json::from_slice(&decoded).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.store = snapshot::Snapshot::build(doc);
+        Ok(())
+    }
 
-        if snapshot.namespace.key != self.namespace {
-            return Err(JsValue::from_str(&format!(
-                "snapshot namespace '{}' does not match engine namespace '{}'",
-                snapshot.namespace.key, self.namespace
-            )));
-        }
+    /// Replace the snapshot with the engine's namespace in a Flipt declarative `features.yml`.
+    pub fn snapshot_yaml(&mut self, data: &str) -> Result<(), JsValue> {
+        self.apply_yaml(data, None)
+    }
 
-        self.store = snapshot;
+    /// Replace the snapshot from a declarative `features.yml` along with its base64-encoded signature.
+    pub fn signed_snapshot_yaml(&mut self, data: &str, signature: &str) -> Result<(), JsValue> {
+        self.apply_yaml(data, Some(signature))
+    }
+
+    pub fn seed_snapshot(&mut self, snapshot_b64: &str) -> Result<(), JsValue> {
+        self.seed(snapshot_b64, None)
+    }
+
+    /// Require seeded and fetched snapshots to be signed, e.g. `{ ed25519: "<public key>" }` or `{ hmac: "<secret>" }`.
+    pub fn set_verifier(&mut self, verifier: JsValue) -> Result<(), JsValue> {
+        let verifier: Verifier = serde_wasm_bindgen::from_value(verifier)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        self.verifier = Some(verifier);
         Ok(())
     }
 
+    /// Seed a snapshot along with its base64-encoded signature over the serialized snapshot.
+    pub fn seed_signed_snapshot(
+        &mut self,
+        snapshot_b64: &str,
+        signature: &str,
+    ) -> Result<(), JsValue> {
+        self.seed(snapshot_b64, Some(signature))
+    }
+
     pub fn get_snapshot(&self) -> Result<String, JsValue> {
-        let json =
-            serde_json::to_string(&self.store).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.encode_snapshot(&SnapshotFormat::default())
+    }
+
+    /// Export the snapshot in the given format, e.g. `{ encoding: "message_pack", compression: "deflate" }`.
+    pub fn get_snapshot_with_format(&self, format: JsValue) -> Result<String, JsValue> {
+        let format: SnapshotFormat = serde_wasm_bindgen::from_value(format)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        self.encode_snapshot(&format)
+    }
 
-        Ok(BASE64_STANDARD.encode(json))
+    /// Content digest of the current snapshot, identical for engines holding the same configuration.
+    pub fn get_snapshot_digest(&self) -> String {
+        self.store.digest().to_string()
     }
 
     pub fn evaluate_boolean(&self, request: JsValue) -> Result<JsValue, JsValue> {
         let result: Result<fliptevaluation::BooleanEvaluationResponse, Error> =
             match serde_wasm_bindgen::from_value(request) {
-                Ok(req) => boolean_evaluation(&self.store, &self.namespace, &req),
+                Ok(req) => hook::evaluate(&self.hooks, &self.namespace, &req, || {
+                    boolean_evaluation(&self.store, &self.namespace, &req)
+                }),
                 Err(e) => Err(Error::InvalidRequest(e.to_string())),
             };
 
@@ -121,7 +176,9 @@ impl Engine {
     pub fn evaluate_variant(&self, request: JsValue) -> Result<JsValue, JsValue> {
         let result: Result<fliptevaluation::VariantEvaluationResponse, Error> =
             match serde_wasm_bindgen::from_value(request) {
-                Ok(req) => variant_evaluation(&self.store, &self.namespace, &req),
+                Ok(req) => hook::evaluate(&self.hooks, &self.namespace, &req, || {
+                    variant_evaluation(&self.store, &self.namespace, &req)
+                }),
                 Err(e) => Err(Error::InvalidRequest(e.to_string())),
             };
 
@@ -132,8 +189,15 @@ impl Engine {
 
     pub fn evaluate_batch(&self, request: JsValue) -> Result<JsValue, JsValue> {
         let result: Result<fliptevaluation::BatchEvaluationResponse, Error> =
-            match serde_wasm_bindgen::from_value(request) {
-                Ok(req) => batch_evaluation(&self.store, &self.namespace, req),
+            match serde_wasm_bindgen::from_value::<Vec<EvaluationRequest>>(request) {
+                Ok(req) => hook::evaluate_batch(&self.hooks, &self.namespace, &req, |req| {
+                    batch_evaluation_with_options(
+                        &self.store,
+                        &self.namespace,
+                        req,
+                        &EvaluationOptions::default(),
+                    )
+                }),
                 Err(e) => Err(Error::InvalidRequest(e.to_string())),
             };
 
@@ -150,6 +214,63 @@ impl Engine {
     }
 }
 
+impl Engine {
+    /// Register hooks that are notified around every evaluation. Panics abort on WebAssembly, so
+    /// unlike in the native engine a panicking hook is not isolated and takes the engine down.
+    pub fn with_hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    fn seed(&mut self, snapshot_b64: &str, signature: Option<&str>) -> Result<(), JsValue> {
+        let decoded = BASE64_STANDARD
+            .decode(snapshot_b64)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        if let Some(verifier) = &self.verifier {
+            verifier
+                .verify(&decoded, signature)
+                .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        }
+
+        let snapshot = codec::decode(&decoded).map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        if snapshot.namespace().key != self.namespace {
+            return Err(JsValue::from_str(&format!(
+                "snapshot namespace '{}' does not match engine namespace '{}'",
+                snapshot.namespace().key,
+                self.namespace
+            )));
+        }
+
+        self.store = snapshot;
+        Ok(())
+    }
+
+    fn apply_yaml(&mut self, data: &str, signature: Option<&str>) -> Result<(), JsValue> {
+        self.verify(data, signature)?;
+        self.store = declarative::parse_snapshot(data, &self.namespace)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(())
+    }
+
+    fn verify(&self, data: &str, signature: Option<&str>) -> Result<(), JsValue> {
+        match &self.verifier {
+            Some(verifier) => verifier
+                .verify(data.as_bytes(), signature)
+                .map_err(|e| JsValue::from_str(&e.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    fn encode_snapshot(&self, format: &SnapshotFormat) -> Result<String, JsValue> {
+        let bytes =
+            codec::encode(&self.store, format).map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        Ok(BASE64_STANDARD.encode(bytes))
+    }
+}
+
 fn serialize_response<T: Serialize>(response: JsResponse<T>) -> Result<JsValue, JsValue> {
     serde_wasm_bindgen::to_value(&response).or_else(|e| {
         let error_response: JsResponse<()> = JsResponse {
@@ -200,6 +321,7 @@ pub mod tests {
             flag_key: "flag1".to_owned(),
             entity_id: "one".to_owned(),
             context: HashMap::new(),
+            list_context: HashMap::new(),
         };
         let req = serde_wasm_bindgen::to_value(&eval_req).unwrap();
         let js_value = engine.evaluate_variant(req.clone());
@@ -248,6 +370,48 @@ pub mod tests {
         assert_eq!(response.result.unwrap().len(), 1);
     }
 
+    #[wasm_bindgen_test]
+    fn test_get_and_seed_snapshot_with_format() {
+        let mut engine = Engine::new("default");
+        let state = r#"{"namespace":{"key":"default"},"flags":[{"key":"flag1","name":"flag1","enabled":true,"type":"BOOLEAN_FLAG_TYPE"}]}"#;
+        let document: source::Document = serde_json::from_str(state).expect("valid snapshot");
+        engine
+            .snapshot(serde_wasm_bindgen::to_value(&document).unwrap())
+            .unwrap();
+
+        let format = serde_wasm_bindgen::to_value(&SnapshotFormat {
+            encoding: codec::SnapshotEncoding::MessagePack,
+            compression: codec::SnapshotCompression::Deflate,
+        })
+        .unwrap();
+        let encoded = engine
+            .get_snapshot_with_format(format)
+            .expect("snapshot export");
+
+        let mut seeded = Engine::new("default");
+        seeded.seed_snapshot(&encoded).expect("snapshot seed");
+        assert_eq!(seeded.store, engine.store);
+        assert_eq!(seeded.get_snapshot_digest(), engine.get_snapshot_digest());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_seed_signed_snapshot() {
+        let engine = Engine::new("default");
+        let encoded = engine.get_snapshot().expect("snapshot export");
+
+        let mut seeded = Engine::new("default");
+        seeded
+            .set_verifier(
+                serde_wasm_bindgen::to_value(&serde_json::json!({ "hmac": "secret" })).unwrap(),
+            )
+            .expect("verifier");
+
+        assert!(seeded.seed_snapshot(&encoded).is_err());
+        assert!(seeded
+            .seed_signed_snapshot(&encoded, "c2lnbmF0dXJl")
+            .is_err());
+    }
+
     #[wasm_bindgen_test]
     fn test_seed_snapshot_rejects_invalid_base64() {
         let mut engine = Engine::new("default");
@@ -270,6 +434,44 @@ pub mod tests {
         );
     }
 
+    #[wasm_bindgen_test]
+    fn test_snapshot_yaml() {
+        let mut engine = Engine::new("default");
+        let yaml = "namespace: default\nflags:\n  - key: flag1\n    type: BOOLEAN_FLAG_TYPE\n    enabled: true\n";
+        engine.snapshot_yaml(yaml).expect("snapshot yaml");
+
+        let flags = engine.list_flags().expect("flags response");
+        let response: JsResponse<Vec<fliptevaluation::models::flipt::Flag>> =
+            serde_wasm_bindgen::from_value(flags).expect("list flags response");
+        assert_eq!(response.result.unwrap().len(), 1);
+
+        let result = engine.snapshot_yaml("flags: [");
+        assert!(result.is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_signed_snapshot() {
+        let data = r#"{"namespace":{"key":"default"},"flags":[{"key":"flag1","name":"flag1","enabled":true,"type":"BOOLEAN_FLAG_TYPE"}]}"#;
+        let document: source::Document = serde_json::from_str(data).expect("valid snapshot");
+
+        let mut engine = Engine::new("default");
+        engine
+            .set_verifier(
+                serde_wasm_bindgen::to_value(&serde_json::json!({ "hmac": "secret" })).unwrap(),
+            )
+            .expect("verifier");
+
+        assert!(engine
+            .snapshot(serde_wasm_bindgen::to_value(&document).unwrap())
+            .is_err());
+        assert!(engine.snapshot_yaml("flags: []").is_err());
+        assert!(engine.signed_snapshot(data, "c2lnbmF0dXJl").is_err());
+        assert!(engine
+            .signed_snapshot_yaml("flags: []", "c2lnbmF0dXJl")
+            .is_err());
+        assert!(engine.store.namespace().flags.is_empty());
+    }
+
     #[wasm_bindgen_test]
     fn test_snapshot_with_invalid_data() {
         let mut engine = Engine::new("default");
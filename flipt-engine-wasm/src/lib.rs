@@ -1,12 +1,17 @@
 use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
 use base64::Engine as _;
+use fliptevaluation::codec::{self, SnapshotFormat};
+use fliptevaluation::declarative;
 use fliptevaluation::error::Error;
+use fliptevaluation::hook::{self, Hooks};
 use fliptevaluation::models::flipt::Flag;
 use fliptevaluation::models::{snapshot, source};
+use fliptevaluation::signature::Verifier;
 use fliptevaluation::store::Store;
 use fliptevaluation::{
-    batch_evaluation, boolean_evaluation, variant_evaluation, BatchEvaluationResponse,
-    BooleanEvaluationResponse, EvaluationRequest, VariantEvaluationResponse,
+    batch_evaluation_with_options, boolean_evaluation, variant_evaluation, BatchEvaluationResponse,
+    BooleanEvaluationResponse, ContextList, EvaluationOptions, EvaluationRequest,
+    VariantEvaluationResponse,
 };
 use libc::c_void;
 use serde::{Deserialize, Serialize};
@@ -21,6 +26,36 @@ struct WASMEvaluationRequest {
     context: Option<Map<String, Value>>,
 }
 
+impl TryFrom<WASMEvaluationRequest> for EvaluationRequest {
+    type Error = WASMError;
+
+    fn try_from(req: WASMEvaluationRequest) -> Result<Self, WASMError> {
+        let mut context = HashMap::new();
+        let mut list_context = HashMap::new();
+        for (key, value) in req.context.unwrap_or_default() {
+            match value {
+                Value::String(s) => {
+                    context.insert(key, s);
+                }
+                Value::Null => continue,
+                Value::Array(_) => {
+                    list_context.insert(key, serde_json::from_value::<ContextList>(value)?);
+                }
+                other => {
+                    context.insert(key, other.to_string());
+                }
+            }
+        }
+
+        Ok(EvaluationRequest {
+            flag_key: req.flag_key,
+            entity_id: req.entity_id,
+            context,
+            list_context,
+        })
+    }
+}
+
 #[derive(Serialize)]
 struct WASMResponse<T>
 where
@@ -87,6 +122,8 @@ fn result_to_string<T: Serialize, E: std::error::Error>(result: Result<T, E>) ->
 pub struct Engine {
     namespace: String,
     store: snapshot::Snapshot,
+    hooks: Hooks,
+    verifier: Option<Verifier>,
 }
 
 impl Engine {
@@ -98,25 +135,93 @@ impl Engine {
         Ok(Self {
             namespace: namespace.to_string(),
             store,
+            hooks: Hooks::new(),
+            verifier: None,
         })
     }
 
+    /// Register hooks that are notified around every evaluation. Panics abort on WebAssembly, so
+    /// unlike in the native engine a panicking hook is not isolated and takes the engine down.
+    pub fn with_hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
     pub fn snapshot(&mut self, data: &str) -> Result<(), WASMError> {
+        self.signed_snapshot(data, None)
+    }
+
+    /// Replace the snapshot with a fetched document along with its base64-encoded signature.
+    /// Once a verifier is set, documents without a valid signature are rejected.
+    pub fn signed_snapshot(
+        &mut self,
+        data: &str,
+        signature: Option<&str>,
+    ) -> Result<(), WASMError> {
+        self.verify(data, signature)?;
         let doc: source::Document = serde_json::from_str(data).map_err(WASMError::InvalidJson)?;
         self.store = snapshot::Snapshot::build(doc);
         Ok(())
     }
 
+    /// Replace the snapshot with the engine's namespace in a Flipt declarative `features.yml`.
+    pub fn snapshot_yaml(&mut self, data: &str) -> Result<(), WASMError> {
+        self.signed_snapshot_yaml(data, None)
+    }
+
+    /// Replace the snapshot from a declarative `features.yml` along with its base64-encoded
+    /// signature. Once a verifier is set, documents without a valid signature are rejected.
+    pub fn signed_snapshot_yaml(
+        &mut self,
+        data: &str,
+        signature: Option<&str>,
+    ) -> Result<(), WASMError> {
+        self.verify(data, signature)?;
+        self.store = declarative::parse_snapshot(data, &self.namespace)?;
+        Ok(())
+    }
+
+    fn verify(&self, data: &str, signature: Option<&str>) -> Result<(), WASMError> {
+        match &self.verifier {
+            Some(verifier) => verifier
+                .verify(data.as_bytes(), signature)
+                .map_err(|e| WASMError::InvalidSnapshot(e.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Require seeded and fetched snapshots to be signed for the given verifier.
+    pub fn set_verifier(&mut self, verifier: Verifier) {
+        self.verifier = Some(verifier);
+    }
+
     pub fn seed_snapshot(&mut self, snapshot_b64: &str) -> Result<(), WASMError> {
+        self.seed_signed_snapshot(snapshot_b64, None)
+    }
+
+    /// Seed the engine from a snapshot along with its base64-encoded signature. The signature
+    /// is checked against the serialized snapshot bytes when a verifier is set, and the current
+    /// snapshot is kept if verification fails.
+    pub fn seed_signed_snapshot(
+        &mut self,
+        snapshot_b64: &str,
+        signature: Option<&str>,
+    ) -> Result<(), WASMError> {
         let decoded = BASE64_STANDARD
             .decode(snapshot_b64)
             .map_err(|e| WASMError::InvalidSnapshot(e.to_string()))?;
-        let snapshot: snapshot::Snapshot =
-            serde_json::from_slice(&decoded).map_err(WASMError::InvalidJson)?;
-        if snapshot.namespace.key != self.namespace {
+        if let Some(verifier) = &self.verifier {
+            verifier
+                .verify(&decoded, signature)
+                .map_err(|e| WASMError::InvalidSnapshot(e.to_string()))?;
+        }
+        let snapshot =
+            codec::decode(&decoded).map_err(|e| WASMError::InvalidSnapshot(e.to_string()))?;
+        if snapshot.namespace().key != self.namespace {
             return Err(WASMError::InvalidSnapshot(format!(
                 "snapshot namespace '{}' does not match engine namespace '{}'",
-                snapshot.namespace.key, self.namespace
+                snapshot.namespace().key,
+                self.namespace
             )));
         }
         self.store = snapshot;
@@ -124,29 +229,49 @@ impl Engine {
     }
 
     pub fn get_snapshot(&self) -> Result<String, WASMError> {
-        let json = serde_json::to_string(&self.store).map_err(WASMError::InvalidJson)?;
-        Ok(BASE64_STANDARD.encode(json))
+        self.get_snapshot_with_format(&SnapshotFormat::default())
+    }
+
+    pub fn get_snapshot_with_format(&self, format: &SnapshotFormat) -> Result<String, WASMError> {
+        let bytes = codec::encode(&self.store, format)?;
+        Ok(BASE64_STANDARD.encode(bytes))
+    }
+
+    /// Content digest of the current snapshot.
+    pub fn get_snapshot_digest(&self) -> String {
+        self.store.digest().to_string()
     }
 
     pub fn evaluate_boolean(
         &self,
         request: &EvaluationRequest,
     ) -> Result<BooleanEvaluationResponse, Error> {
-        boolean_evaluation(&self.store, &self.namespace, request)
+        hook::evaluate(&self.hooks, &self.namespace, request, || {
+            boolean_evaluation(&self.store, &self.namespace, request)
+        })
     }
 
     pub fn evaluate_variant(
         &self,
         request: &EvaluationRequest,
     ) -> Result<VariantEvaluationResponse, Error> {
-        variant_evaluation(&self.store, &self.namespace, request)
+        hook::evaluate(&self.hooks, &self.namespace, request, || {
+            variant_evaluation(&self.store, &self.namespace, request)
+        })
     }
 
     pub fn evaluate_batch(
         &self,
         request: Vec<fliptevaluation::EvaluationRequest>,
     ) -> Result<BatchEvaluationResponse, Error> {
-        batch_evaluation(&self.store, &self.namespace, request)
+        hook::evaluate_batch(&self.hooks, &self.namespace, &request, |request| {
+            batch_evaluation_with_options(
+                &self.store,
+                &self.namespace,
+                request,
+                &EvaluationOptions::default(),
+            )
+        })
     }
 
     pub fn list_flags(&self) -> Result<Option<Vec<Flag>>, Error> {
@@ -440,6 +565,138 @@ pub unsafe extern "C" fn snapshot(
     })
 }
 
+/// # Safety
+///
+/// This function will take in a pointer to the engine and a declarative `features.yml` document.
+#[no_mangle]
+pub unsafe extern "C" fn snapshot_yaml(
+    engine_ptr: *mut c_void,
+    yaml_ptr: *const u8,
+    yaml_len: usize,
+) -> u64 {
+    let result = std::panic::catch_unwind(|| {
+        let e = match get_engine_mut(engine_ptr) {
+            Ok(e) => e,
+            Err(e) => return result_to_ptr::<(), _>(Err(e)),
+        };
+
+        if yaml_ptr.is_null() || yaml_len == 0 {
+            return result_to_ptr::<(), _>(Err(WASMError::NullPointer));
+        }
+
+        let yaml = match std::str::from_utf8(std::slice::from_raw_parts(yaml_ptr, yaml_len)) {
+            Ok(s) => s,
+            Err(_) => {
+                return result_to_ptr::<(), _>(Err(WASMError::InvalidSnapshot(
+                    "Invalid UTF-8 in snapshot".to_string(),
+                )))
+            }
+        };
+
+        result_to_ptr(e.snapshot_yaml(yaml))
+    });
+
+    result.unwrap_or_else(|_| unsafe {
+        result_to_ptr::<(), _>(Err(WASMError::InternalError(
+            "panic in snapshot_yaml".to_string(),
+        )))
+    })
+}
+
+/// # Safety
+///
+/// This function will take in a pointer to the engine, a snapshot document and its base64-encoded signature.
+#[no_mangle]
+pub unsafe extern "C" fn signed_snapshot(
+    engine_ptr: *mut c_void,
+    snapshot_ptr: *const u8,
+    snapshot_len: usize,
+    signature_ptr: *const u8,
+    signature_len: usize,
+) -> u64 {
+    let result = std::panic::catch_unwind(|| {
+        let e = match get_engine_mut(engine_ptr) {
+            Ok(e) => e,
+            Err(e) => return result_to_ptr::<(), _>(Err(e)),
+        };
+
+        if snapshot_ptr.is_null()
+            || snapshot_len == 0
+            || signature_ptr.is_null()
+            || signature_len == 0
+        {
+            return result_to_ptr::<(), _>(Err(WASMError::NullPointer));
+        }
+
+        let (snapshot, signature) = match (
+            std::str::from_utf8(std::slice::from_raw_parts(snapshot_ptr, snapshot_len)),
+            std::str::from_utf8(std::slice::from_raw_parts(signature_ptr, signature_len)),
+        ) {
+            (Ok(snapshot), Ok(signature)) => (snapshot, signature),
+            _ => {
+                return result_to_ptr::<(), _>(Err(WASMError::InvalidSnapshot(
+                    "Invalid UTF-8 in snapshot or signature".to_string(),
+                )))
+            }
+        };
+
+        result_to_ptr(e.signed_snapshot(snapshot, Some(signature)))
+    });
+
+    result.unwrap_or_else(|_| unsafe {
+        result_to_ptr::<(), _>(Err(WASMError::InternalError(
+            "panic in signed_snapshot".to_string(),
+        )))
+    })
+}
+
+/// # Safety
+///
+/// This function will take in a pointer to the engine, a declarative `features.yml` document and its base64-encoded signature.
+#[no_mangle]
+pub unsafe extern "C" fn signed_snapshot_yaml(
+    engine_ptr: *mut c_void,
+    snapshot_ptr: *const u8,
+    snapshot_len: usize,
+    signature_ptr: *const u8,
+    signature_len: usize,
+) -> u64 {
+    let result = std::panic::catch_unwind(|| {
+        let e = match get_engine_mut(engine_ptr) {
+            Ok(e) => e,
+            Err(e) => return result_to_ptr::<(), _>(Err(e)),
+        };
+
+        if snapshot_ptr.is_null()
+            || snapshot_len == 0
+            || signature_ptr.is_null()
+            || signature_len == 0
+        {
+            return result_to_ptr::<(), _>(Err(WASMError::NullPointer));
+        }
+
+        let (snapshot, signature) = match (
+            std::str::from_utf8(std::slice::from_raw_parts(snapshot_ptr, snapshot_len)),
+            std::str::from_utf8(std::slice::from_raw_parts(signature_ptr, signature_len)),
+        ) {
+            (Ok(snapshot), Ok(signature)) => (snapshot, signature),
+            _ => {
+                return result_to_ptr::<(), _>(Err(WASMError::InvalidSnapshot(
+                    "Invalid UTF-8 in snapshot or signature".to_string(),
+                )))
+            }
+        };
+
+        result_to_ptr(e.signed_snapshot_yaml(snapshot, Some(signature)))
+    });
+
+    result.unwrap_or_else(|_| unsafe {
+        result_to_ptr::<(), _>(Err(WASMError::InternalError(
+            "panic in signed_snapshot_yaml".to_string(),
+        )))
+    })
+}
+
 /// # Safety
 ///
 /// Seed the engine from a base64-encoded serialized snapshot.
@@ -479,6 +736,91 @@ pub unsafe extern "C" fn seed_snapshot(
     })
 }
 
+/// # Safety
+///
+/// Require seeded snapshots to be signed for the given JSON verifier, e.g. `{"ed25519":"<public key>"}`.
+#[no_mangle]
+pub unsafe extern "C" fn set_verifier(
+    engine_ptr: *mut c_void,
+    verifier_ptr: *const u8,
+    verifier_len: usize,
+) -> u64 {
+    let result = std::panic::catch_unwind(|| {
+        let e = match get_engine_mut(engine_ptr) {
+            Ok(e) => e,
+            Err(e) => return result_to_ptr::<(), _>(Err(e)),
+        };
+
+        if verifier_ptr.is_null() || verifier_len == 0 {
+            return result_to_ptr::<(), _>(Err(WASMError::NullPointer));
+        }
+
+        match serde_json::from_slice::<Verifier>(std::slice::from_raw_parts(
+            verifier_ptr,
+            verifier_len,
+        )) {
+            Ok(verifier) => {
+                e.set_verifier(verifier);
+                result_to_ptr::<(), WASMError>(Ok(()))
+            }
+            Err(e) => result_to_ptr::<(), _>(Err(WASMError::InvalidJson(e))),
+        }
+    });
+
+    result.unwrap_or_else(|_| unsafe {
+        result_to_ptr::<(), _>(Err(WASMError::InternalError(
+            "panic in set_verifier".to_string(),
+        )))
+    })
+}
+
+/// # Safety
+///
+/// Seed the engine from a base64-encoded serialized snapshot and its base64-encoded signature.
+#[no_mangle]
+pub unsafe extern "C" fn seed_signed_snapshot(
+    engine_ptr: *mut c_void,
+    snapshot_ptr: *const u8,
+    snapshot_len: usize,
+    signature_ptr: *const u8,
+    signature_len: usize,
+) -> u64 {
+    let result = std::panic::catch_unwind(|| {
+        let e = match get_engine_mut(engine_ptr) {
+            Ok(e) => e,
+            Err(e) => return result_to_ptr::<(), _>(Err(e)),
+        };
+
+        if snapshot_ptr.is_null()
+            || snapshot_len == 0
+            || signature_ptr.is_null()
+            || signature_len == 0
+        {
+            return result_to_ptr::<(), _>(Err(WASMError::NullPointer));
+        }
+
+        let (snapshot, signature) = match (
+            std::str::from_utf8(std::slice::from_raw_parts(snapshot_ptr, snapshot_len)),
+            std::str::from_utf8(std::slice::from_raw_parts(signature_ptr, signature_len)),
+        ) {
+            (Ok(snapshot), Ok(signature)) => (snapshot, signature),
+            _ => {
+                return result_to_ptr::<(), _>(Err(WASMError::InvalidSnapshot(
+                    "Invalid UTF-8 in snapshot or signature".to_string(),
+                )))
+            }
+        };
+
+        result_to_ptr(e.seed_signed_snapshot(snapshot, Some(signature)))
+    });
+
+    result.unwrap_or_else(|_| unsafe {
+        result_to_ptr::<(), _>(Err(WASMError::InternalError(
+            "panic in seed_signed_snapshot".to_string(),
+        )))
+    })
+}
+
 /// # Safety
 ///
 /// Return a base64-encoded serialized snapshot.
@@ -500,6 +842,63 @@ pub unsafe extern "C" fn get_snapshot(engine_ptr: *mut c_void) -> u64 {
     })
 }
 
+/// # Safety
+///
+/// Return the content digest of the current snapshot.
+#[no_mangle]
+pub unsafe extern "C" fn get_snapshot_digest(engine_ptr: *mut c_void) -> u64 {
+    let result = std::panic::catch_unwind(|| {
+        let e = match get_engine(engine_ptr) {
+            Ok(e) => e,
+            Err(e) => return result_to_ptr::<String, _>(Err(e)),
+        };
+
+        result_to_ptr::<_, WASMError>(Ok(e.get_snapshot_digest()))
+    });
+
+    result.unwrap_or_else(|_| unsafe {
+        result_to_ptr::<String, _>(Err(WASMError::InternalError(
+            "panic in get_snapshot_digest".to_string(),
+        )))
+    })
+}
+
+/// # Safety
+///
+/// Return a base64-encoded serialized snapshot in the given JSON snapshot format.
+#[no_mangle]
+pub unsafe extern "C" fn get_snapshot_with_format(
+    engine_ptr: *mut c_void,
+    format_ptr: *const u8,
+    format_len: usize,
+) -> u64 {
+    let result = std::panic::catch_unwind(|| {
+        let e = match get_engine(engine_ptr) {
+            Ok(e) => e,
+            Err(e) => return result_to_ptr::<String, _>(Err(e)),
+        };
+
+        if format_ptr.is_null() || format_len == 0 {
+            return result_to_ptr::<String, _>(Err(WASMError::NullPointer));
+        }
+
+        let format = match serde_json::from_slice::<SnapshotFormat>(std::slice::from_raw_parts(
+            format_ptr, format_len,
+        )) {
+            Ok(format) => format,
+            Err(e) => return result_to_ptr::<String, _>(Err(WASMError::InvalidJson(e))),
+        };
+
+        result_to_ptr(e.get_snapshot_with_format(&format))
+    });
+
+    result.unwrap_or_else(|_| unsafe {
+        result_to_ptr::<String, _>(Err(WASMError::InternalError(
+            "panic in get_snapshot_with_format".to_string(),
+        )))
+    })
+}
+
 /// # Safety
 ///
 /// This function will free the memory occupied by the engine.
@@ -549,57 +948,16 @@ pub unsafe extern "C" fn deallocate(ptr: *mut c_void, size: usize) {
 }
 
 fn get_evaluation_request(evaluation_request: &str) -> Result<EvaluationRequest, WASMError> {
-    let client_eval_request: WASMEvaluationRequest =
-        serde_json::from_str(evaluation_request).map_err(WASMError::InvalidJson)?;
-
-    let mut context_map: HashMap<String, String> = HashMap::new();
-    if let Some(context_value) = client_eval_request.context {
-        for (key, value) in context_value {
-            let str_val = match value {
-                serde_json::Value::String(s) => s,
-                serde_json::Value::Null => continue,
-                other => other.to_string(),
-            };
-            context_map.insert(key, str_val);
-        }
-    }
-
-    Ok(EvaluationRequest {
-        flag_key: client_eval_request.flag_key,
-        entity_id: client_eval_request.entity_id,
-        context: context_map,
-    })
+    serde_json::from_str::<WASMEvaluationRequest>(evaluation_request)?.try_into()
 }
 
 fn get_batch_evaluation_request(
     batch_evaluation_request: &str,
 ) -> Result<Vec<EvaluationRequest>, WASMError> {
-    let batch_eval_request: Vec<WASMEvaluationRequest> =
-        serde_json::from_str(batch_evaluation_request).map_err(WASMError::InvalidJson)?;
-
-    let mut evaluation_requests: Vec<EvaluationRequest> =
-        Vec::with_capacity(batch_eval_request.len());
-    for req in batch_eval_request {
-        let mut context_map: HashMap<String, String> = HashMap::new();
-        if let Some(context_value) = req.context {
-            for (key, value) in context_value {
-                let str_val = match value {
-                    serde_json::Value::String(s) => s,
-                    serde_json::Value::Null => continue,
-                    other => other.to_string(),
-                };
-                context_map.insert(key, str_val);
-            }
-        }
-
-        evaluation_requests.push(EvaluationRequest {
-            flag_key: req.flag_key,
-            entity_id: req.entity_id,
-            context: context_map,
-        });
-    }
-
-    Ok(evaluation_requests)
+    serde_json::from_str::<Vec<WASMEvaluationRequest>>(batch_evaluation_request)?
+        .into_iter()
+        .map(EvaluationRequest::try_from)
+        .collect()
 }
 
 /// Returns a pointer and size pair for the given string in a way compatible
@@ -638,6 +996,7 @@ mod tests {
                 flag_key: "flag1".into(),
                 entity_id: "entity".into(),
                 context: HashMap::new(),
+                list_context: HashMap::new(),
             })
             .expect("variant evaluation");
         assert_eq!(result.flag_key, "flag1");
@@ -647,6 +1006,7 @@ mod tests {
                 flag_key: "flag_boolean".into(),
                 entity_id: "entity".into(),
                 context: HashMap::new(),
+                list_context: HashMap::new(),
             })
             .expect("boolean evaluation");
         assert!(result.enabled);
@@ -657,11 +1017,13 @@ mod tests {
                     flag_key: "flag1".into(),
                     entity_id: "entity".into(),
                     context: HashMap::new(),
+                    list_context: HashMap::new(),
                 },
                 EvaluationRequest {
                     flag_key: "flag_boolean".into(),
                     entity_id: "entity".into(),
                     context: HashMap::new(),
+                    list_context: HashMap::new(),
                 },
             ])
             .expect("batch evaluation");
@@ -677,6 +1039,169 @@ mod tests {
         assert!(!snapshot.is_empty());
     }
 
+    #[test]
+    fn test_get_evaluation_request_list_context() {
+        let request = get_evaluation_request(
+            r#"{"flag_key":"flag1","entity_id":"entity","context":{"plan":"pro","age":42,"roles":["admin"],"ids":[1,2]}}"#,
+        )
+        .expect("evaluation request");
+
+        assert_eq!(request.context.get("plan").unwrap(), "pro");
+        assert_eq!(request.context.get("age").unwrap(), "42");
+        assert_eq!(
+            request.list_context.get("roles"),
+            Some(&ContextList::Strings(vec!["admin".into()]))
+        );
+        assert_eq!(
+            request.list_context.get("ids"),
+            Some(&ContextList::Numbers(vec![1, 2]))
+        );
+
+        assert!(get_batch_evaluation_request(
+            r#"[{"flag_key":"flag1","entity_id":"entity","context":{"roles":["admin",1]}}]"#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_seed_snapshot_with_format() {
+        let snapshot = r#"{"namespace":{"key":"default"},"flags":[{"key":"flag1","name":"flag1","enabled":true,"type":"VARIANT_FLAG_TYPE"}]}"#;
+        let engine = Engine::new("default", snapshot).expect("engine");
+
+        let encoded = engine
+            .get_snapshot_with_format(&SnapshotFormat {
+                encoding: codec::SnapshotEncoding::MessagePack,
+                compression: codec::SnapshotCompression::Deflate,
+            })
+            .expect("get snapshot");
+
+        let mut seeded = Engine::new("default", r#"{"namespace":{"key":"default"},"flags":[]}"#)
+            .expect("engine");
+        seeded.seed_snapshot(&encoded).expect("seed snapshot");
+        assert_eq!(seeded.store, engine.store);
+        assert_eq!(seeded.get_snapshot_digest(), engine.get_snapshot_digest());
+
+        // plain base64 JSON keeps working
+        seeded
+            .seed_snapshot(&encoded_snapshot("default"))
+            .expect("seed json snapshot");
+        assert!(seeded.store.namespace().flags.is_empty());
+    }
+
+    #[test]
+    fn test_seed_signed_snapshot() {
+        use hmac::{Hmac, Mac};
+
+        let source = Engine::new(
+            "default",
+            r#"{"namespace":{"key":"default"},"flags":[{"key":"flag1","name":"flag1","enabled":true,"type":"VARIANT_FLAG_TYPE"}]}"#,
+        )
+        .expect("engine");
+        let encoded = source.get_snapshot().expect("get snapshot");
+
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(&BASE64_STANDARD.decode(&encoded).unwrap());
+        let signature = BASE64_STANDARD.encode(mac.finalize().into_bytes());
+
+        let mut engine = Engine::new("default", r#"{"namespace":{"key":"default"},"flags":[]}"#)
+            .expect("engine");
+        engine.set_verifier(Verifier::Hmac("secret".into()));
+
+        let err = engine.seed_snapshot(&encoded).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid snapshot: signature verification failed: payload is not signed"
+        );
+        assert!(engine
+            .seed_signed_snapshot(&encoded, Some("c2lnbmF0dXJl"))
+            .is_err());
+        assert!(engine.store.namespace().flags.is_empty());
+
+        engine
+            .seed_signed_snapshot(&encoded, Some(&signature))
+            .expect("seed signed snapshot");
+        assert_eq!(engine.store, source.store);
+    }
+
+    #[test]
+    fn test_snapshot_yaml() {
+        let yaml = r#"
+namespace: default
+flags:
+  - key: flag1
+    type: BOOLEAN_FLAG_TYPE
+    enabled: false
+    rollouts:
+      - segment:
+          key: internal
+          value: true
+segments:
+  - key: internal
+    constraints:
+      - type: STRING_COMPARISON_TYPE
+        property: team
+        operator: eq
+        value: core
+"#;
+        let mut engine = Engine::new("default", r#"{"namespace":{"key":"default"},"flags":[]}"#)
+            .expect("engine");
+        engine.snapshot_yaml(yaml).expect("snapshot yaml");
+
+        let result = engine
+            .evaluate_boolean(&EvaluationRequest {
+                flag_key: "flag1".into(),
+                entity_id: "entity".into(),
+                context: HashMap::from([("team".into(), "core".into())]),
+                list_context: HashMap::new(),
+            })
+            .expect("boolean evaluation");
+        assert!(result.enabled);
+
+        let err = engine.snapshot_yaml("namespace: other").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error building snapshot: invalid snapshot: namespace default is not defined"
+        );
+        assert_eq!(engine.list_flags().unwrap().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_signed_snapshot() {
+        use hmac::{Hmac, Mac};
+
+        let sign = |data: &str| {
+            let mut mac = Hmac::<sha2::Sha256>::new_from_slice(b"secret").unwrap();
+            mac.update(data.as_bytes());
+            BASE64_STANDARD.encode(mac.finalize().into_bytes())
+        };
+
+        let json = r#"{"namespace":{"key":"default"},"flags":[{"key":"flag1","name":"flag1","enabled":true,"type":"VARIANT_FLAG_TYPE"}]}"#;
+        let yaml = "flags:\n  - key: flag1\n    enabled: true\n";
+
+        let mut engine = Engine::new("default", r#"{"namespace":{"key":"default"},"flags":[]}"#)
+            .expect("engine");
+        engine.set_verifier(Verifier::Hmac("secret".into()));
+
+        assert_eq!(
+            engine.snapshot(json).unwrap_err().to_string(),
+            "Invalid snapshot: signature verification failed: payload is not signed"
+        );
+        assert!(engine.snapshot_yaml(yaml).is_err());
+        assert!(engine.signed_snapshot(json, Some(&sign(yaml))).is_err());
+        assert!(engine.store.namespace().flags.is_empty());
+
+        engine
+            .signed_snapshot(json, Some(&sign(json)))
+            .expect("signed snapshot");
+        assert_eq!(engine.list_flags().unwrap().unwrap().len(), 1);
+
+        assert!(engine.snapshot_yaml("flags: []").is_err());
+        engine
+            .signed_snapshot_yaml(yaml, Some(&sign(yaml)))
+            .expect("signed snapshot yaml");
+        assert_eq!(engine.list_flags().unwrap().unwrap().len(), 1);
+    }
+
     #[test]
     fn test_snapshot_updates_flags() {
         let flags_one = r#"{"namespace":{"key":"default"},"flags":[{"key":"flag1","name":"flag1","enabled":true,"type":"VARIANT_FLAG_TYPE"}]}"#;
@@ -706,6 +1231,40 @@ mod tests {
         assert!(flags.is_empty());
     }
 
+    #[test]
+    fn test_hooks_receive_evaluations() {
+        use fliptevaluation::hook::{EvaluationHook, HookResponse};
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Default)]
+        struct RecordingHook {
+            flags: Mutex<Vec<String>>,
+        }
+
+        impl EvaluationHook for RecordingHook {
+            fn after(&self, _: &str, request: &EvaluationRequest, _: &HookResponse<'_>) {
+                self.flags.lock().unwrap().push(request.flag_key.clone());
+            }
+        }
+
+        let hook = Arc::new(RecordingHook::default());
+        let snapshot = r#"{"namespace":{"key":"default"},"flags":[{"key":"flag_boolean","name":"flag_boolean","enabled":true,"type":"BOOLEAN_FLAG_TYPE"}]}"#;
+        let engine = Engine::new("default", snapshot)
+            .expect("engine")
+            .with_hooks(vec![hook.clone()]);
+
+        engine
+            .evaluate_boolean(&EvaluationRequest {
+                flag_key: "flag_boolean".into(),
+                entity_id: "entity".into(),
+                context: HashMap::new(),
+                list_context: HashMap::new(),
+            })
+            .expect("boolean evaluation");
+
+        assert_eq!(*hook.flags.lock().unwrap(), vec!["flag_boolean"]);
+    }
+
     #[test]
     fn seed_snapshot_rejects_namespace_mismatch() {
         let mut engine = Engine::new("default", r#"{"namespace":{"key":"default"},"flags":[]}"#)
@@ -0,0 +1,74 @@
+use chrono::{DateTime, Utc};
+
+/// Source of the current time used when evaluating relative and time-of-day constraints.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Clock backed by the system time.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Clock that always returns the same instant, useful for deterministic evaluation.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+/// Measures how long an evaluation took.
+///
+/// Without `std` there is no monotonic timer, so the duration is read from the
+/// evaluation clock instead.
+pub(crate) struct Stopwatch {
+    #[cfg(feature = "std")]
+    start: web_time::Instant,
+    #[cfg(not(feature = "std"))]
+    start: DateTime<Utc>,
+}
+
+impl Stopwatch {
+    #[cfg(feature = "std")]
+    pub(crate) fn start(_clock: &dyn Clock) -> Self {
+        Self {
+            start: web_time::Instant::now(),
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub(crate) fn start(clock: &dyn Clock) -> Self {
+        Self { start: clock.now() }
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn elapsed_millis(&self, _clock: &dyn Clock) -> f64 {
+        self.start.elapsed().as_millis() as f64
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub(crate) fn elapsed_millis(&self, clock: &dyn Clock) -> f64 {
+        (clock.now() - self.start).num_milliseconds().max(0) as f64
+    }
+}
+
+/// Timestamp given to default-constructed responses.
+#[cfg(feature = "std")]
+pub(crate) fn default_timestamp() -> DateTime<Utc> {
+    Utc::now()
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn default_timestamp() -> DateTime<Utc> {
+    DateTime::<Utc>::UNIX_EPOCH
+}
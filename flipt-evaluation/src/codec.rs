@@ -0,0 +1,331 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::models::snapshot::{Snapshot, SnapshotEnvelope};
+
+/// Marks a snapshot encoded with a header, as opposed to plain JSON.
+const MAGIC: &[u8; 4] = b"FLPS";
+const FORMAT_VERSION: u8 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 3;
+
+/// Largest size a compressed snapshot may inflate to, far above any real snapshot. Snapshots
+/// are read from CDNs and seeds, so without a bound a small payload could inflate until the
+/// allocation aborts the process.
+pub const MAX_DECOMPRESSED_LEN: usize = 64 * 1024 * 1024;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotEncoding {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotCompression {
+    #[default]
+    None,
+    Deflate,
+}
+
+/// How a snapshot is serialized when exported.
+///
+/// The default is plain JSON without a header, which is what older engines export and
+/// accept. Every other format is prefixed with a header carrying the format version,
+/// encoding and compression, so [`decode`] can read any of them.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SnapshotFormat {
+    #[serde(default)]
+    pub encoding: SnapshotEncoding,
+    #[serde(default)]
+    pub compression: SnapshotCompression,
+}
+
+impl SnapshotEncoding {
+    fn tag(self) -> u8 {
+        match self {
+            SnapshotEncoding::Json => 0,
+            SnapshotEncoding::MessagePack => 1,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Result<Self, Error> {
+        match tag {
+            0 => Ok(SnapshotEncoding::Json),
+            1 => Ok(SnapshotEncoding::MessagePack),
+            _ => Err(Error::InvalidSnapshot(format!("unknown encoding {tag}"))),
+        }
+    }
+}
+
+impl SnapshotCompression {
+    fn tag(self) -> u8 {
+        match self {
+            SnapshotCompression::None => 0,
+            SnapshotCompression::Deflate => 1,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Result<Self, Error> {
+        match tag {
+            0 => Ok(SnapshotCompression::None),
+            1 => Ok(SnapshotCompression::Deflate),
+            _ => Err(Error::InvalidSnapshot(format!("unknown compression {tag}"))),
+        }
+    }
+}
+
+pub fn encode(snapshot: &Snapshot, format: &SnapshotFormat) -> Result<Vec<u8>, Error> {
+    let payload = match format.encoding {
+        SnapshotEncoding::Json => {
+            serde_json::to_vec(snapshot).map_err(|e| Error::InvalidJSON(e.to_string()))?
+        }
+        // field names are kept so optional fields can be skipped
+        SnapshotEncoding::MessagePack => {
+            rmp_serde::to_vec_named(snapshot).map_err(|e| Error::InvalidSnapshot(e.to_string()))?
+        }
+    };
+
+    if *format == SnapshotFormat::default() {
+        return Ok(payload);
+    }
+
+    let payload = match format.compression {
+        SnapshotCompression::None => payload,
+        SnapshotCompression::Deflate => miniz_oxide::deflate::compress_to_vec(&payload, 6),
+    };
+
+    let mut encoded = Vec::with_capacity(HEADER_LEN + payload.len());
+    encoded.extend_from_slice(MAGIC);
+    encoded.push(FORMAT_VERSION);
+    encoded.push(format.encoding.tag());
+    encoded.push(format.compression.tag());
+    encoded.extend_from_slice(&payload);
+
+    Ok(encoded)
+}
+
+/// Decodes a snapshot in any format produced by [`encode`], including plain JSON.
+pub fn decode(bytes: &[u8]) -> Result<Snapshot, Error> {
+    let Some(header) = bytes.strip_prefix(MAGIC) else {
+        return decode_json(bytes);
+    };
+
+    let [version, encoding, compression, ..] = *header else {
+        return Err(Error::InvalidSnapshot("truncated header".into()));
+    };
+
+    if version != FORMAT_VERSION {
+        return Err(Error::InvalidSnapshot(format!(
+            "unsupported format version {version}"
+        )));
+    }
+
+    let encoding = SnapshotEncoding::from_tag(encoding)?;
+    let compression = SnapshotCompression::from_tag(compression)?;
+    let payload = &bytes[HEADER_LEN..];
+
+    let decompressed;
+    let payload = match compression {
+        SnapshotCompression::None => payload,
+        SnapshotCompression::Deflate => {
+            decompressed = inflate(payload, MAX_DECOMPRESSED_LEN)?;
+            &decompressed
+        }
+    };
+
+    match encoding {
+        SnapshotEncoding::Json => decode_json(payload),
+        SnapshotEncoding::MessagePack => rmp_serde::from_slice::<Snapshot>(payload)
+            .map_err(|e| Error::InvalidSnapshot(e.to_string())),
+    }
+}
+
+fn inflate(payload: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+    use miniz_oxide::inflate::TINFLStatus;
+
+    miniz_oxide::inflate::decompress_to_vec_with_limit(payload, limit).map_err(|e| match e.status {
+        TINFLStatus::HasMoreOutput => {
+            Error::InvalidSnapshot(format!("decompressed snapshot exceeds {limit} bytes"))
+        }
+        _ => Error::InvalidSnapshot(format!("failed to decompress: {e}")),
+    })
+}
+
+fn decode_json(bytes: &[u8]) -> Result<Snapshot, Error> {
+    serde_json::from_slice::<SnapshotEnvelope>(bytes)
+        .map_err(|e| Error::InvalidJSON(e.to_string()))?
+        .migrate()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::snapshot::SNAPSHOT_VERSION;
+    use crate::models::source;
+
+    fn snapshot() -> Snapshot {
+        let document: source::Document =
+            serde_json::from_str(include_str!("testdata/state.json")).unwrap();
+        Snapshot::build(document)
+    }
+
+    #[test]
+    fn test_round_trip_all_formats() {
+        let snapshot = snapshot();
+
+        for encoding in [SnapshotEncoding::Json, SnapshotEncoding::MessagePack] {
+            for compression in [SnapshotCompression::None, SnapshotCompression::Deflate] {
+                let format = SnapshotFormat {
+                    encoding,
+                    compression,
+                };
+                let encoded = encode(&snapshot, &format).unwrap();
+                assert_eq!(decode(&encoded).unwrap(), snapshot, "{format:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_migrates_older_versions() {
+        // fields are serialized in declaration order, so the version precedes the namespace
+        #[derive(serde::Serialize)]
+        struct Envelope {
+            version: u32,
+            namespace: serde_json::Value,
+        }
+
+        let v1 = Envelope {
+            version: 1,
+            namespace: serde_json::json!({
+                "key": "default",
+                "flags": {},
+                "eval_rules": {
+                    "flag1": [{
+                        "id": "flag1-1",
+                        "flag_key": "flag1",
+                        "segments": {
+                            "segment1": {
+                                "segment_key": "segment1",
+                                "match_type": "ANY_SEGMENT_MATCH_TYPE",
+                                "constraints": []
+                            }
+                        },
+                        "rank": 1,
+                        "segment_operator": "OR_SEGMENT_OPERATOR"
+                    }]
+                },
+                "eval_rollouts": {},
+                "eval_distributions": {}
+            }),
+        };
+
+        let message_pack = |payload: Vec<u8>| {
+            let mut encoded = MAGIC.to_vec();
+            encoded.extend([
+                FORMAT_VERSION,
+                SnapshotEncoding::MessagePack.tag(),
+                SnapshotCompression::None.tag(),
+            ]);
+            encoded.extend(payload);
+            encoded
+        };
+
+        for encoded in [
+            serde_json::to_vec(&v1).unwrap(),
+            message_pack(rmp_serde::to_vec_named(&v1).unwrap()),
+            message_pack(rmp_serde::to_vec(&v1).unwrap()),
+        ] {
+            let snapshot = decode(&encoded).unwrap();
+            assert_eq!(snapshot.version, SNAPSHOT_VERSION);
+            assert_eq!(snapshot.namespace().segments.len(), 1);
+        }
+
+        let newer = Envelope {
+            version: SNAPSHOT_VERSION + 1,
+            namespace: serde_json::json!({}),
+        };
+        for encoded in [
+            serde_json::to_vec(&newer).unwrap(),
+            message_pack(rmp_serde::to_vec_named(&newer).unwrap()),
+        ] {
+            assert!(matches!(decode(&encoded), Err(Error::InvalidSnapshot(_))));
+        }
+    }
+
+    #[test]
+    fn test_default_format_is_plain_json() {
+        let snapshot = snapshot();
+        let encoded = encode(&snapshot, &SnapshotFormat::default()).unwrap();
+
+        assert_eq!(encoded, serde_json::to_vec(&snapshot).unwrap());
+        assert_eq!(decode(&encoded).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn test_message_pack_is_smaller() {
+        let snapshot = snapshot();
+        let json = encode(&snapshot, &SnapshotFormat::default()).unwrap();
+        let compact = encode(
+            &snapshot,
+            &SnapshotFormat {
+                encoding: SnapshotEncoding::MessagePack,
+                compression: SnapshotCompression::Deflate,
+            },
+        )
+        .unwrap();
+
+        assert!(compact.starts_with(MAGIC));
+        assert!(compact.len() < json.len());
+    }
+
+    #[test]
+    fn test_decode_rejects_oversized_payload() {
+        let mut encoded = MAGIC.to_vec();
+        encoded.extend_from_slice(&[
+            FORMAT_VERSION,
+            SnapshotEncoding::Json.tag(),
+            SnapshotCompression::Deflate.tag(),
+        ]);
+        encoded.extend(miniz_oxide::deflate::compress_to_vec(
+            &vec![b' '; MAX_DECOMPRESSED_LEN + 1],
+            1,
+        ));
+        // a few hundred kilobytes that would inflate past the limit
+        assert!(encoded.len() < 2 * 1024 * 1024);
+
+        assert_eq!(
+            decode(&encoded),
+            Err(Error::InvalidSnapshot(format!(
+                "decompressed snapshot exceeds {MAX_DECOMPRESSED_LEN} bytes"
+            )))
+        );
+    }
+
+    #[test]
+    fn test_decode_rejects_unknown_header() {
+        let mut encoded = encode(
+            &snapshot(),
+            &SnapshotFormat {
+                encoding: SnapshotEncoding::MessagePack,
+                compression: SnapshotCompression::None,
+            },
+        )
+        .unwrap();
+
+        encoded[MAGIC.len()] = FORMAT_VERSION + 1;
+        assert_eq!(
+            decode(&encoded),
+            Err(Error::InvalidSnapshot(format!(
+                "unsupported format version {}",
+                FORMAT_VERSION + 1
+            )))
+        );
+
+        assert_eq!(
+            decode(b"FLPS"),
+            Err(Error::InvalidSnapshot("truncated header".into()))
+        );
+    }
+}
@@ -0,0 +1,588 @@
+//! Flipt's declarative `features.yml` format.
+//!
+//! Declarative documents define the segments of a namespace once and reference them by key
+//! from rules and rollouts. Parsing resolves those references into the [`source::Document`]
+//! served by Flipt's snapshot endpoint, so a parsed file evaluates exactly like a fetched one.
+
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::error::Error;
+use crate::models::{flipt, snapshot, source};
+use crate::HashMap;
+
+const DEFAULT_NAMESPACE: &str = "default";
+
+#[derive(Deserialize, Default)]
+struct Document {
+    #[serde(default)]
+    namespace: Option<NamespaceEmbed>,
+    #[serde(default)]
+    flags: Vec<Flag>,
+    #[serde(default)]
+    segments: Vec<Segment>,
+}
+
+/// A namespace given either by key or in full.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NamespaceEmbed {
+    Key(String),
+    Namespace { key: String, name: Option<String> },
+}
+
+#[derive(Deserialize)]
+struct Flag {
+    key: String,
+    name: Option<String>,
+    #[serde(rename = "type")]
+    flag_type: Option<flipt::FlagType>,
+    description: Option<String>,
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default)]
+    variants: Vec<Variant>,
+    #[serde(default)]
+    rules: Vec<Rule>,
+    #[serde(default)]
+    rollouts: Vec<Rollout>,
+}
+
+#[derive(Deserialize)]
+struct Variant {
+    key: String,
+    #[serde(default)]
+    default: bool,
+    attachment: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct Rule {
+    segment: Option<SegmentEmbed>,
+    #[serde(default)]
+    distributions: Vec<Distribution>,
+}
+
+/// The segments of a rule, either a single key or several keys joined by an operator.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SegmentEmbed {
+    Key(String),
+    Keys {
+        keys: Vec<String>,
+        #[serde(default)]
+        operator: flipt::SegmentOperator,
+    },
+}
+
+#[derive(Deserialize)]
+struct Distribution {
+    variant: String,
+    rollout: f32,
+}
+
+#[derive(Deserialize)]
+struct Rollout {
+    description: Option<String>,
+    segment: Option<RolloutSegment>,
+    threshold: Option<Threshold>,
+}
+
+#[derive(Deserialize)]
+struct RolloutSegment {
+    key: Option<String>,
+    #[serde(default)]
+    keys: Vec<String>,
+    operator: Option<flipt::SegmentOperator>,
+    #[serde(default)]
+    value: bool,
+}
+
+#[derive(Deserialize)]
+struct Threshold {
+    #[serde(default)]
+    percentage: f32,
+    #[serde(default)]
+    value: bool,
+}
+
+#[derive(Deserialize)]
+struct Segment {
+    key: String,
+    #[serde(default)]
+    match_type: MatchType,
+    #[serde(default)]
+    constraints: Vec<Constraint>,
+}
+
+/// Flipt's segment match type; an omitted match type means all constraints must match.
+#[derive(Deserialize, Default)]
+enum MatchType {
+    #[default]
+    #[serde(rename = "ALL_MATCH_TYPE")]
+    All,
+    #[serde(rename = "ANY_MATCH_TYPE")]
+    Any,
+}
+
+#[derive(Deserialize)]
+struct Constraint {
+    #[serde(rename = "type")]
+    constraint_type: String,
+    property: String,
+    operator: String,
+    #[serde(default, deserialize_with = "scalar_string")]
+    value: String,
+}
+
+/// Every namespace defined in a `features.yml` stream, which may hold several documents.
+///
+/// Documents for the same namespace are merged, so flags may reference segments defined in
+/// another document of their namespace.
+pub fn parse_documents(yaml: &str) -> Result<Vec<source::Document>, Error> {
+    let mut namespaces: Vec<(source::Namespace, Document)> = Vec::new();
+
+    for document in serde_yaml_ng::Deserializer::from_str(yaml) {
+        let mut document = Option::<Document>::deserialize(document)
+            .map_err(|e| Error::InvalidYAML(e.to_string()))?
+            .unwrap_or_default();
+
+        let (key, name) = match document.namespace.take() {
+            Some(NamespaceEmbed::Key(key)) => (key, None),
+            Some(NamespaceEmbed::Namespace { key, name }) => (key, name),
+            None => (DEFAULT_NAMESPACE.to_string(), None),
+        };
+
+        match namespaces.iter_mut().find(|(ns, _)| ns.key == key) {
+            Some((ns, merged)) => {
+                ns.name = ns.name.take().or(name);
+                merged.flags.extend(document.flags);
+                merged.segments.extend(document.segments);
+            }
+            None => namespaces.push((source::Namespace { key, name }, document)),
+        }
+    }
+
+    namespaces
+        .into_iter()
+        .map(|(namespace, document)| resolve(namespace, document))
+        .collect()
+}
+
+/// The document for `namespace` in a `features.yml` stream.
+pub fn parse_document(yaml: &str, namespace: &str) -> Result<source::Document, Error> {
+    parse_documents(yaml)?
+        .into_iter()
+        .find(|document| document.namespace.key == namespace)
+        .ok_or_else(|| Error::InvalidSnapshot(format!("namespace {namespace} is not defined")))
+}
+
+/// The snapshot of `namespace` in a `features.yml` stream.
+pub fn parse_snapshot(yaml: &str, namespace: &str) -> Result<snapshot::Snapshot, Error> {
+    parse_document(yaml, namespace).map(snapshot::Snapshot::build)
+}
+
+fn resolve(namespace: source::Namespace, document: Document) -> Result<source::Document, Error> {
+    let segments: HashMap<&str, &Segment> = document
+        .segments
+        .iter()
+        .map(|segment| (segment.key.as_str(), segment))
+        .collect();
+
+    let flags = document
+        .flags
+        .into_iter()
+        .map(|flag| resolve_flag(&segments, flag))
+        .collect::<Result<_, _>>()?;
+
+    Ok(source::Document { namespace, flags })
+}
+
+fn resolve_flag(segments: &HashMap<&str, &Segment>, flag: Flag) -> Result<source::Flag, Error> {
+    let invalid = |message: String| Error::InvalidSnapshot(format!("flag {}: {message}", flag.key));
+
+    let lookup_segments = |keys: &[String]| {
+        keys.iter()
+            .map(|key| {
+                segments
+                    .get(key.as_str())
+                    .map(|segment| segment.to_source())
+                    .ok_or_else(|| invalid(format!("segment {key} is not defined")))
+            })
+            .collect::<Result<Vec<_>, _>>()
+    };
+
+    let mut rules = Vec::with_capacity(flag.rules.len());
+    for rule in &flag.rules {
+        let (keys, segment_operator) = match &rule.segment {
+            Some(SegmentEmbed::Key(key)) => (vec![key.clone()], flipt::SegmentOperator::Or),
+            Some(SegmentEmbed::Keys { keys, operator }) => (keys.clone(), operator.clone()),
+            None => return Err(invalid("rule has no segment".into())),
+        };
+
+        let distributions = rule
+            .distributions
+            .iter()
+            .map(|distribution| {
+                let variant = flag
+                    .variants
+                    .iter()
+                    .find(|variant| variant.key == distribution.variant)
+                    .ok_or_else(|| {
+                        invalid(format!("variant {} is not defined", distribution.variant))
+                    })?;
+
+                Ok(source::Distribution {
+                    variant_key: variant.key.clone(),
+                    rollout: distribution.rollout,
+                    variant_attachment: attachment(variant)?,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+
+        rules.push(source::Rule {
+            distributions,
+            segments: Some(lookup_segments(&keys)?),
+            segment_operator,
+        });
+    }
+
+    let mut rollouts = Vec::with_capacity(flag.rollouts.len());
+    for rollout in &flag.rollouts {
+        let segment = match &rollout.segment {
+            Some(segment) => {
+                let keys: Vec<String> = segment.key.iter().chain(&segment.keys).cloned().collect();
+
+                Some(source::SegmentRule {
+                    segment_operator: segment.operator.clone(),
+                    value: segment.value,
+                    segments: lookup_segments(&keys)?,
+                })
+            }
+            None => None,
+        };
+
+        rollouts.push(source::Rollout {
+            description: rollout.description.clone(),
+            segment,
+            threshold: rollout
+                .threshold
+                .as_ref()
+                .map(|threshold| source::Threshold {
+                    percentage: threshold.percentage,
+                    value: threshold.value,
+                }),
+        });
+    }
+
+    let default_variant = match flag.variants.iter().find(|variant| variant.default) {
+        Some(variant) => Some(source::Variant {
+            id: variant.key.clone(),
+            key: variant.key.clone(),
+            attachment: attachment(variant)?,
+        }),
+        None => None,
+    };
+
+    Ok(source::Flag {
+        name: flag.name.unwrap_or_else(|| flag.key.clone()),
+        key: flag.key,
+        r#type: flag.flag_type,
+        description: flag.description,
+        enabled: flag.enabled,
+        rules: Some(rules),
+        rollouts: Some(rollouts),
+        default_variant,
+    })
+}
+
+/// A variant attachment as the JSON string the snapshot endpoint serves.
+fn attachment(variant: &Variant) -> Result<Option<String>, Error> {
+    variant
+        .attachment
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|e| Error::InvalidYAML(format!("variant {}: {e}", variant.key)))
+}
+
+impl Segment {
+    fn to_source(&self) -> source::Segment {
+        let match_type = match self.match_type {
+            MatchType::All => flipt::SegmentMatchType::All,
+            MatchType::Any => flipt::SegmentMatchType::Any,
+        };
+
+        source::Segment {
+            key: self.key.clone(),
+            match_type,
+            constraints: self
+                .constraints
+                .iter()
+                .map(|constraint| source::SegmentConstraint {
+                    r#type: comparison_type(&constraint.constraint_type),
+                    property: constraint.property.clone(),
+                    operator: constraint.operator.clone(),
+                    value: constraint.value.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Declarative files name comparison types without the `CONSTRAINT` infix the API uses.
+fn comparison_type(value: &str) -> flipt::ConstraintComparisonType {
+    match value.replace("_CONSTRAINT_", "_").as_str() {
+        "STRING_COMPARISON_TYPE" => flipt::ConstraintComparisonType::String,
+        "NUMBER_COMPARISON_TYPE" => flipt::ConstraintComparisonType::Number,
+        "BOOLEAN_COMPARISON_TYPE" => flipt::ConstraintComparisonType::Boolean,
+        "DATETIME_COMPARISON_TYPE" => flipt::ConstraintComparisonType::DateTime,
+        "ENTITY_ID_COMPARISON_TYPE" => flipt::ConstraintComparisonType::EntityId,
+        _ => flipt::ConstraintComparisonType::Unknown,
+    }
+}
+
+/// Constraint values are strings, but YAML reads unquoted numbers and booleans as such.
+fn scalar_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::String(value) => Ok(value),
+        Value::Null => Ok(String::new()),
+        value => Ok(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EvaluationRequest;
+
+    const FEATURES: &str = r##"
+version: "1.4"
+namespace: default
+flags:
+  - key: colors
+    name: Colors
+    type: VARIANT_FLAG_TYPE
+    enabled: true
+    variants:
+      - key: red
+        attachment:
+          hex: "#ff0000"
+      - key: blue
+        default: true
+    rules:
+      - segment: internal
+        distributions:
+          - variant: red
+            rollout: 100
+      - segment:
+          keys: [internal, beta]
+          operator: AND_SEGMENT_OPERATOR
+        distributions:
+          - variant: blue
+            rollout: 100
+  - key: new-ui
+    type: BOOLEAN_FLAG_TYPE
+    enabled: false
+    rollouts:
+      - segment:
+          key: internal
+          value: true
+      - threshold:
+          percentage: 50
+          value: true
+segments:
+  - key: internal
+    match_type: ALL_MATCH_TYPE
+    constraints:
+      - type: STRING_COMPARISON_TYPE
+        property: email
+        operator: suffix
+        value: "@example.com"
+      - type: NUMBER_COMPARISON_TYPE
+        property: age
+        operator: gte
+        value: 18
+  - key: beta
+    match_type: ANY_MATCH_TYPE
+---
+namespace:
+  key: other
+  name: Other
+flags:
+  - key: flag1
+    enabled: true
+"##;
+
+    #[test]
+    fn test_parse_documents() {
+        let documents = parse_documents(FEATURES).unwrap();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[1].namespace.key, "other");
+        assert_eq!(documents[1].namespace.name, Some("Other".into()));
+        assert_eq!(documents[1].flags[0].name, "flag1");
+
+        let colors = &documents[0].flags[0];
+        assert_eq!(colors.name, "Colors");
+        assert_eq!(colors.default_variant.as_ref().unwrap().key, "blue");
+
+        let rules = colors.rules.as_ref().unwrap();
+        assert_eq!(rules[0].segment_operator, flipt::SegmentOperator::Or);
+        assert_eq!(
+            rules[0].distributions[0].variant_attachment,
+            Some(r##"{"hex":"#ff0000"}"##.into())
+        );
+
+        // segment keys are resolved to their definitions
+        let internal = &rules[0].segments.as_ref().unwrap()[0];
+        assert_eq!(internal.match_type, flipt::SegmentMatchType::All);
+        assert_eq!(
+            internal.constraints[0].r#type,
+            flipt::ConstraintComparisonType::String
+        );
+        assert_eq!(internal.constraints[1].value, "18");
+
+        assert_eq!(rules[1].segment_operator, flipt::SegmentOperator::And);
+        assert_eq!(rules[1].segments.as_ref().unwrap().len(), 2);
+
+        let rollouts = documents[0].flags[1].rollouts.as_ref().unwrap();
+        assert_eq!(
+            rollouts[0].segment.as_ref().unwrap().segments[0].key,
+            "internal"
+        );
+        assert_eq!(rollouts[1].threshold.as_ref().unwrap().percentage, 50.0);
+    }
+
+    #[test]
+    fn test_parse_snapshot_evaluates() {
+        let snapshot = parse_snapshot(FEATURES, "default").unwrap();
+
+        let request = |entity_id: &str, email: &str| EvaluationRequest {
+            flag_key: "colors".into(),
+            entity_id: entity_id.into(),
+            context: HashMap::from([("email".into(), email.into()), ("age".into(), "30".into())]),
+            list_context: HashMap::new(),
+        };
+
+        let response =
+            crate::variant_evaluation(&snapshot, "default", &request("1", "a@example.com"))
+                .unwrap();
+        assert!(response.r#match);
+        assert_eq!(response.variant_key, "red");
+
+        let response =
+            crate::variant_evaluation(&snapshot, "default", &request("1", "a@other.com")).unwrap();
+        assert!(!response.r#match);
+        assert_eq!(response.variant_key, "blue");
+    }
+
+    #[test]
+    fn test_parse_merges_documents_of_a_namespace() {
+        let yaml = r#"
+flags:
+  - key: flag1
+    enabled: true
+    rollouts:
+      - segment:
+          key: everyone
+          value: true
+---
+namespace: default
+segments:
+  - key: everyone
+"#;
+        let document = parse_document(yaml, "default").unwrap();
+        assert_eq!(document.flags.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_defaults_to_all_match_type() {
+        let yaml = r#"
+flags:
+  - key: flag1
+    type: BOOLEAN_FLAG_TYPE
+    rollouts:
+      - segment:
+          key: internal-admins
+          value: true
+segments:
+  - key: internal-admins
+    constraints:
+      - type: STRING_COMPARISON_TYPE
+        property: email
+        operator: suffix
+        value: "@example.com"
+      - type: STRING_COMPARISON_TYPE
+        property: role
+        operator: eq
+        value: admin
+"#;
+        let snapshot = parse_snapshot(yaml, "default").unwrap();
+        let segment = &snapshot.namespace().segments["internal-admins"];
+        assert_eq!(segment.match_type, flipt::SegmentMatchType::All);
+
+        let request = |role: &str| EvaluationRequest {
+            flag_key: "flag1".into(),
+            entity_id: "1".into(),
+            context: HashMap::from([
+                ("email".into(), "a@example.com".into()),
+                ("role".into(), role.into()),
+            ]),
+            list_context: HashMap::new(),
+        };
+        assert!(
+            crate::boolean_evaluation(&snapshot, "default", &request("admin"))
+                .unwrap()
+                .enabled
+        );
+        assert!(
+            !crate::boolean_evaluation(&snapshot, "default", &request("viewer"))
+                .unwrap()
+                .enabled
+        );
+
+        assert!(matches!(
+            parse_documents("segments:\n  - key: s\n    match_type: ALL_SEGMENT_MATCH_TYPE\n"),
+            Err(Error::InvalidYAML(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let unknown_segment = r#"
+flags:
+  - key: flag1
+    rules:
+      - segment: missing
+"#;
+        assert_eq!(
+            parse_documents(unknown_segment).unwrap_err(),
+            Error::InvalidSnapshot("flag flag1: segment missing is not defined".into())
+        );
+
+        let unknown_variant = r#"
+flags:
+  - key: flag1
+    rules:
+      - segment: everyone
+        distributions:
+          - variant: missing
+            rollout: 100
+segments:
+  - key: everyone
+"#;
+        assert_eq!(
+            parse_documents(unknown_variant).unwrap_err(),
+            Error::InvalidSnapshot("flag flag1: variant missing is not defined".into())
+        );
+
+        assert!(matches!(
+            parse_documents("flags: {"),
+            Err(Error::InvalidYAML(_))
+        ));
+        assert_eq!(
+            parse_document("namespace: default", "other").unwrap_err(),
+            Error::InvalidSnapshot("namespace other is not defined".into())
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use alloc::string::String;
 use thiserror::Error;
 
 #[non_exhaustive]
@@ -5,8 +6,14 @@ use thiserror::Error;
 pub enum Error {
     #[error("error parsing json: {0}")]
     InvalidJSON(String),
+    #[error("error parsing yaml: {0}")]
+    InvalidYAML(String),
     #[error("invalid request: {0}")]
     InvalidRequest(String),
+    #[error("invalid snapshot: {0}")]
+    InvalidSnapshot(String),
+    #[error("signature verification failed: {0}")]
+    InvalidSignature(String),
     #[error("server error: {0}")]
     Server(String),
     #[error("internal error: {0}")]
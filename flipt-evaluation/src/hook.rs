@@ -0,0 +1,299 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::error::Error;
+use crate::{
+    BatchEvaluationResponse, BooleanEvaluationResponse, ErrorEvaluationResponse, EvaluationRequest,
+    VariantEvaluationResponse,
+};
+
+/// Response handed to [`EvaluationHook::after`].
+#[derive(Debug)]
+pub enum HookResponse<'a> {
+    Variant(&'a VariantEvaluationResponse),
+    Boolean(&'a BooleanEvaluationResponse),
+    Error(&'a ErrorEvaluationResponse),
+}
+
+impl<'a> From<&'a VariantEvaluationResponse> for HookResponse<'a> {
+    fn from(value: &'a VariantEvaluationResponse) -> Self {
+        HookResponse::Variant(value)
+    }
+}
+
+impl<'a> From<&'a BooleanEvaluationResponse> for HookResponse<'a> {
+    fn from(value: &'a BooleanEvaluationResponse) -> Self {
+        HookResponse::Boolean(value)
+    }
+}
+
+/// Listener invoked around every evaluation. Engines run hooks without holding their locks, so
+/// a hook may call back into the engine it is registered with.
+///
+/// Hooks are fault-isolated: a panicking hook is ignored and does not affect the
+/// evaluation result or the remaining hooks. This requires the `std` feature and unwinding
+/// panics, so it does not hold on targets built with `panic = "abort"`, which includes
+/// `wasm32`: there a panicking hook aborts the engine.
+pub trait EvaluationHook: Send + Sync {
+    fn before(&self, _namespace: &str, _request: &EvaluationRequest) {}
+
+    fn after(&self, _namespace: &str, _request: &EvaluationRequest, _response: &HookResponse<'_>) {}
+
+    fn error(&self, _namespace: &str, _request: &EvaluationRequest, _error: &Error) {}
+}
+
+pub type Hooks = Vec<Arc<dyn EvaluationHook>>;
+
+/// Catches a panic of a hook. Where panics abort, e.g. on `wasm32`, this cannot help.
+#[cfg(feature = "std")]
+fn isolate<F: FnOnce()>(f: F) {
+    let _ = catch_unwind(AssertUnwindSafe(f));
+}
+
+/// Without `std` panics cannot be caught, so hooks run as-is.
+#[cfg(not(feature = "std"))]
+fn isolate<F: FnOnce()>(f: F) {
+    f()
+}
+
+fn before(hooks: &[Arc<dyn EvaluationHook>], namespace: &str, request: &EvaluationRequest) {
+    for hook in hooks {
+        isolate(|| hook.before(namespace, request));
+    }
+}
+
+fn after(
+    hooks: &[Arc<dyn EvaluationHook>],
+    namespace: &str,
+    request: &EvaluationRequest,
+    response: &HookResponse<'_>,
+) {
+    for hook in hooks {
+        isolate(|| hook.after(namespace, request, response));
+    }
+}
+
+fn error(
+    hooks: &[Arc<dyn EvaluationHook>],
+    namespace: &str,
+    request: &EvaluationRequest,
+    err: &Error,
+) {
+    for hook in hooks {
+        isolate(|| hook.error(namespace, request, err));
+    }
+}
+
+/// Runs a single evaluation, notifying the hooks before and after it. Panicking hooks are only
+/// isolated where panics unwind, see [`EvaluationHook`].
+pub fn evaluate<T, F>(
+    hooks: &[Arc<dyn EvaluationHook>],
+    namespace: &str,
+    request: &EvaluationRequest,
+    evaluation: F,
+) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+    for<'a> &'a T: Into<HookResponse<'a>>,
+{
+    if hooks.is_empty() {
+        return evaluation();
+    }
+
+    before(hooks, namespace, request);
+
+    let result = evaluation();
+
+    match &result {
+        Ok(response) => after(hooks, namespace, request, &response.into()),
+        Err(err) => error(hooks, namespace, request, err),
+    }
+
+    result
+}
+
+/// Runs a batch evaluation, notifying the hooks for each request in the batch. Panicking hooks are
+/// only isolated where panics unwind, see [`EvaluationHook`].
+pub fn evaluate_batch<F>(
+    hooks: &[Arc<dyn EvaluationHook>],
+    namespace: &str,
+    requests: &[EvaluationRequest],
+    evaluation: F,
+) -> Result<BatchEvaluationResponse, Error>
+where
+    F: FnOnce(&[EvaluationRequest]) -> Result<BatchEvaluationResponse, Error>,
+{
+    if hooks.is_empty() {
+        return evaluation(requests);
+    }
+
+    for request in requests {
+        before(hooks, namespace, request);
+    }
+
+    let result = evaluation(requests);
+
+    match &result {
+        Ok(batch) => {
+            for (request, response) in requests.iter().zip(&batch.responses) {
+                let response = if let Some(r) = &response.variant_evaluation_response {
+                    HookResponse::Variant(r)
+                } else if let Some(r) = &response.boolean_evaluation_response {
+                    HookResponse::Boolean(r)
+                } else if let Some(r) = &response.error_evaluation_response {
+                    HookResponse::Error(r)
+                } else {
+                    continue;
+                };
+
+                after(hooks, namespace, request, &response);
+            }
+        }
+        Err(err) => {
+            for request in requests {
+                error(hooks, namespace, request, err);
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct RecordingHook {
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl EvaluationHook for RecordingHook {
+        fn before(&self, namespace: &str, request: &EvaluationRequest) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("before {}/{}", namespace, request.flag_key));
+        }
+
+        fn after(&self, namespace: &str, request: &EvaluationRequest, response: &HookResponse<'_>) {
+            let kind = match response {
+                HookResponse::Variant(_) => "variant",
+                HookResponse::Boolean(_) => "boolean",
+                HookResponse::Error(_) => "error",
+            };
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("after {}/{} {}", namespace, request.flag_key, kind));
+        }
+
+        fn error(&self, namespace: &str, request: &EvaluationRequest, error: &Error) {
+            self.calls.lock().unwrap().push(format!(
+                "error {}/{} {}",
+                namespace, request.flag_key, error
+            ));
+        }
+    }
+
+    struct PanickingHook;
+
+    impl EvaluationHook for PanickingHook {
+        fn before(&self, _namespace: &str, _request: &EvaluationRequest) {
+            panic!("before");
+        }
+
+        fn after(
+            &self,
+            _namespace: &str,
+            _request: &EvaluationRequest,
+            _response: &HookResponse<'_>,
+        ) {
+            panic!("after");
+        }
+    }
+
+    fn request(flag_key: &str) -> EvaluationRequest {
+        EvaluationRequest {
+            flag_key: flag_key.into(),
+            entity_id: "entity".into(),
+            context: HashMap::new(),
+            list_context: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_evaluate_notifies_hooks() {
+        let recorder = Arc::new(RecordingHook::default());
+        let hooks: Hooks = vec![Arc::new(PanickingHook), recorder.clone()];
+
+        let result = evaluate(&hooks, "default", &request("foo"), || {
+            Ok(BooleanEvaluationResponse::default())
+        });
+        assert!(result.is_ok());
+
+        let result: Result<BooleanEvaluationResponse, Error> =
+            evaluate(&hooks, "default", &request("bar"), || {
+                Err(Error::InvalidRequest("boom".into()))
+            });
+        assert!(result.is_err());
+
+        assert_eq!(
+            *recorder.calls.lock().unwrap(),
+            vec![
+                "before default/foo",
+                "after default/foo boolean",
+                "before default/bar",
+                "error default/bar invalid request: boom",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_evaluate_batch_notifies_hooks_per_request() {
+        let recorder = Arc::new(RecordingHook::default());
+        let hooks: Hooks = vec![recorder.clone()];
+
+        let result = evaluate_batch(
+            &hooks,
+            "default",
+            &[request("foo"), request("bar")],
+            |requests| {
+                assert_eq!(requests.len(), 2);
+                Ok(BatchEvaluationResponse {
+                    responses: vec![
+                        crate::EvaluationResponse {
+                            r#type: crate::flipt::ResponseType::Variant,
+                            boolean_evaluation_response: None,
+                            variant_evaluation_response: Some(VariantEvaluationResponse::default()),
+                            error_evaluation_response: None,
+                        },
+                        crate::EvaluationResponse {
+                            r#type: crate::flipt::ResponseType::Error,
+                            boolean_evaluation_response: None,
+                            variant_evaluation_response: None,
+                            error_evaluation_response: Some(ErrorEvaluationResponse::default()),
+                        },
+                    ],
+                    request_duration_millis: 0.0,
+                })
+            },
+        );
+        assert!(result.is_ok());
+
+        assert_eq!(
+            *recorder.calls.lock().unwrap(),
+            vec![
+                "before default/foo",
+                "before default/bar",
+                "after default/foo variant",
+                "after default/bar error",
+            ]
+        );
+    }
+}
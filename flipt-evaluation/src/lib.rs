@@ -1,29 +1,263 @@
-use chrono::{DateTime, Utc};
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(any(feature = "std", feature = "alloc")))]
+compile_error!("either the `std` or the `alloc` feature must be enabled");
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Utc, Weekday};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use web_time::Instant;
 
+#[cfg(not(feature = "std"))]
+pub use hashbrown::HashMap;
+#[cfg(feature = "std")]
+pub use std::collections::HashMap;
+
+pub mod clock;
+#[cfg(feature = "std")]
+pub mod codec;
+#[cfg(feature = "std")]
+pub mod declarative;
 pub mod error;
+pub mod hook;
 pub mod models;
+#[cfg(feature = "std")]
+pub mod signature;
+pub mod simulation;
 pub mod store;
+pub mod synthesis;
 
+#[cfg(feature = "std")]
+use crate::clock::SystemClock;
+use crate::clock::{default_timestamp, Clock, Stopwatch};
 use crate::error::Error;
 use crate::models::flipt;
 use crate::store::Store;
 
 const DEFAULT_PERCENT: f32 = 100.0;
 const DEFAULT_TOTAL_BUCKET_NUMBER: u32 = 1000;
-const DEFAULT_PERCENT_MULTIPIER: f32 = DEFAULT_TOTAL_BUCKET_NUMBER as f32 / DEFAULT_PERCENT;
+const BASIS_POINT_TOTAL_BUCKET_NUMBER: u32 = 10000;
+const DEFAULT_THRESHOLD_BUCKET_NUMBER: u32 = 100;
+
+/// Granularity of the buckets entities are hashed into for threshold rollouts and
+/// variant distributions.
+///
+/// Finer buckets subdivide the default ones, so percentages that can be expressed at the
+/// default resolution (whole percents for thresholds, tenths of a percent for distributions)
+/// assign exactly the same entities at every resolution.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BucketResolution {
+    /// 1% steps for threshold rollouts and 0.1% steps for distributions.
+    #[default]
+    Percent,
+    /// 0.01% steps for threshold rollouts and distributions.
+    BasisPoint,
+}
+
+impl BucketResolution {
+    fn distribution_buckets(self) -> u32 {
+        match self {
+            BucketResolution::Percent => DEFAULT_TOTAL_BUCKET_NUMBER,
+            BucketResolution::BasisPoint => BASIS_POINT_TOTAL_BUCKET_NUMBER,
+        }
+    }
+
+    fn distribution_bucket(self, flag_key: &str, entity_id: &str) -> u32 {
+        let hash = crc32fast::hash(format!("{}{}", flag_key, entity_id).as_bytes());
+        let bucket = hash % DEFAULT_TOTAL_BUCKET_NUMBER;
+
+        match self {
+            BucketResolution::Percent => bucket,
+            BucketResolution::BasisPoint => {
+                let sub_buckets = BASIS_POINT_TOTAL_BUCKET_NUMBER / DEFAULT_TOTAL_BUCKET_NUMBER;
+                bucket * sub_buckets + (hash / DEFAULT_TOTAL_BUCKET_NUMBER) % sub_buckets
+            }
+        }
+    }
+
+    fn matches_threshold(self, entity_id: &str, flag_key: &str, percentage: f32) -> bool {
+        let hash = crc32fast::hash(format!("{}{}", entity_id, flag_key).as_bytes());
+        let bucket = hash % DEFAULT_THRESHOLD_BUCKET_NUMBER;
+
+        match self {
+            BucketResolution::Percent => (bucket as f32) < percentage,
+            BucketResolution::BasisPoint => {
+                let sub_buckets = DEFAULT_THRESHOLD_BUCKET_NUMBER;
+                let bucket =
+                    bucket * sub_buckets + (hash / DEFAULT_THRESHOLD_BUCKET_NUMBER) % sub_buckets;
+                bucket < round(percentage * sub_buckets as f32) as u32
+            }
+        }
+    }
+}
+
+/// Rounds half away from zero, like `f32::round` which is only available with `std`.
+#[cfg(feature = "std")]
+fn round(value: f32) -> f32 {
+    value.round()
+}
+
+#[cfg(not(feature = "std"))]
+fn round(value: f32) -> f32 {
+    libm::roundf(value)
+}
+
+/// Settings applied to an evaluation.
+#[derive(Clone, Copy)]
+pub struct EvaluationOptions<'a> {
+    pub clock: &'a dyn Clock,
+    pub resolution: BucketResolution,
+}
+
+impl<'a> EvaluationOptions<'a> {
+    pub fn new(clock: &'a dyn Clock) -> Self {
+        Self {
+            clock,
+            resolution: BucketResolution::default(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for EvaluationOptions<'_> {
+    fn default() -> Self {
+        Self {
+            clock: &SystemClock,
+            resolution: BucketResolution::default(),
+        }
+    }
+}
 
 #[repr(C)]
-#[derive(Deserialize, Clone, PartialEq, Debug, Serialize)]
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(from = "SerializedRequest", into = "SerializedRequest")]
 pub struct EvaluationRequest {
     pub flag_key: String,
     pub entity_id: String,
     pub context: HashMap<String, String>,
+    /// List-valued context properties, serialized as arrays in `context`. A property set in
+    /// `context` takes precedence over a list of the same name.
+    pub list_context: HashMap<String, ContextList>,
 }
 
-#[derive(Serialize, Debug)]
+/// Elements of a list-valued context property.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[serde(untagged)]
+pub enum ContextList {
+    Strings(Vec<String>),
+    Numbers(Vec<i32>),
+}
+
+impl ContextList {
+    pub fn len(&self) -> usize {
+        match self {
+            ContextList::Strings(values) => values.len(),
+            ContextList::Numbers(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The elements as strings, or `None` for a non-empty list of numbers.
+    fn strings(&self) -> Option<&[String]> {
+        match self {
+            ContextList::Strings(values) => Some(values),
+            ContextList::Numbers(values) if values.is_empty() => Some(&[]),
+            ContextList::Numbers(_) => None,
+        }
+    }
+
+    /// The elements as numbers, or `None` for a non-empty list of strings.
+    fn numbers(&self) -> Option<&[i32]> {
+        match self {
+            ContextList::Numbers(values) => Some(values),
+            ContextList::Strings(values) if values.is_empty() => Some(&[]),
+            ContextList::Strings(_) => None,
+        }
+    }
+}
+
+/// A context property as it is serialized: a string, or an array for list-valued properties.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(untagged)]
+pub enum ContextValue {
+    String(String),
+    List(ContextList),
+}
+
+/// Splits serialized context properties into string and list-valued properties.
+pub(crate) fn split_context(
+    context: HashMap<String, ContextValue>,
+) -> (HashMap<String, String>, HashMap<String, ContextList>) {
+    let mut values = HashMap::new();
+    let mut lists = HashMap::new();
+    for (key, value) in context {
+        match value {
+            ContextValue::String(value) => {
+                values.insert(key, value);
+            }
+            ContextValue::List(list) => {
+                lists.insert(key, list);
+            }
+        }
+    }
+    (values, lists)
+}
+
+/// Joins string and list-valued properties for serialization.
+pub(crate) fn merge_context(
+    values: HashMap<String, String>,
+    lists: HashMap<String, ContextList>,
+) -> HashMap<String, ContextValue> {
+    let mut context: HashMap<String, ContextValue> = lists
+        .into_iter()
+        .map(|(key, list)| (key, ContextValue::List(list)))
+        .collect();
+    context.extend(
+        values
+            .into_iter()
+            .map(|(key, value)| (key, ContextValue::String(value))),
+    );
+    context
+}
+
+#[derive(Deserialize, Serialize)]
+struct SerializedRequest {
+    flag_key: String,
+    entity_id: String,
+    context: HashMap<String, ContextValue>,
+}
+
+impl From<SerializedRequest> for EvaluationRequest {
+    fn from(request: SerializedRequest) -> Self {
+        let (context, list_context) = split_context(request.context);
+        Self {
+            flag_key: request.flag_key,
+            entity_id: request.entity_id,
+            context,
+            list_context,
+        }
+    }
+}
+
+impl From<EvaluationRequest> for SerializedRequest {
+    fn from(request: EvaluationRequest) -> Self {
+        Self {
+            flag_key: request.flag_key,
+            entity_id: request.entity_id,
+            context: merge_context(request.context, request.list_context),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
 pub struct VariantEvaluationResponse {
     pub r#match: bool,
     pub segment_keys: Vec<String>,
@@ -34,9 +268,17 @@ pub struct VariantEvaluationResponse {
     pub variant_attachment: Option<String>,
     pub request_duration_millis: f64,
     pub timestamp: DateTime<Utc>,
+    /// Id of the rule that produced the result.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule_rank: Option<usize>,
+    /// Index of the chosen distribution among the distributions of the rule.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distribution_index: Option<usize>,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub struct BooleanEvaluationResponse {
     pub enabled: bool,
     pub flag_key: String,
@@ -44,6 +286,11 @@ pub struct BooleanEvaluationResponse {
     pub request_duration_millis: f64,
     pub timestamp: DateTime<Utc>,
     pub segment_keys: Vec<String>,
+    /// Rank of the rollout that produced the result.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rollout_rank: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rollout_type: Option<flipt::RolloutType>,
 }
 
 #[derive(Serialize, Debug)]
@@ -77,7 +324,10 @@ impl Default for VariantEvaluationResponse {
             variant_key: String::from(""),
             variant_attachment: None,
             request_duration_millis: 0.0,
-            timestamp: chrono::offset::Utc::now(),
+            timestamp: default_timestamp(),
+            rule_id: None,
+            rule_rank: None,
+            distribution_index: None,
         }
     }
 }
@@ -89,8 +339,10 @@ impl Default for BooleanEvaluationResponse {
             flag_key: String::from(""),
             reason: flipt::EvaluationReason::Unknown,
             request_duration_millis: 0.0,
-            timestamp: chrono::offset::Utc::now(),
+            timestamp: default_timestamp(),
             segment_keys: vec![],
+            rollout_rank: None,
+            rollout_type: None,
         }
     }
 }
@@ -105,12 +357,60 @@ impl Default for ErrorEvaluationResponse {
     }
 }
 
+#[cfg(feature = "std")]
 pub fn variant_evaluation(
     store: &dyn Store,
     namespace: &str,
     request: &EvaluationRequest,
 ) -> Result<VariantEvaluationResponse, Error> {
-    let start = Instant::now();
+    variant_evaluation_with_options(store, namespace, request, &EvaluationOptions::default())
+}
+
+pub fn variant_evaluation_with_clock(
+    store: &dyn Store,
+    namespace: &str,
+    request: &EvaluationRequest,
+    clock: &dyn Clock,
+) -> Result<VariantEvaluationResponse, Error> {
+    let options = EvaluationOptions::new(clock);
+    variant_evaluation_with_options(store, namespace, request, &options)
+}
+
+pub fn variant_evaluation_with_options(
+    store: &dyn Store,
+    namespace: &str,
+    request: &EvaluationRequest,
+    options: &EvaluationOptions,
+) -> Result<VariantEvaluationResponse, Error> {
+    let mut segment_matches = SegmentMatches::default();
+    evaluate_variant(store, namespace, request, options, &mut segment_matches)
+}
+
+/// Error for rules or rollouts of a flag that could not be looked up, naming the missing segment
+/// that caused it if the store can tell.
+pub(crate) fn lookup_error(
+    store: &dyn Store,
+    namespace: &str,
+    flag_key: &str,
+    what: &str,
+) -> Error {
+    match store.get_missing_segment(namespace, flag_key) {
+        Some(segment_key) => Error::InvalidSnapshot(format!("segment {segment_key} not found")),
+        None => Error::Unknown(format!(
+            "error getting evaluation {what} for namespace {namespace} and flag {flag_key}"
+        )),
+    }
+}
+
+fn evaluate_variant(
+    store: &dyn Store,
+    namespace: &str,
+    request: &EvaluationRequest,
+    options: &EvaluationOptions,
+    segment_matches: &mut SegmentMatches,
+) -> Result<VariantEvaluationResponse, Error> {
+    let start = Stopwatch::start(options.clock);
+    let now = options.clock.now();
     let mut last_rank = 0;
 
     let flag = store
@@ -131,6 +431,7 @@ pub fn variant_evaluation(
 
     let mut variant_evaluation_response = VariantEvaluationResponse {
         flag_key: flag.key.clone(),
+        timestamp: now,
         ..Default::default()
     };
 
@@ -142,23 +443,17 @@ pub fn variant_evaluation(
 
     if !flag.enabled {
         variant_evaluation_response.reason = flipt::EvaluationReason::FlagDisabled;
-        variant_evaluation_response.request_duration_millis = start.elapsed().as_millis() as f64;
+        variant_evaluation_response.request_duration_millis = start.elapsed_millis(options.clock);
         return Ok(variant_evaluation_response);
     }
 
     let evaluation_rules = store
         .get_evaluation_rules(namespace, &request.flag_key)
-        .ok_or_else(|| {
-            Error::Unknown(format!(
-                "error getting evaluation rules for namespace {} and flag {}",
-                namespace,
-                request.flag_key.clone()
-            ))
-        })?;
+        .ok_or_else(|| lookup_error(store, namespace, &request.flag_key, "rules"))?;
 
     // if no rules and flag is enabled, return default variant
     if evaluation_rules.is_empty() {
-        variant_evaluation_response.request_duration_millis = start.elapsed().as_millis() as f64;
+        variant_evaluation_response.request_duration_millis = start.elapsed_millis(options.clock);
         return Ok(variant_evaluation_response);
     }
 
@@ -173,32 +468,28 @@ pub fn variant_evaluation(
         last_rank = rule.rank;
 
         let mut segment_keys: Vec<String> = vec![];
-        let mut segment_matches = 0;
+        let mut matched_segments = 0;
 
         for (segment_key, segment) in &rule.segments {
-            let matched = matches_constraints(
-                &request.context,
-                &segment.constraints,
-                &segment.match_type,
-                &request.entity_id,
-            )?;
+            let matched = segment_matches.matches(segment, request, now)?;
 
             if matched {
                 segment_keys.push(segment_key.clone());
-                segment_matches += 1;
+                matched_segments += 1;
             }
         }
 
         if rule.segment_operator == flipt::SegmentOperator::Or {
-            if segment_matches < 1 {
+            if matched_segments < 1 {
                 continue;
             }
         } else if rule.segment_operator == flipt::SegmentOperator::And
-            && rule.segments.len() != segment_matches
+            && rule.segments.len() != matched_segments
         {
             continue;
         }
 
+        segment_keys.sort();
         variant_evaluation_response.segment_keys = segment_keys;
 
         let distributions = store
@@ -211,10 +502,10 @@ pub fn variant_evaluation(
                 ))
             })?;
 
-        let mut valid_distributions: Vec<flipt::EvaluationDistribution> = vec![];
-        for distribution in distributions {
+        let mut valid_distributions: Vec<(usize, flipt::EvaluationDistribution)> = vec![];
+        for (idx, distribution) in distributions.into_iter().enumerate() {
             if distribution.rollout > 0.0 {
-                valid_distributions.push(distribution.clone());
+                valid_distributions.push((idx, distribution));
             }
         }
 
@@ -222,29 +513,33 @@ pub fn variant_evaluation(
         // match is true here because it did match the segment/rule
         if valid_distributions.is_empty() {
             variant_evaluation_response.r#match = true;
+            variant_evaluation_response.rule_id = Some(rule.id.clone());
+            variant_evaluation_response.rule_rank = Some(rule.rank);
             variant_evaluation_response.reason = flipt::EvaluationReason::Match;
             variant_evaluation_response.request_duration_millis =
-                start.elapsed().as_millis() as f64;
+                start.elapsed_millis(options.clock);
             return Ok(variant_evaluation_response);
         }
 
         let mut buckets: Vec<i32> = vec![];
         let mut cumulative_rollout: f32 = 0.0;
-        let total_rollout: f32 = valid_distributions.iter().map(|d| d.rollout).sum();
+        let total_rollout: f32 = valid_distributions.iter().map(|(_, d)| d.rollout).sum();
+        let total_buckets = options.resolution.distribution_buckets();
+        let percent_multiplier = total_buckets as f32 / DEFAULT_PERCENT;
 
-        for (idx, distribution) in valid_distributions.iter().enumerate() {
+        for (idx, (_, distribution)) in valid_distributions.iter().enumerate() {
             let normalized_rollout = (distribution.rollout / total_rollout) * DEFAULT_PERCENT;
             cumulative_rollout += normalized_rollout;
-            let mut bucket = (cumulative_rollout * DEFAULT_PERCENT_MULTIPIER).round() as i32;
+            let mut bucket = round(cumulative_rollout * percent_multiplier) as i32;
             if idx + 1 == valid_distributions.len() {
-                bucket = DEFAULT_TOTAL_BUCKET_NUMBER as i32;
+                bucket = total_buckets as i32;
             }
             buckets.push(bucket);
         }
 
-        let bucket =
-            crc32fast::hash(format!("{}{}", request.flag_key, request.entity_id).as_bytes())
-                % DEFAULT_TOTAL_BUCKET_NUMBER;
+        let bucket = options
+            .resolution
+            .distribution_bucket(&request.flag_key, &request.entity_id);
 
         buckets.sort();
 
@@ -258,29 +553,64 @@ pub fn variant_evaluation(
             variant_evaluation_response.r#match = false;
             variant_evaluation_response.reason = flipt::EvaluationReason::Default;
             variant_evaluation_response.request_duration_millis =
-                start.elapsed().as_millis() as f64;
+                start.elapsed_millis(options.clock);
             return Ok(variant_evaluation_response);
         }
 
-        let d = &valid_distributions[index];
+        let (distribution_index, d) = &valid_distributions[index];
 
         variant_evaluation_response.r#match = true;
+        variant_evaluation_response.rule_id = Some(rule.id.clone());
+        variant_evaluation_response.rule_rank = Some(rule.rank);
+        variant_evaluation_response.distribution_index = Some(*distribution_index);
         variant_evaluation_response.variant_key = d.variant_key.clone();
         variant_evaluation_response.variant_attachment = d.variant_attachment.clone();
         variant_evaluation_response.reason = flipt::EvaluationReason::Match;
-        variant_evaluation_response.request_duration_millis = start.elapsed().as_millis() as f64;
+        variant_evaluation_response.request_duration_millis = start.elapsed_millis(options.clock);
         return Ok(variant_evaluation_response);
     }
 
     Ok(variant_evaluation_response)
 }
 
+#[cfg(feature = "std")]
 pub fn boolean_evaluation(
     store: &dyn Store,
     namespace: &str,
     request: &EvaluationRequest,
 ) -> Result<BooleanEvaluationResponse, Error> {
-    let start = Instant::now();
+    boolean_evaluation_with_options(store, namespace, request, &EvaluationOptions::default())
+}
+
+pub fn boolean_evaluation_with_clock(
+    store: &dyn Store,
+    namespace: &str,
+    request: &EvaluationRequest,
+    clock: &dyn Clock,
+) -> Result<BooleanEvaluationResponse, Error> {
+    let options = EvaluationOptions::new(clock);
+    boolean_evaluation_with_options(store, namespace, request, &options)
+}
+
+pub fn boolean_evaluation_with_options(
+    store: &dyn Store,
+    namespace: &str,
+    request: &EvaluationRequest,
+    options: &EvaluationOptions,
+) -> Result<BooleanEvaluationResponse, Error> {
+    let mut segment_matches = SegmentMatches::default();
+    evaluate_boolean(store, namespace, request, options, &mut segment_matches)
+}
+
+fn evaluate_boolean(
+    store: &dyn Store,
+    namespace: &str,
+    request: &EvaluationRequest,
+    options: &EvaluationOptions,
+    segment_matches: &mut SegmentMatches,
+) -> Result<BooleanEvaluationResponse, Error> {
+    let start = Stopwatch::start(options.clock);
+    let now = options.clock.now();
     let mut last_rank = 0;
 
     let flag = store
@@ -301,13 +631,7 @@ pub fn boolean_evaluation(
 
     let evaluation_rollouts = store
         .get_evaluation_rollouts(namespace, &request.flag_key)
-        .ok_or_else(|| {
-            Error::Unknown(format!(
-                "error getting evaluation rollouts for namespace {} and flag {}",
-                namespace,
-                request.flag_key.clone()
-            ))
-        })?;
+        .ok_or_else(|| lookup_error(store, namespace, &request.flag_key, "rollouts"))?;
 
     for rollout in evaluation_rollouts {
         if rollout.rank < last_rank {
@@ -320,55 +644,56 @@ pub fn boolean_evaluation(
         last_rank = rollout.rank;
 
         if let Some(threshold) = rollout.threshold {
-            let normalized_value =
-                (crc32fast::hash(format!("{}{}", request.entity_id, request.flag_key).as_bytes())
-                    % 100) as f32;
-
-            if normalized_value < threshold.percentage {
+            if options.resolution.matches_threshold(
+                &request.entity_id,
+                &request.flag_key,
+                threshold.percentage,
+            ) {
                 return Ok(BooleanEvaluationResponse {
                     enabled: threshold.value,
                     flag_key: flag.key.clone(),
                     reason: flipt::EvaluationReason::Match,
-                    request_duration_millis: start.elapsed().as_millis() as f64,
-                    timestamp: chrono::offset::Utc::now(),
+                    request_duration_millis: start.elapsed_millis(options.clock),
+                    timestamp: now,
                     segment_keys: vec![],
+                    rollout_rank: Some(rollout.rank),
+                    rollout_type: Some(flipt::RolloutType::Threshold),
                 });
             }
         } else if let Some(segment) = rollout.segment {
-            let mut segment_matches = 0;
+            let mut matched_segments = 0;
             let mut segments = vec![];
 
             for segment_data in segment.segments.values() {
-                let matched = matches_constraints(
-                    &request.context,
-                    &segment_data.constraints,
-                    &segment_data.match_type,
-                    &request.entity_id,
-                )?;
+                let matched = segment_matches.matches(segment_data, request, now)?;
 
                 if matched {
                     segments.push(segment_data.segment_key.clone());
-                    segment_matches += 1;
+                    matched_segments += 1;
                 }
             }
 
             if segment.segment_operator == flipt::SegmentOperator::Or {
-                if segment_matches < 1 {
+                if matched_segments < 1 {
                     continue;
                 }
             } else if segment.segment_operator == flipt::SegmentOperator::And
-                && segment.segments.len() != segment_matches
+                && segment.segments.len() != matched_segments
             {
                 continue;
             }
 
+            segments.sort();
+
             return Ok(BooleanEvaluationResponse {
                 enabled: segment.value,
                 flag_key: flag.key.clone(),
                 reason: flipt::EvaluationReason::Match,
-                request_duration_millis: start.elapsed().as_millis() as f64,
-                timestamp: chrono::offset::Utc::now(),
+                request_duration_millis: start.elapsed_millis(options.clock),
+                timestamp: now,
                 segment_keys: segments,
+                rollout_rank: Some(rollout.rank),
+                rollout_type: Some(flipt::RolloutType::Segment),
             });
         }
     }
@@ -377,21 +702,55 @@ pub fn boolean_evaluation(
         enabled: flag.enabled,
         flag_key: flag.key.clone(),
         reason: flipt::EvaluationReason::Default,
-        request_duration_millis: start.elapsed().as_millis() as f64,
-        timestamp: chrono::offset::Utc::now(),
+        request_duration_millis: start.elapsed_millis(options.clock),
+        timestamp: now,
         segment_keys: vec![],
+        rollout_rank: None,
+        rollout_type: None,
     })
 }
 
+#[cfg(feature = "std")]
 pub fn batch_evaluation(
     store: &dyn Store,
     namespace: &str,
     requests: Vec<EvaluationRequest>,
 ) -> Result<BatchEvaluationResponse, Error> {
-    let start = Instant::now();
+    batch_evaluation_with_options(store, namespace, &requests, &EvaluationOptions::default())
+}
+
+pub fn batch_evaluation_with_clock(
+    store: &dyn Store,
+    namespace: &str,
+    requests: Vec<EvaluationRequest>,
+    clock: &dyn Clock,
+) -> Result<BatchEvaluationResponse, Error> {
+    let options = EvaluationOptions::new(clock);
+    batch_evaluation_with_options(store, namespace, &requests, &options)
+}
+
+pub fn batch_evaluation_with_options(
+    store: &dyn Store,
+    namespace: &str,
+    requests: &[EvaluationRequest],
+    options: &EvaluationOptions,
+) -> Result<BatchEvaluationResponse, Error> {
+    let start = Stopwatch::start(options.clock);
 
     let mut evaluation_responses: Vec<EvaluationResponse> = vec![];
+    let mut segment_matches = SegmentMatches::default();
+    let mut previous: Option<&EvaluationRequest> = None;
     for request in requests {
+        // requests for the same entity and context share their segment match results
+        if previous.is_some_and(|p| {
+            p.entity_id != request.entity_id
+                || p.context != request.context
+                || p.list_context != request.list_context
+        }) {
+            segment_matches = SegmentMatches::default();
+        }
+        previous = Some(request);
+
         let flag = match store.get_flag(namespace, &request.flag_key) {
             Some(f) => f,
             None => {
@@ -411,7 +770,8 @@ pub fn batch_evaluation(
 
         match flag.r#type {
             flipt::FlagType::Boolean => {
-                let boolean_evaluation = boolean_evaluation(store, namespace, &request)?;
+                let boolean_evaluation =
+                    evaluate_boolean(store, namespace, request, options, &mut segment_matches)?;
                 evaluation_responses.push(EvaluationResponse {
                     r#type: flipt::ResponseType::Boolean,
                     boolean_evaluation_response: Some(boolean_evaluation),
@@ -420,7 +780,8 @@ pub fn batch_evaluation(
                 });
             }
             flipt::FlagType::Variant => {
-                let variant_evaluation = variant_evaluation(store, namespace, &request)?;
+                let variant_evaluation =
+                    evaluate_variant(store, namespace, request, options, &mut segment_matches)?;
                 evaluation_responses.push(EvaluationResponse {
                     r#type: flipt::ResponseType::Variant,
                     boolean_evaluation_response: None,
@@ -433,15 +794,49 @@ pub fn batch_evaluation(
 
     Ok(BatchEvaluationResponse {
         responses: evaluation_responses,
-        request_duration_millis: start.elapsed().as_millis() as f64,
+        request_duration_millis: start.elapsed_millis(options.clock),
     })
 }
 
+/// Segment match results for one entity and context, so that a segment referenced by
+/// several rules and rollouts is only matched once.
+#[derive(Default)]
+struct SegmentMatches {
+    results: HashMap<String, bool>,
+}
+
+impl SegmentMatches {
+    fn matches(
+        &mut self,
+        segment: &flipt::EvaluationSegment,
+        request: &EvaluationRequest,
+        now: DateTime<Utc>,
+    ) -> Result<bool, Error> {
+        if let Some(matched) = self.results.get(&segment.segment_key) {
+            return Ok(*matched);
+        }
+
+        let matched = matches_constraints(
+            &request.context,
+            &request.list_context,
+            &segment.constraints,
+            &segment.match_type,
+            &request.entity_id,
+            now,
+        )?;
+
+        self.results.insert(segment.segment_key.clone(), matched);
+        Ok(matched)
+    }
+}
+
 fn matches_constraints(
     eval_context: &HashMap<String, String>,
+    list_context: &HashMap<String, ContextList>,
     constraints: &Vec<flipt::EvaluationConstraint>,
     segment_match_type: &flipt::SegmentMatchType,
     entity_id: &str,
+    now: DateTime<Utc>,
 ) -> Result<bool, Error> {
     let mut constraint_matches: usize = 0;
     for constraint in constraints {
@@ -450,21 +845,37 @@ fn matches_constraints(
             .unwrap_or(&String::new())
             .to_string();
 
-        let matched = match constraint.r#type {
-            flipt::ConstraintComparisonType::String => matches_string(constraint, &value),
-            flipt::ConstraintComparisonType::Number => {
-                matches_number(constraint, &value).unwrap_or(false)
-            }
-            flipt::ConstraintComparisonType::Boolean => {
-                matches_boolean(constraint, &value).unwrap_or(false)
-            }
-            flipt::ConstraintComparisonType::DateTime => {
-                matches_datetime(constraint, &value).unwrap_or(false)
+        // only string and number comparisons apply to list-valued properties
+        let list = if eval_context.contains_key(&constraint.property) {
+            None
+        } else {
+            list_context.get(&constraint.property)
+        };
+
+        let matched = match (&constraint.r#type, list) {
+            (flipt::ConstraintComparisonType::String, Some(list)) => {
+                matches_string_list(constraint, list)
             }
-            flipt::ConstraintComparisonType::EntityId => matches_string(constraint, entity_id),
-            _ => {
-                return Ok(false);
+            (flipt::ConstraintComparisonType::Number, Some(list)) => {
+                matches_number_list(constraint, list).unwrap_or(false)
             }
+            (_, Some(_)) => false,
+            (_, None) => match constraint.r#type {
+                flipt::ConstraintComparisonType::String => matches_string(constraint, &value),
+                flipt::ConstraintComparisonType::Number => {
+                    matches_number(constraint, &value).unwrap_or(false)
+                }
+                flipt::ConstraintComparisonType::Boolean => {
+                    matches_boolean(constraint, &value).unwrap_or(false)
+                }
+                flipt::ConstraintComparisonType::DateTime => {
+                    matches_datetime(constraint, &value, now).unwrap_or(false)
+                }
+                flipt::ConstraintComparisonType::EntityId => matches_string(constraint, entity_id),
+                _ => {
+                    return Ok(false);
+                }
+            },
         };
 
         if matched {
@@ -490,6 +901,110 @@ fn matches_constraints(
     Ok(is_match)
 }
 
+/// Operators comparing a list-valued context property against a set of values or a size.
+/// A scalar context property is compared as a list of its value, and an absent one as an
+/// empty list.
+fn is_list_operator(operator: &str) -> bool {
+    matches!(
+        operator,
+        "containsany" | "containsall" | "containsnone" | "sizeeq" | "sizegt" | "sizelt"
+    )
+}
+
+fn matches_list<T, F>(
+    operator: &str,
+    values: &[T],
+    constraint_value: &str,
+    parse: F,
+) -> Result<bool, Error>
+where
+    T: PartialEq,
+    F: FnOnce(&str) -> Result<Vec<T>, Error>,
+{
+    match operator {
+        "sizeeq" | "sizegt" | "sizelt" => {
+            let size = constraint_value.parse::<usize>().map_err(|err| {
+                Error::InvalidRequest(format!("error parsing size {constraint_value}: {err}"))
+            })?;
+
+            return Ok(match operator {
+                "sizeeq" => values.len() == size,
+                "sizegt" => values.len() > size,
+                _ => values.len() < size,
+            });
+        }
+        _ => {}
+    }
+
+    let expected = parse(constraint_value)?;
+
+    match operator {
+        "containsany" => Ok(expected.iter().any(|e| values.contains(e))),
+        "containsall" => Ok(expected.iter().all(|e| values.contains(e))),
+        "containsnone" => Ok(!expected.iter().any(|e| values.contains(e))),
+        _ => Ok(false),
+    }
+}
+
+fn parse_strings(values: &str) -> Result<Vec<String>, Error> {
+    serde_json::from_str::<Vec<String>>(values)
+        .map_err(|err| Error::InvalidRequest(format!("error parsing strings {values}: {err}")))
+}
+
+fn parse_numbers(values: &str) -> Result<Vec<i32>, Error> {
+    serde_json::from_str::<Vec<i32>>(values)
+        .map_err(|err| Error::InvalidRequest(format!("error parsing numbers {values}: {err}")))
+}
+
+fn matches_string_list(
+    evaluation_constraint: &flipt::EvaluationConstraint,
+    list: &ContextList,
+) -> bool {
+    let operator = evaluation_constraint.operator.as_str();
+
+    match operator {
+        "empty" => list.is_empty(),
+        "notempty" => !list.is_empty(),
+        _ if is_list_operator(operator) => list.strings().is_some_and(|values| {
+            matches_list(
+                operator,
+                values,
+                &evaluation_constraint.value,
+                parse_strings,
+            )
+            .unwrap_or(false)
+        }),
+        _ => false,
+    }
+}
+
+fn matches_number_list(
+    evaluation_constraint: &flipt::EvaluationConstraint,
+    list: &ContextList,
+) -> Result<bool, Error> {
+    let operator = evaluation_constraint.operator.as_str();
+
+    match operator {
+        "notpresent" => Ok(list.is_empty()),
+        "present" => Ok(!list.is_empty()),
+        _ if is_list_operator(operator) => {
+            let values = list.numbers().ok_or_else(|| {
+                Error::InvalidRequest(format!(
+                    "{} is not a list of numbers",
+                    evaluation_constraint.property
+                ))
+            })?;
+            matches_list(
+                operator,
+                values,
+                &evaluation_constraint.value,
+                parse_numbers,
+            )
+        }
+        _ => Ok(false),
+    }
+}
+
 fn oneof_string(v: &str, values: &str) -> bool {
     match serde_json::from_str::<Vec<&str>>(values) {
         Ok(values) => values.contains(&v),
@@ -510,11 +1025,21 @@ fn matches_string(evaluation_constraint: &flipt::EvaluationConstraint, v: &str)
         _ => {}
     }
 
+    let value = evaluation_constraint.value.as_str();
+
+    if is_list_operator(operator) {
+        let values = if v.is_empty() {
+            vec![]
+        } else {
+            vec![v.to_string()]
+        };
+        return matches_list(operator, &values, value, parse_strings).unwrap_or(false);
+    }
+
     if v.is_empty() {
         return false;
     }
 
-    let value = evaluation_constraint.value.as_str();
     match operator {
         "eq" => v == value,
         "neq" => v != value,
@@ -553,6 +1078,23 @@ fn matches_number(
         _ => {}
     }
 
+    if is_list_operator(operator) {
+        let values = if v.is_empty() {
+            vec![]
+        } else {
+            let v_number = v
+                .parse::<i32>()
+                .map_err(|err| Error::InvalidRequest(format!("error parsing number {v}: {err}")))?;
+            vec![v_number]
+        };
+        return matches_list(
+            operator,
+            &values,
+            &evaluation_constraint.value,
+            parse_numbers,
+        );
+    }
+
     if v.is_empty() {
         return Ok(false);
     }
@@ -630,9 +1172,23 @@ fn matches_boolean(
     }
 }
 
+#[derive(Deserialize)]
+struct TimeOfDayWindow {
+    start: String,
+    end: String,
+    timezone: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DayOfWeekWindow {
+    days: Vec<String>,
+    timezone: Option<String>,
+}
+
 fn matches_datetime(
     evaluation_constraint: &flipt::EvaluationConstraint,
     v: &str,
+    now: DateTime<Utc>,
 ) -> Result<bool, Error> {
     let operator = evaluation_constraint.operator.as_str();
 
@@ -643,6 +1199,21 @@ fn matches_datetime(
         "present" => {
             return Ok(!v.is_empty());
         }
+        // time of day and day of week operators fall back to the evaluation time
+        // when the property is absent from the context
+        "timeofday" | "dayofweek" => {
+            let instant = if v.is_empty() {
+                now
+            } else {
+                parse_datetime(v)?
+            };
+
+            return if operator == "timeofday" {
+                matches_time_of_day(&evaluation_constraint.value, instant)
+            } else {
+                matches_day_of_week(&evaluation_constraint.value, instant)
+            };
+        }
         _ => {}
     }
 
@@ -650,20 +1221,26 @@ fn matches_datetime(
         return Ok(false);
     }
 
-    let d = match DateTime::parse_from_rfc3339(v) {
-        Ok(t) => t.timestamp(),
-        Err(e) => Err(Error::InvalidRequest(format!(
-            "error parsing time {v}: {e}"
-        )))?,
-    };
+    let d = parse_datetime(v)?;
 
-    let value = match DateTime::parse_from_rfc3339(&evaluation_constraint.value) {
-        Ok(t) => t.timestamp(),
-        Err(e) => Err(Error::InvalidRequest(format!(
-            "error parsing time {}: {}",
-            &evaluation_constraint.value, e
-        )))?,
-    };
+    match operator {
+        "withinlast" => {
+            let start = shift_datetime(now, &evaluation_constraint.value, false)?;
+            return Ok(d <= now && d >= start);
+        }
+        "withinnext" => {
+            let end = shift_datetime(now, &evaluation_constraint.value, true)?;
+            return Ok(d >= now && d <= end);
+        }
+        "olderthan" => {
+            let start = shift_datetime(now, &evaluation_constraint.value, false)?;
+            return Ok(d < start);
+        }
+        _ => {}
+    }
+
+    let d = d.timestamp();
+    let value = parse_datetime(&evaluation_constraint.value)?.timestamp();
 
     match operator {
         "eq" => Ok(d == value),
@@ -676,11 +1253,144 @@ fn matches_datetime(
     }
 }
 
-#[cfg(test)]
+/// Parses an RFC3339 timestamp, falling back to timezone-less date-times and
+/// plain dates which are interpreted as UTC.
+fn parse_datetime(v: &str) -> Result<DateTime<Utc>, Error> {
+    let rfc3339_err = match DateTime::parse_from_rfc3339(v) {
+        Ok(t) => return Ok(t.with_timezone(&Utc)),
+        Err(e) => e,
+    };
+
+    for format in [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+    ] {
+        if let Ok(t) = NaiveDateTime::parse_from_str(v, format) {
+            return Ok(t.and_utc());
+        }
+    }
+
+    if let Ok(d) = NaiveDate::parse_from_str(v, "%Y-%m-%d") {
+        return Ok(d.and_time(NaiveTime::MIN).and_utc());
+    }
+
+    Err(Error::InvalidRequest(format!(
+        "error parsing time {v}: {rfc3339_err}"
+    )))
+}
+
+/// Parses a duration such as `30d`, `12h` or `1d12h30m` using the units
+/// `w`, `d`, `h`, `m` and `s`.
+fn parse_duration(v: &str) -> Result<TimeDelta, Error> {
+    let invalid = || Error::InvalidRequest(format!("error parsing duration {v}"));
+
+    let mut total = TimeDelta::zero();
+    let mut digits = String::new();
+
+    for c in v.trim().chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+
+        let amount: i64 = digits.parse().map_err(|_| invalid())?;
+        digits.clear();
+
+        let delta = match c {
+            'w' => TimeDelta::try_weeks(amount),
+            'd' => TimeDelta::try_days(amount),
+            'h' => TimeDelta::try_hours(amount),
+            'm' => TimeDelta::try_minutes(amount),
+            's' => TimeDelta::try_seconds(amount),
+            _ => None,
+        }
+        .ok_or_else(invalid)?;
+
+        total = total.checked_add(&delta).ok_or_else(invalid)?;
+    }
+
+    if !digits.is_empty() || total.is_zero() {
+        return Err(invalid());
+    }
+
+    Ok(total)
+}
+
+/// `now` moved forward or back by the duration `v`, or an error if that leaves the range of
+/// representable date-times.
+fn shift_datetime(now: DateTime<Utc>, v: &str, forward: bool) -> Result<DateTime<Utc>, Error> {
+    let duration = parse_duration(v)?;
+    let shifted = if forward {
+        now.checked_add_signed(duration)
+    } else {
+        now.checked_sub_signed(duration)
+    };
+
+    shifted.ok_or_else(|| Error::InvalidRequest(format!("duration {v} is out of range")))
+}
+
+fn parse_timezone(timezone: Option<&str>) -> Result<chrono_tz::Tz, Error> {
+    match timezone {
+        Some(name) => name
+            .parse::<chrono_tz::Tz>()
+            .map_err(|e| Error::InvalidRequest(format!("error parsing timezone {name}: {e}"))),
+        None => Ok(chrono_tz::UTC),
+    }
+}
+
+fn parse_time_of_day(v: &str) -> Result<NaiveTime, Error> {
+    NaiveTime::parse_from_str(v, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(v, "%H:%M"))
+        .map_err(|e| Error::InvalidRequest(format!("error parsing time of day {v}: {e}")))
+}
+
+fn matches_time_of_day(value: &str, instant: DateTime<Utc>) -> Result<bool, Error> {
+    let window: TimeOfDayWindow = serde_json::from_str(value).map_err(|e| {
+        Error::InvalidRequest(format!("error parsing time of day window {value}: {e}"))
+    })?;
+
+    let start = parse_time_of_day(&window.start)?;
+    let end = parse_time_of_day(&window.end)?;
+    let tz = parse_timezone(window.timezone.as_deref())?;
+
+    let time = instant.with_timezone(&tz).time();
+
+    // windows such as 22:00-06:00 wrap around midnight
+    if start <= end {
+        Ok(time >= start && time < end)
+    } else {
+        Ok(time >= start || time < end)
+    }
+}
+
+fn matches_day_of_week(value: &str, instant: DateTime<Utc>) -> Result<bool, Error> {
+    let window: DayOfWeekWindow = serde_json::from_str(value).map_err(|e| {
+        Error::InvalidRequest(format!("error parsing day of week window {value}: {e}"))
+    })?;
+
+    let tz = parse_timezone(window.timezone.as_deref())?;
+    let weekday = instant.with_timezone(&tz).weekday();
+
+    for day in &window.days {
+        let day = day
+            .parse::<Weekday>()
+            .map_err(|_| Error::InvalidRequest(format!("error parsing day of week {day}")))?;
+
+        if day == weekday {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
     use crate::models::flipt::RolloutSegment;
     use crate::store::MockStore;
+    use alloc::sync::Arc;
 
     macro_rules! matches_string_tests {
         ($($name:ident: $value:expr,)*) => {
@@ -694,13 +1404,20 @@ mod tests {
         }
     }
 
+    fn test_now() -> DateTime<Utc> {
+        // Wednesday 2006-01-04T12:00:00Z
+        DateTime::parse_from_rfc3339("2006-01-04T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
     macro_rules! matches_datetime_tests {
         ($($name:ident: $value:expr,)*) => {
         $(
             #[test]
             fn $name() {
                 let (first, second, expected) = $value;
-                assert_eq!(expected, matches_datetime(first, second).unwrap());
+                assert_eq!(expected, matches_datetime(first, second, test_now()).unwrap());
             }
         )*
         }
@@ -718,6 +1435,26 @@ mod tests {
         }
     }
 
+    macro_rules! matches_list_tests {
+        ($matches:ident: $($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (first, second, expected) = $value;
+                assert_eq!(expected, $matches(first, second));
+            }
+        )*
+        }
+    }
+
+    fn strings(values: &[&str]) -> ContextList {
+        ContextList::Strings(values.iter().map(|v| v.to_string()).collect())
+    }
+
+    fn numbers(values: &[i32]) -> ContextList {
+        ContextList::Numbers(values.to_vec())
+    }
+
     matches_string_tests! {
         string_eq: (&flipt::EvaluationConstraint{
             r#type: flipt::ConstraintComparisonType::String,
@@ -791,6 +1528,12 @@ mod tests {
             operator: String::from("notcontains"),
             value: String::from("num"),
         }, "number", false),
+        string_containsany_scalar: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::String,
+            property: String::from("roles"),
+            operator: String::from("containsany"),
+            value: String::from(r#"["admin"]"#),
+        }, r#"admin"#, true),
 
     }
 
@@ -831,6 +1574,102 @@ mod tests {
             operator: String::from("gte"),
             value: String::from("2006-01-02T15:04:05Z"),
         }, "2006-01-02T16:03:05Z", true),
+        datetime_eq_date_only: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::DateTime,
+            property: String::from("date"),
+            operator: String::from("eq"),
+            value: String::from(r#"2006-01-02"#),
+        }, "2006-01-02T00:00:00Z", true),
+        datetime_lt_timezone_less: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::DateTime,
+            property: String::from("date"),
+            operator: String::from("lt"),
+            value: String::from(r#"2006-01-02T15:04:05"#),
+        }, "2006-01-02T15:04:04Z", true),
+        datetime_gt_date_only_context: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::DateTime,
+            property: String::from("date"),
+            operator: String::from("gt"),
+            value: String::from(r#"2006-01-02T15:04:05Z"#),
+        }, "2006-01-03", true),
+        datetime_withinlast: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::DateTime,
+            property: String::from("date"),
+            operator: String::from("withinlast"),
+            value: String::from(r#"30d"#),
+        }, "2005-12-20T12:00:00Z", true),
+        datetime_withinlast_too_old: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::DateTime,
+            property: String::from("date"),
+            operator: String::from("withinlast"),
+            value: String::from(r#"30d"#),
+        }, "2005-11-20T12:00:00Z", false),
+        datetime_withinlast_future: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::DateTime,
+            property: String::from("date"),
+            operator: String::from("withinlast"),
+            value: String::from(r#"30d"#),
+        }, "2006-01-05T12:00:00Z", false),
+        datetime_withinnext: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::DateTime,
+            property: String::from("date"),
+            operator: String::from("withinnext"),
+            value: String::from(r#"1d12h"#),
+        }, "2006-01-05T23:00:00Z", true),
+        datetime_withinnext_too_far: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::DateTime,
+            property: String::from("date"),
+            operator: String::from("withinnext"),
+            value: String::from(r#"1d12h"#),
+        }, "2006-01-06T01:00:00Z", false),
+        datetime_olderthan: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::DateTime,
+            property: String::from("date"),
+            operator: String::from("olderthan"),
+            value: String::from(r#"2w"#),
+        }, "2005-12-01T00:00:00Z", true),
+        datetime_olderthan_recent: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::DateTime,
+            property: String::from("date"),
+            operator: String::from("olderthan"),
+            value: String::from(r#"2w"#),
+        }, "2006-01-01T00:00:00Z", false),
+        datetime_timeofday_now: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::DateTime,
+            property: String::from("date"),
+            operator: String::from("timeofday"),
+            value: String::from(r#"{"start":"09:00","end":"17:00","timezone":"Europe/Berlin"}"#),
+        }, "", true),
+        datetime_timeofday_context_outside: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::DateTime,
+            property: String::from("date"),
+            operator: String::from("timeofday"),
+            value: String::from(r#"{"start":"09:00","end":"17:00","timezone":"Europe/Berlin"}"#),
+        }, "2006-01-04T16:30:00Z", false),
+        datetime_timeofday_overnight: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::DateTime,
+            property: String::from("date"),
+            operator: String::from("timeofday"),
+            value: String::from(r#"{"start":"22:00","end":"06:00"}"#),
+        }, "2006-01-04T23:15:00Z", true),
+        datetime_dayofweek_now: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::DateTime,
+            property: String::from("date"),
+            operator: String::from("dayofweek"),
+            value: String::from(r#"{"days":["mon","tue","wed","thu","fri"]}"#),
+        }, "", true),
+        datetime_dayofweek_context: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::DateTime,
+            property: String::from("date"),
+            operator: String::from("dayofweek"),
+            value: String::from(r#"{"days":["sat","sun"]}"#),
+        }, "2006-01-07T10:00:00Z", true),
+        datetime_dayofweek_timezone: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::DateTime,
+            property: String::from("date"),
+            operator: String::from("dayofweek"),
+            value: String::from(r#"{"days":["tue"],"timezone":"America/New_York"}"#),
+        }, "2006-01-04T03:00:00Z", true),
 
     }
 
@@ -895,6 +1734,123 @@ mod tests {
             operator: String::from("isnotoneof"),
             value: String::from("[1, 2]"),
         }, "3", true),
+        number_containsany_scalar: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::Number,
+            property: String::from("roles"),
+            operator: String::from("containsany"),
+            value: String::from(r#"[4]"#),
+        }, r#"4"#, true),
+    }
+
+    matches_list_tests! {
+        matches_string_list:
+        string_containsany: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::String,
+            property: String::from("roles"),
+            operator: String::from("containsany"),
+            value: String::from(r#"["admin", "owner"]"#),
+        }, &strings(&["viewer", "admin"]), true),
+        string_containsany_absent: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::String,
+            property: String::from("roles"),
+            operator: String::from("containsany"),
+            value: String::from(r#"["admin", "owner"]"#),
+        }, &strings(&["viewer"]), false),
+        string_containsall: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::String,
+            property: String::from("roles"),
+            operator: String::from("containsall"),
+            value: String::from(r#"["admin", "owner"]"#),
+        }, &strings(&["owner", "viewer", "admin"]), true),
+        string_containsall_partial: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::String,
+            property: String::from("roles"),
+            operator: String::from("containsall"),
+            value: String::from(r#"["admin", "owner"]"#),
+        }, &strings(&["admin"]), false),
+        string_containsnone: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::String,
+            property: String::from("roles"),
+            operator: String::from("containsnone"),
+            value: String::from(r#"["banned"]"#),
+        }, &strings(&["viewer", "admin"]), true),
+        string_containsnone_present: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::String,
+            property: String::from("roles"),
+            operator: String::from("containsnone"),
+            value: String::from(r#"["banned"]"#),
+        }, &strings(&["banned"]), false),
+        string_sizegt: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::String,
+            property: String::from("roles"),
+            operator: String::from("sizegt"),
+            value: String::from(r#"1"#),
+        }, &strings(&["viewer", "admin"]), true),
+        string_sizeeq: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::String,
+            property: String::from("roles"),
+            operator: String::from("sizeeq"),
+            value: String::from(r#"2"#),
+        }, &strings(&["viewer", "admin"]), true),
+        string_containsany_invalid_value: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::String,
+            property: String::from("roles"),
+            operator: String::from("containsany"),
+            value: String::from(r#"not json"#),
+        }, &strings(&["admin"]), false),
+        string_sizelt_empty_list: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::String,
+            property: String::from("roles"),
+            operator: String::from("sizelt"),
+            value: String::from(r#"1"#),
+        }, &strings(&[]), true),
+    }
+
+    fn matches_number_list_ok(
+        constraint: &flipt::EvaluationConstraint,
+        list: &ContextList,
+    ) -> bool {
+        matches_number_list(constraint, list).unwrap()
+    }
+
+    matches_list_tests! {
+        matches_number_list_ok:
+        number_sizelt: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::Number,
+            property: String::from("roles"),
+            operator: String::from("sizelt"),
+            value: String::from(r#"2"#),
+        }, &numbers(&[1, 2, 3]), false),
+        number_containsany: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::Number,
+            property: String::from("roles"),
+            operator: String::from("containsany"),
+            value: String::from(r#"[1, 2]"#),
+        }, &numbers(&[2, 3]), true),
+        number_containsall: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::Number,
+            property: String::from("roles"),
+            operator: String::from("containsall"),
+            value: String::from(r#"[1, 2]"#),
+        }, &numbers(&[3, 2, 1]), true),
+        number_containsall_partial: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::Number,
+            property: String::from("roles"),
+            operator: String::from("containsall"),
+            value: String::from(r#"[1, 2]"#),
+        }, &numbers(&[1]), false),
+        number_containsnone: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::Number,
+            property: String::from("roles"),
+            operator: String::from("containsnone"),
+            value: String::from(r#"[4]"#),
+        }, &numbers(&[1, 2]), true),
+        number_sizegt: (&flipt::EvaluationConstraint{
+            r#type: flipt::ConstraintComparisonType::Number,
+            property: String::from("roles"),
+            operator: String::from("sizegt"),
+            value: String::from(r#"2"#),
+        }, &numbers(&[1, 2, 3]), true),
     }
 
     #[test]
@@ -990,6 +1946,7 @@ mod tests {
                 value: String::from("blah"),
             },
             "2006-01-02T15:04:05Z",
+            test_now(),
         );
 
         assert!(result_one.is_err());
@@ -1007,6 +1964,7 @@ mod tests {
                 value: String::from("2006-01-02T15:04:05Z"),
             },
             "blah",
+            test_now(),
         );
 
         assert!(result_two.is_err());
@@ -1017,6 +1975,219 @@ mod tests {
             .contains("invalid request: error parsing time blah: "));
     }
 
+    #[test]
+    fn test_matches_datetime_invalid_duration() {
+        let result = matches_datetime(
+            &flipt::EvaluationConstraint {
+                r#type: flipt::ConstraintComparisonType::DateTime,
+                property: String::from("date"),
+                operator: String::from("withinlast"),
+                value: String::from("30x"),
+            },
+            "2006-01-02T15:04:05Z",
+            test_now(),
+        );
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "invalid request: error parsing duration 30x"
+        );
+    }
+
+    #[test]
+    fn test_matches_datetime_overflowing_duration() {
+        for operator in ["withinlast", "withinnext", "olderthan"] {
+            let result = matches_datetime(
+                &flipt::EvaluationConstraint {
+                    r#type: flipt::ConstraintComparisonType::DateTime,
+                    property: String::from("date"),
+                    operator: String::from(operator),
+                    value: String::from("100000000w"),
+                },
+                "2006-01-02T15:04:05Z",
+                test_now(),
+            );
+
+            assert_eq!(
+                result.err().unwrap().to_string(),
+                "invalid request: duration 100000000w is out of range"
+            );
+        }
+    }
+
+    #[test]
+    fn test_evaluation_with_fixed_clock() {
+        let mut mock_store = MockStore::new();
+
+        mock_store.expect_get_flag().returning(|_, _| {
+            Some(flipt::Flag {
+                key: String::from("foo"),
+                enabled: true,
+                description: None,
+                r#type: flipt::FlagType::Boolean,
+                default_variant: None,
+            })
+        });
+
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
+        segments.insert(
+            String::from("new_accounts"),
+            Arc::new(flipt::EvaluationSegment {
+                segment_key: String::from("new_accounts"),
+                match_type: flipt::SegmentMatchType::All,
+                constraints: vec![flipt::EvaluationConstraint {
+                    r#type: flipt::ConstraintComparisonType::DateTime,
+                    property: String::from("created_at"),
+                    operator: String::from("withinlast"),
+                    value: String::from("30d"),
+                }],
+            }),
+        );
+
+        mock_store
+            .expect_get_evaluation_rollouts()
+            .returning(move |_, _| {
+                Some(vec![flipt::EvaluationRollout {
+                    rollout_type: flipt::RolloutType::Segment,
+                    rank: 1,
+                    segment: Some(RolloutSegment {
+                        value: false,
+                        segment_operator: flipt::SegmentOperator::Or,
+                        segments: segments.clone(),
+                    }),
+                    threshold: None,
+                }])
+            });
+
+        let request = EvaluationRequest {
+            flag_key: String::from("foo"),
+            entity_id: String::from("1"),
+            context: HashMap::from([(String::from("created_at"), String::from("2006-01-01"))]),
+            list_context: HashMap::new(),
+        };
+
+        let b = boolean_evaluation_with_clock(
+            &mock_store,
+            "default",
+            &request,
+            &clock::FixedClock(test_now()),
+        )
+        .unwrap();
+
+        assert!(!b.enabled);
+        assert_eq!(b.reason, flipt::EvaluationReason::Match);
+        assert_eq!(b.timestamp, test_now());
+
+        let later = test_now() + TimeDelta::days(60);
+        let b = boolean_evaluation_with_clock(
+            &mock_store,
+            "default",
+            &request,
+            &clock::FixedClock(later),
+        )
+        .unwrap();
+
+        assert!(b.enabled);
+        assert_eq!(b.reason, flipt::EvaluationReason::Default);
+    }
+
+    #[test]
+    fn test_basis_point_resolution_preserves_assignments() {
+        for i in 0..10_000 {
+            let entity_id = format!("entity-{i}");
+
+            for percentage in [0.0, 1.0, 25.0, 50.0, 99.0, 100.0] {
+                assert_eq!(
+                    BucketResolution::Percent.matches_threshold(&entity_id, "foo", percentage),
+                    BucketResolution::BasisPoint.matches_threshold(&entity_id, "foo", percentage),
+                );
+            }
+
+            let percent = BucketResolution::Percent.distribution_bucket("foo", &entity_id);
+            let basis_point = BucketResolution::BasisPoint.distribution_bucket("foo", &entity_id);
+            assert_eq!(percent, basis_point / 10);
+        }
+    }
+
+    #[test]
+    fn test_basis_point_threshold() {
+        let enabled = |resolution: BucketResolution, percentage: f32| {
+            (0..100_000)
+                .filter(|i| resolution.matches_threshold(&format!("entity-{i}"), "foo", percentage))
+                .count()
+        };
+
+        // 0.05% is rounded up to a whole percent bucket at the default resolution
+        assert!(enabled(BucketResolution::Percent, 0.05) > 500);
+
+        let canary = enabled(BucketResolution::BasisPoint, 0.05);
+        assert!((20..=80).contains(&canary), "{canary} entities enabled");
+    }
+
+    #[test]
+    fn test_variant_evaluation_with_basis_point_resolution() {
+        let mut mock_store = MockStore::new();
+
+        mock_store.expect_get_flag().returning(|_, _| {
+            Some(flipt::Flag {
+                key: String::from("foo"),
+                enabled: true,
+                description: None,
+                r#type: flipt::FlagType::Variant,
+                default_variant: None,
+            })
+        });
+
+        mock_store.expect_get_evaluation_rules().returning(|_, _| {
+            Some(vec![flipt::EvaluationRule {
+                id: String::from("1"),
+                flag_key: String::from("foo"),
+                rank: 1,
+                segments: HashMap::new(),
+                segment_operator: flipt::SegmentOperator::Or,
+            }])
+        });
+
+        mock_store
+            .expect_get_evaluation_distributions()
+            .returning(|_, _| {
+                Some(vec![
+                    flipt::EvaluationDistribution {
+                        rule_id: String::from("1"),
+                        rollout: 12.5,
+                        variant_key: String::from("a"),
+                        variant_attachment: None,
+                    },
+                    flipt::EvaluationDistribution {
+                        rule_id: String::from("1"),
+                        rollout: 87.5,
+                        variant_key: String::from("b"),
+                        variant_attachment: None,
+                    },
+                ])
+            });
+
+        let options = EvaluationOptions {
+            resolution: BucketResolution::BasisPoint,
+            ..Default::default()
+        };
+
+        for i in 0..1_000 {
+            let request = EvaluationRequest {
+                flag_key: String::from("foo"),
+                entity_id: format!("entity-{i}"),
+                context: HashMap::new(),
+                list_context: HashMap::new(),
+            };
+
+            let percent = variant_evaluation(&mock_store, "default", &request).unwrap();
+            let basis_point =
+                variant_evaluation_with_options(&mock_store, "default", &request, &options)
+                    .unwrap();
+            assert_eq!(percent.variant_key, basis_point.variant_key);
+        }
+    }
+
     #[test]
     fn test_entity_id_match() {
         let mut mock_store = MockStore::new();
@@ -1031,10 +2202,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::All,
                 constraints: vec![flipt::EvaluationConstraint {
@@ -1043,7 +2214,7 @@ mod tests {
                     operator: String::from("eq"),
                     value: String::from("user@flipt.io"),
                 }],
-            },
+            }),
         );
 
         mock_store
@@ -1071,6 +2242,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("user@flipt.io"),
                 context,
+                list_context: HashMap::new(),
             },
         );
         assert!(variant.is_ok());
@@ -1108,6 +2280,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("entity"),
                 context,
+                list_context: HashMap::new(),
             },
         );
 
@@ -1151,6 +2324,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("entity"),
                 context,
+                list_context: HashMap::new(),
             },
         );
 
@@ -1183,10 +2357,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::All,
                 constraints: vec![
@@ -1203,7 +2377,7 @@ mod tests {
                         value: String::from("bar"),
                     },
                 ],
-            },
+            }),
         );
 
         mock_store
@@ -1233,6 +2407,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("entity"),
                 context,
+                list_context: HashMap::new(),
             },
         );
         assert!(variant.is_ok());
@@ -1263,10 +2438,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::All,
                 constraints: vec![
@@ -1283,7 +2458,7 @@ mod tests {
                         value: String::from("bar"),
                     },
                 ],
-            },
+            }),
         );
 
         mock_store
@@ -1313,6 +2488,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("entity"),
                 context,
+                list_context: HashMap::new(),
             },
         );
         assert!(variant.is_ok());
@@ -1344,10 +2520,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::All,
                 constraints: vec![
@@ -1364,12 +2540,12 @@ mod tests {
                         value: String::from("bar"),
                     },
                 ],
-            },
+            }),
         );
 
         segments.insert(
             String::from("segment2"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment2"),
                 match_type: flipt::SegmentMatchType::All,
                 constraints: vec![flipt::EvaluationConstraint {
@@ -1378,7 +2554,7 @@ mod tests {
                     operator: String::from("eq"),
                     value: String::from("flipt"),
                 }],
-            },
+            }),
         );
 
         mock_store
@@ -1409,6 +2585,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("entity"),
                 context,
+                list_context: HashMap::new(),
             },
         );
 
@@ -1430,6 +2607,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("entity"),
                 context,
+                list_context: HashMap::new(),
             },
         );
 
@@ -1441,6 +2619,9 @@ mod tests {
         assert!(!v.r#match);
         assert_eq!(v.reason, flipt::EvaluationReason::Unknown);
         assert!(v.segment_keys.is_empty());
+        assert_eq!(v.rule_id, None);
+        assert_eq!(v.rule_rank, None);
+        assert_eq!(v.distribution_index, None);
     }
 
     #[test]
@@ -1457,10 +2638,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::All,
                 constraints: vec![
@@ -1477,11 +2658,11 @@ mod tests {
                         value: String::from("bar"),
                     },
                 ],
-            },
+            }),
         );
         segments.insert(
             String::from("segment2"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment2"),
                 match_type: flipt::SegmentMatchType::All,
                 constraints: vec![flipt::EvaluationConstraint {
@@ -1490,7 +2671,7 @@ mod tests {
                     operator: String::from("true"),
                     value: String::from(""),
                 }],
-            },
+            }),
         );
 
         mock_store
@@ -1528,6 +2709,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("123"),
                 context,
+                list_context: HashMap::new(),
             },
         );
 
@@ -1555,10 +2737,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::All,
                 constraints: vec![flipt::EvaluationConstraint {
@@ -1567,7 +2749,7 @@ mod tests {
                     operator: String::from("eq"),
                     value: String::from("baz"),
                 }],
-            },
+            }),
         );
 
         mock_store
@@ -1621,6 +2803,7 @@ mod tests {
                     flag_key: String::from("foo"),
                     entity_id: entity_id.clone(),
                     context: context.clone(),
+                    list_context: HashMap::new(),
                 },
             );
             assert!(variant.is_ok(), "entity_id {} should evaluate", entity_id);
@@ -1659,10 +2842,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::All,
                 constraints: vec![flipt::EvaluationConstraint {
@@ -1671,7 +2854,7 @@ mod tests {
                     operator: String::from("eq"),
                     value: String::from("baz"),
                 }],
-            },
+            }),
         );
 
         mock_store
@@ -1728,6 +2911,7 @@ mod tests {
                     flag_key: String::from("bar"),
                     entity_id: entity_id.clone(),
                     context: context.clone(),
+                    list_context: HashMap::new(),
                 },
             );
             assert!(variant.is_ok(), "entity_id {} should evaluate", entity_id);
@@ -1781,10 +2965,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::All,
                 constraints: vec![flipt::EvaluationConstraint {
@@ -1793,7 +2977,7 @@ mod tests {
                     operator: String::from("eq"),
                     value: String::from("baz"),
                 }],
-            },
+            }),
         );
 
         mock_store
@@ -1843,6 +3027,7 @@ mod tests {
                     flag_key: String::from("baz"),
                     entity_id: entity_id.clone(),
                     context: context.clone(),
+                    list_context: HashMap::new(),
                 },
             );
             assert!(variant.is_ok(), "entity_id {} should evaluate", entity_id);
@@ -1890,10 +3075,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::All,
                 constraints: vec![flipt::EvaluationConstraint {
@@ -1902,7 +3087,7 @@ mod tests {
                     operator: String::from("eq"),
                     value: String::from("baz"),
                 }],
-            },
+            }),
         );
 
         mock_store
@@ -1952,6 +3137,7 @@ mod tests {
                     flag_key: String::from("qux"),
                     entity_id: entity_id.clone(),
                     context: context.clone(),
+                    list_context: HashMap::new(),
                 },
             );
             assert!(variant.is_ok(), "entity_id {} should evaluate", entity_id);
@@ -1999,10 +3185,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::All,
                 constraints: vec![
@@ -2019,11 +3205,11 @@ mod tests {
                         value: String::from("bar"),
                     },
                 ],
-            },
+            }),
         );
         segments.insert(
             String::from("segment2"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment2"),
                 match_type: flipt::SegmentMatchType::All,
                 constraints: vec![flipt::EvaluationConstraint {
@@ -2032,7 +3218,7 @@ mod tests {
                     operator: String::from("true"),
                     value: String::from(""),
                 }],
-            },
+            }),
         );
 
         mock_store
@@ -2070,6 +3256,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("123"),
                 context,
+                list_context: HashMap::new(),
             },
         );
 
@@ -2085,14 +3272,10 @@ mod tests {
             v.variant_attachment,
             Some(String::from(r#"{"foo": "bar"}"#))
         );
-        assert!(v
-            .segment_keys
-            .iter()
-            .any(|segment_key| segment_key == "segment1"),);
-        assert!(v
-            .segment_keys
-            .iter()
-            .any(|segment_key| segment_key == "segment2"),);
+        assert_eq!(
+            v.segment_keys,
+            vec![String::from("segment1"), String::from("segment2")]
+        );
     }
 
     #[test]
@@ -2109,10 +3292,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::All,
                 constraints: vec![
@@ -2129,7 +3312,7 @@ mod tests {
                         value: String::from("bar"),
                     },
                 ],
-            },
+            }),
         );
 
         mock_store
@@ -2174,6 +3357,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("1"),
                 context: context.clone(),
+                list_context: HashMap::new(),
             },
         );
 
@@ -2194,6 +3378,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("2"),
                 context,
+                list_context: HashMap::new(),
             },
         );
 
@@ -2222,10 +3407,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::All,
                 constraints: vec![
@@ -2242,17 +3427,17 @@ mod tests {
                         value: String::from("bar"),
                     },
                 ],
-            },
+            }),
         );
 
-        let mut segments_two: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments_two: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments_two.insert(
             String::from("segment2"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment2"),
                 match_type: flipt::SegmentMatchType::All,
                 constraints: vec![],
-            },
+            }),
         );
 
         mock_store
@@ -2306,6 +3491,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("1"),
                 context: context.clone(),
+                list_context: HashMap::new(),
             },
         );
 
@@ -2334,14 +3520,14 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::All,
                 constraints: vec![],
-            },
+            }),
         );
 
         mock_store
@@ -2384,6 +3570,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("10"),
                 context: context.clone(),
+                list_context: HashMap::new(),
             },
         );
 
@@ -2404,6 +3591,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("01"),
                 context,
+                list_context: HashMap::new(),
             },
         );
 
@@ -2433,10 +3621,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::Any,
                 constraints: vec![
@@ -2453,7 +3641,7 @@ mod tests {
                         value: String::from("bar"),
                     },
                 ],
-            },
+            }),
         );
 
         mock_store
@@ -2482,6 +3670,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("entity"),
                 context,
+                list_context: HashMap::new(),
             },
         );
 
@@ -2513,10 +3702,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::Any,
                 constraints: vec![
@@ -2533,7 +3722,7 @@ mod tests {
                         value: String::from("bar"),
                     },
                 ],
-            },
+            }),
         );
 
         mock_store
@@ -2562,6 +3751,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("entity"),
                 context,
+                list_context: HashMap::new(),
             },
         );
 
@@ -2594,10 +3784,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::Any,
                 constraints: vec![
@@ -2614,11 +3804,11 @@ mod tests {
                         value: String::from("bar"),
                     },
                 ],
-            },
+            }),
         );
         segments.insert(
             String::from("segment2"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment2"),
                 match_type: flipt::SegmentMatchType::Any,
                 constraints: vec![flipt::EvaluationConstraint {
@@ -2627,7 +3817,7 @@ mod tests {
                     operator: String::from("eq"),
                     value: String::from("flipt"),
                 }],
-            },
+            }),
         );
 
         mock_store
@@ -2657,6 +3847,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("entity"),
                 context,
+                list_context: HashMap::new(),
             },
         );
 
@@ -2678,6 +3869,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("entity"),
                 context,
+                list_context: HashMap::new(),
             },
         );
 
@@ -2705,10 +3897,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::Any,
                 constraints: vec![
@@ -2725,11 +3917,11 @@ mod tests {
                         value: String::from("bar"),
                     },
                 ],
-            },
+            }),
         );
         segments.insert(
             String::from("segment2"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment2"),
                 match_type: flipt::SegmentMatchType::Any,
                 constraints: vec![flipt::EvaluationConstraint {
@@ -2738,7 +3930,7 @@ mod tests {
                     operator: String::from("true"),
                     value: String::from(""),
                 }],
-            },
+            }),
         );
 
         mock_store
@@ -2775,6 +3967,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("123"),
                 context,
+                list_context: HashMap::new(),
             },
         );
 
@@ -2802,10 +3995,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::Any,
                 constraints: vec![
@@ -2822,11 +4015,11 @@ mod tests {
                         value: String::from("bar"),
                     },
                 ],
-            },
+            }),
         );
         segments.insert(
             String::from("segment2"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment2"),
                 match_type: flipt::SegmentMatchType::Any,
                 constraints: vec![flipt::EvaluationConstraint {
@@ -2835,7 +4028,7 @@ mod tests {
                     operator: String::from("true"),
                     value: String::from(""),
                 }],
-            },
+            }),
         );
 
         mock_store
@@ -2872,6 +4065,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("123"),
                 context,
+                list_context: HashMap::new(),
             },
         );
 
@@ -2903,10 +4097,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::Any,
                 constraints: vec![
@@ -2923,7 +4117,7 @@ mod tests {
                         value: String::from("bar"),
                     },
                 ],
-            },
+            }),
         );
 
         mock_store
@@ -2967,6 +4161,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("1"),
                 context: context.clone(),
+                list_context: HashMap::new(),
             },
         );
 
@@ -2987,6 +4182,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("2"),
                 context,
+                list_context: HashMap::new(),
             },
         );
 
@@ -3015,10 +4211,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::Any,
                 constraints: vec![
@@ -3035,17 +4231,17 @@ mod tests {
                         value: String::from("bar"),
                     },
                 ],
-            },
+            }),
         );
 
-        let mut segments_two: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments_two: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments_two.insert(
             String::from("segment2"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment2"),
                 match_type: flipt::SegmentMatchType::Any,
                 constraints: vec![],
-            },
+            }),
         );
 
         mock_store
@@ -3098,6 +4294,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("1"),
                 context: context.clone(),
+                list_context: HashMap::new(),
             },
         );
 
@@ -3126,14 +4323,14 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::Any,
                 constraints: vec![],
-            },
+            }),
         );
 
         mock_store
@@ -3176,6 +4373,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("10"),
                 context: context.clone(),
+                list_context: HashMap::new(),
             },
         );
 
@@ -3196,6 +4394,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("01"),
                 context: context.clone(),
+                list_context: HashMap::new(),
             },
         );
 
@@ -3218,6 +4417,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("01"),
                 context,
+                list_context: HashMap::new(),
             },
         );
 
@@ -3247,10 +4447,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::Any,
                 constraints: vec![flipt::EvaluationConstraint {
@@ -3259,7 +4459,7 @@ mod tests {
                     operator: String::from("eq"),
                     value: String::from("baz"),
                 }],
-            },
+            }),
         );
 
         mock_store
@@ -3303,6 +4503,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("1"),
                 context: context.clone(),
+                list_context: HashMap::new(),
             },
         );
 
@@ -3331,10 +4532,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::Any,
                 constraints: vec![flipt::EvaluationConstraint {
@@ -3343,7 +4544,7 @@ mod tests {
                     operator: String::from("eq"),
                     value: String::from("baz"),
                 }],
-            },
+            }),
         );
 
         mock_store
@@ -3411,6 +4612,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("1"),
                 context: context.clone(),
+                list_context: HashMap::new(),
             },
         );
 
@@ -3423,6 +4625,10 @@ mod tests {
         assert_eq!(v.reason, flipt::EvaluationReason::Match);
         assert_eq!(v.variant_key, String::from("variant3"));
         assert_eq!(v.segment_keys, vec![String::from("segment1")]);
+        assert_eq!(v.rule_id, Some(String::from("1")));
+        assert_eq!(v.rule_rank, Some(1));
+        // index among all distributions of the rule, including zero rollouts
+        assert_eq!(v.distribution_index, Some(2));
     }
 
     #[test]
@@ -3439,10 +4645,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::Any,
                 constraints: vec![flipt::EvaluationConstraint {
@@ -3451,7 +4657,7 @@ mod tests {
                     operator: String::from("notpresent"),
                     value: String::from(""),
                 }],
-            },
+            }),
         );
 
         mock_store
@@ -3476,6 +4682,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("1"),
                 context: HashMap::new(),
+                list_context: HashMap::new(),
             },
         );
 
@@ -3502,10 +4709,10 @@ mod tests {
             })
         });
 
-        let mut segments: HashMap<String, flipt::EvaluationSegment> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
         segments.insert(
             String::from("segment1"),
-            flipt::EvaluationSegment {
+            Arc::new(flipt::EvaluationSegment {
                 segment_key: String::from("segment1"),
                 match_type: flipt::SegmentMatchType::Any,
                 constraints: vec![flipt::EvaluationConstraint {
@@ -3514,7 +4721,7 @@ mod tests {
                     operator: String::from("present"),
                     value: String::from(""),
                 }],
-            },
+            }),
         );
 
         mock_store
@@ -3542,6 +4749,7 @@ mod tests {
                 flag_key: String::from("foo"),
                 entity_id: String::from("1"),
                 context,
+                list_context: HashMap::new(),
             },
         );
 
@@ -3553,6 +4761,8 @@ mod tests {
         assert!(b.enabled);
         assert_eq!(b.reason, flipt::EvaluationReason::Match);
         assert_eq!(b.segment_keys, vec![String::from("segment1")]);
+        assert_eq!(b.rollout_rank, Some(1));
+        assert_eq!(b.rollout_type, Some(flipt::RolloutType::Segment));
     }
 
     #[test]
@@ -3575,14 +4785,117 @@ mod tests {
         ];
         let result = matches_constraints(
             &eval_context,
+            &HashMap::new(),
             &constraints,
             &flipt::SegmentMatchType::Any,
             "",
+            Utc::now(),
         );
         assert!(result.is_ok());
         assert!(result.unwrap());
     }
 
+    #[test]
+    fn test_matches_number_list_failure() {
+        let result = matches_number_list(
+            &flipt::EvaluationConstraint {
+                r#type: flipt::ConstraintComparisonType::Number,
+                property: String::from("scores"),
+                operator: String::from("containsany"),
+                value: String::from("[1]"),
+            },
+            &ContextList::Strings(vec![String::from("one")]),
+        );
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "invalid request: scores is not a list of numbers"
+        );
+    }
+
+    #[test]
+    fn test_evaluation_request_list_context() {
+        let json = r#"{"flag_key":"foo","entity_id":"1","context":{"plan":"pro","quoted":"[\"admin\"]","roles":["admin","viewer"],"scores":[1,2],"none":[]}}"#;
+        let request: EvaluationRequest = serde_json::from_str(json).unwrap();
+
+        assert_eq!(request.context.len(), 2);
+        assert_eq!(request.context.get("plan").unwrap(), "pro");
+        assert_eq!(request.context.get("quoted").unwrap(), r#"["admin"]"#);
+        assert_eq!(
+            request.list_context.get("roles").unwrap(),
+            &ContextList::Strings(vec![String::from("admin"), String::from("viewer")])
+        );
+        assert_eq!(
+            request.list_context.get("scores").unwrap(),
+            &ContextList::Numbers(vec![1, 2])
+        );
+        assert!(request.list_context.get("none").unwrap().is_empty());
+
+        let serialized = serde_json::to_string(&request).unwrap();
+        assert_eq!(
+            serde_json::from_str::<EvaluationRequest>(&serialized).unwrap(),
+            request
+        );
+
+        // lists mixing element types are rejected rather than read as a string
+        assert!(serde_json::from_str::<EvaluationRequest>(
+            r#"{"flag_key":"foo","entity_id":"1","context":{"roles":["admin",1]}}"#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_matches_list_context() {
+        let request: EvaluationRequest = serde_json::from_str(
+            r#"{"flag_key":"foo","entity_id":"1","context":{"quoted":"[\"admin\"]","roles":["admin","viewer"],"scores":[1,2],"none":[]}}"#,
+        )
+        .unwrap();
+
+        let matches = |r#type, property: &str, operator: &str, value: &str| {
+            let constraints = vec![flipt::EvaluationConstraint {
+                r#type,
+                property: String::from(property),
+                operator: String::from(operator),
+                value: String::from(value),
+            }];
+            matches_constraints(
+                &request.context,
+                &request.list_context,
+                &constraints,
+                &flipt::SegmentMatchType::All,
+                "",
+                Utc::now(),
+            )
+            .unwrap()
+        };
+
+        use flipt::ConstraintComparisonType::{Number, String as Str};
+        assert!(matches(
+            Str,
+            "roles",
+            "containsall",
+            r#"["viewer", "admin"]"#
+        ));
+        assert!(matches(Str, "roles", "notempty", ""));
+        assert!(!matches(Str, "roles", "eq", "admin"));
+        assert!(matches(Number, "scores", "containsany", "[2, 3]"));
+        assert!(matches(Number, "scores", "sizegt", "1"));
+        // the element types of the list and the constraint differ
+        assert!(!matches(Str, "scores", "containsany", r#"["1"]"#));
+        assert!(!matches(Number, "roles", "sizeeq", "2"));
+        // a string is not parsed as a list
+        assert!(!matches(Str, "quoted", "containsany", r#"["admin"]"#));
+        assert!(matches(Str, "quoted", "sizeeq", "1"));
+        // empty and absent properties are empty lists
+        for property in ["none", "absent"] {
+            assert!(matches(Str, property, "empty", ""));
+            assert!(matches(Str, property, "containsnone", r#"["admin"]"#));
+            assert!(matches(Str, property, "sizeeq", "0"));
+            assert!(matches(Number, property, "sizelt", "1"));
+            assert!(!matches(Str, property, "containsany", r#"["admin"]"#));
+        }
+    }
+
     #[test]
     fn test_evaluator_matches_constraint_with_unknown_operator() {
         let eval_context: HashMap<String, String> =
@@ -3595,11 +4908,197 @@ mod tests {
         }];
         let result = matches_constraints(
             &eval_context,
+            &HashMap::new(),
             &constraints,
             &flipt::SegmentMatchType::Any,
             "",
+            Utc::now(),
         );
         assert!(result.is_ok());
         assert!(!result.unwrap());
     }
+
+    #[test]
+    fn test_batch_evaluation_segment_matches_per_context() {
+        let document: models::source::Document =
+            serde_json::from_str(include_str!("testdata/state.json")).unwrap();
+        let snapshot = models::snapshot::Snapshot::build(document);
+
+        let request = |fizz: &str| EvaluationRequest {
+            flag_key: String::from("flag1"),
+            entity_id: String::from("entity"),
+            context: HashMap::from([(String::from("fizz"), String::from(fizz))]),
+            list_context: HashMap::new(),
+        };
+
+        let batch = batch_evaluation(
+            &snapshot,
+            "default",
+            vec![
+                request("buzz"),
+                request("buzz"),
+                request("other"),
+                request("buzz"),
+            ],
+        )
+        .unwrap();
+
+        let matched: Vec<bool> = batch
+            .responses
+            .iter()
+            .map(|r| r.variant_evaluation_response.as_ref().unwrap().r#match)
+            .collect();
+        assert_eq!(matched, vec![true, true, false, true]);
+    }
+}
+
+/// Evaluation results pinned from the `std` build. These tests also run without `std`, so the
+/// `alloc` build, which rounds with `libm` and hashes with `hashbrown`, must agree with them.
+#[cfg(test)]
+mod parity_tests {
+    use super::*;
+    use crate::clock::FixedClock;
+    use crate::models::snapshot::Snapshot;
+
+    fn snapshot() -> Snapshot {
+        serde_json::from_value(serde_json::json!({
+            "version": 2,
+            "namespace": {
+                "key": "default",
+                "flags": {
+                    "split": { "key": "split", "enabled": true, "type": "VARIANT_FLAG_TYPE" },
+                    "canary": { "key": "canary", "enabled": false, "type": "BOOLEAN_FLAG_TYPE" }
+                },
+                "segments": {
+                    "recent-pro": {
+                        "segment_key": "recent-pro",
+                        "match_type": "ALL_SEGMENT_MATCH_TYPE",
+                        "constraints": [
+                            {
+                                "type": "STRING_CONSTRAINT_COMPARISON_TYPE",
+                                "property": "plan",
+                                "operator": "isoneof",
+                                "value": "[\"pro\",\"enterprise\"]"
+                            },
+                            {
+                                "type": "NUMBER_CONSTRAINT_COMPARISON_TYPE",
+                                "property": "seats",
+                                "operator": "gte",
+                                "value": "10"
+                            },
+                            {
+                                "type": "DATETIME_CONSTRAINT_COMPARISON_TYPE",
+                                "property": "signed_up",
+                                "operator": "withinlast",
+                                "value": "30d"
+                            }
+                        ]
+                    }
+                },
+                "eval_rules": {
+                    "split": [{
+                        "id": "rule1",
+                        "flag_key": "split",
+                        "segment_keys": ["recent-pro"],
+                        "rank": 1,
+                        "segment_operator": "OR_SEGMENT_OPERATOR"
+                    }]
+                },
+                "eval_rollouts": {
+                    "canary": [{
+                        "rollout_type": "THRESHOLD_ROLLOUT_TYPE",
+                        "rank": 1,
+                        "threshold": { "percentage": 33.35, "value": true }
+                    }]
+                },
+                "eval_distributions": {
+                    "rule1": [
+                        { "rule_id": "rule1", "rollout": 33.35, "variant_key": "a" },
+                        { "rule_id": "rule1", "rollout": 33.3, "variant_key": "b" },
+                        { "rule_id": "rule1", "rollout": 33.35, "variant_key": "c" }
+                    ]
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    fn request(flag_key: &str, i: usize) -> EvaluationRequest {
+        let mut context = HashMap::new();
+        context.insert("plan".to_string(), "pro".to_string());
+        context.insert("seats".to_string(), "12".to_string());
+        context.insert("signed_up".to_string(), "2006-01-01T00:00:00Z".to_string());
+
+        EvaluationRequest {
+            flag_key: flag_key.to_string(),
+            entity_id: format!("entity-{i}"),
+            context,
+            list_context: HashMap::new(),
+        }
+    }
+
+    fn counts(resolution: BucketResolution) -> (Vec<(String, usize)>, usize) {
+        let snapshot = snapshot();
+        let clock = FixedClock(
+            DateTime::parse_from_rfc3339("2006-01-04T12:00:00Z")
+                .unwrap()
+                .with_timezone(&Utc),
+        );
+        let options = EvaluationOptions {
+            clock: &clock,
+            resolution,
+        };
+
+        let mut variants: Vec<(String, usize)> = vec![];
+        let mut enabled = 0;
+        for i in 0..1000 {
+            let variant = variant_evaluation_with_options(
+                &snapshot,
+                "default",
+                &request("split", i),
+                &options,
+            )
+            .unwrap();
+            assert!(variant.r#match);
+            match variants
+                .iter_mut()
+                .find(|(key, _)| *key == variant.variant_key)
+            {
+                Some((_, count)) => *count += 1,
+                None => variants.push((variant.variant_key, 1)),
+            }
+
+            let boolean = boolean_evaluation_with_options(
+                &snapshot,
+                "default",
+                &request("canary", i),
+                &options,
+            )
+            .unwrap();
+            enabled += usize::from(boolean.enabled);
+        }
+
+        variants.sort();
+        (variants, enabled)
+    }
+
+    #[test]
+    fn test_percent_resolution() {
+        let (variants, enabled) = counts(BucketResolution::Percent);
+        assert_eq!(
+            variants,
+            vec![("a".into(), 344), ("b".into(), 325), ("c".into(), 331)]
+        );
+        assert_eq!(enabled, 324);
+    }
+
+    #[test]
+    fn test_basis_point_resolution() {
+        let (variants, enabled) = counts(BucketResolution::BasisPoint);
+        assert_eq!(
+            variants,
+            vec![("a".into(), 344), ("b".into(), 325), ("c".into(), 331)]
+        );
+        assert_eq!(enabled, 320);
+    }
 }
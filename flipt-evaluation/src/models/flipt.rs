@@ -1,5 +1,9 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+
+use crate::HashMap;
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Flag {
@@ -33,7 +37,7 @@ pub struct Constraint {
 pub struct EvaluationRule {
     pub id: String,
     pub flag_key: String,
-    pub segments: HashMap<String, EvaluationSegment>,
+    pub segments: HashMap<String, Arc<EvaluationSegment>>,
     pub rank: usize,
     pub segment_operator: SegmentOperator,
 }
@@ -67,7 +71,7 @@ pub struct RolloutThreshold {
 pub struct RolloutSegment {
     pub value: bool,
     pub segment_operator: SegmentOperator,
-    pub segments: HashMap<String, EvaluationSegment>,
+    pub segments: HashMap<String, Arc<EvaluationSegment>>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -85,6 +89,18 @@ pub struct EvaluationConstraint {
     pub value: String,
 }
 
+impl EvaluationConstraint {
+    /// Whether the constraint is matched against the evaluation time, so its result can change
+    /// while the snapshot and the context stay the same.
+    pub fn depends_on_clock(&self) -> bool {
+        self.r#type == ConstraintComparisonType::DateTime
+            && matches!(
+                self.operator.as_str(),
+                "withinlast" | "withinnext" | "olderthan" | "timeofday" | "dayofweek"
+            )
+    }
+}
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
 pub enum FlagType {
     #[serde(rename = "BOOLEAN_FLAG_TYPE")]
@@ -1,24 +1,412 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
 use serde::Deserialize;
 use serde::Serialize;
-use std::collections::HashMap;
+use serde_json::value::RawValue;
+use sha2::{Digest, Sha256};
 
+use crate::error::Error;
 use crate::models::{flipt, source};
+use crate::HashMap;
 
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+/// Version of the snapshot schema written by this library.
+///
+/// Bump it whenever the serialized shape of [`Namespace`] changes and add a migration from the
+/// previous version to the deserialization of versioned namespaces.
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub struct Snapshot {
     pub version: u32,
-    pub namespace: Namespace,
+    namespace: Namespace,
+    #[serde(skip)]
+    digest: String,
+}
+
+/// A serialized JSON snapshot of any version, with its namespace left unparsed until it is
+/// migrated to [`SNAPSHOT_VERSION`].
+#[derive(Debug, Deserialize)]
+pub struct SnapshotEnvelope<'a> {
+    pub version: u32,
+    #[serde(borrow)]
+    pub namespace: &'a RawValue,
 }
 
+impl SnapshotEnvelope<'_> {
+    /// Parses the namespace in the shape of its version and migrates it to the current version.
+    pub fn migrate(&self) -> Result<Snapshot, Error> {
+        let version = self.version;
+        if let Some(err) = version_error(version) {
+            return Err(err);
+        }
+
+        let namespace = VersionedNamespace(version)
+            .deserialize(&mut serde_json::Deserializer::from_str(
+                self.namespace.get(),
+            ))
+            .map_err(|e| Error::InvalidSnapshot(format!("version {version}: {e}")))?;
+
+        Ok(Snapshot::new(namespace))
+    }
+}
+
+/// The error for a snapshot version that cannot be migrated, if it cannot.
+fn version_error(version: u32) -> Option<Error> {
+    match version {
+        1 | SNAPSHOT_VERSION => None,
+        _ if version > SNAPSHOT_VERSION => Some(Error::InvalidSnapshot(format!(
+            "snapshot version {version} is newer than the supported version {SNAPSHOT_VERSION}"
+        ))),
+        _ => Some(Error::InvalidSnapshot(format!(
+            "unknown snapshot version {version}"
+        ))),
+    }
+}
+
+/// Deserializes a namespace in the shape of the given snapshot version, converting the
+/// namespace of an older version to the next one until it reaches the current version.
+struct VersionedNamespace(u32);
+
+impl<'de> DeserializeSeed<'de> for VersionedNamespace {
+    type Value = Namespace;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Namespace, D::Error> {
+        if let Some(err) = version_error(self.0) {
+            return Err(de::Error::custom(err));
+        }
+
+        match self.0 {
+            1 => v1::Namespace::deserialize(deserializer).map(migrate_v1),
+            _ => Namespace::deserialize(deserializer),
+        }
+    }
+}
+
+/// Snapshots are deserialized by reading the version first and the namespace straight into the
+/// shape of that version. Only a namespace that precedes the version is buffered.
+impl<'de> Deserialize<'de> for Snapshot {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct("Snapshot", &["version", "namespace"], SnapshotVisitor)
+    }
+}
+
+struct SnapshotVisitor;
+
+impl<'de> Visitor<'de> for SnapshotVisitor {
+    type Value = Snapshot;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a snapshot")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Snapshot, A::Error> {
+        let version: u32 = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let namespace = seq
+            .next_element_seed(VersionedNamespace(version))?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+
+        Ok(Snapshot::new(namespace))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Snapshot, A::Error> {
+        let mut version = None;
+        let mut namespace = None;
+        let mut buffered = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "version" => version = Some(map.next_value::<u32>()?),
+                "namespace" => match version {
+                    Some(version) => {
+                        namespace = Some(map.next_value_seed(VersionedNamespace(version))?)
+                    }
+                    None => buffered = Some(map.next_value::<serde_json::Value>()?),
+                },
+                _ => {
+                    map.next_value::<de::IgnoredAny>()?;
+                }
+            }
+        }
+
+        let version = version.ok_or_else(|| de::Error::missing_field("version"))?;
+        let namespace = match (namespace, buffered) {
+            (Some(namespace), _) => namespace,
+            (None, Some(buffered)) => VersionedNamespace(version)
+                .deserialize(buffered)
+                .map_err(de::Error::custom)?,
+            (None, None) => return Err(de::Error::missing_field("namespace")),
+        };
+
+        Ok(Snapshot::new(namespace))
+    }
+}
+
+/// Flags of a namespace with their rules and rollouts.
+///
+/// Segments are stored once per namespace and referenced by key from rules and rollouts.
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Namespace {
     pub key: String,
     pub flags: HashMap<String, flipt::Flag>,
-    pub eval_rules: HashMap<String, Vec<flipt::EvaluationRule>>,
-    pub eval_rollouts: HashMap<String, Vec<flipt::EvaluationRollout>>,
+    pub segments: HashMap<String, Arc<flipt::EvaluationSegment>>,
+    pub eval_rules: HashMap<String, Vec<Rule>>,
+    pub eval_rollouts: HashMap<String, Vec<Rollout>>,
     pub eval_distributions: HashMap<String, Vec<flipt::EvaluationDistribution>>,
 }
 
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Rule {
+    pub id: String,
+    pub flag_key: String,
+    pub segment_keys: Vec<String>,
+    pub rank: usize,
+    pub segment_operator: flipt::SegmentOperator,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Rollout {
+    pub rollout_type: flipt::RolloutType,
+    pub rank: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub segment: Option<RolloutSegment>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<flipt::RolloutThreshold>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RolloutSegment {
+    pub value: bool,
+    pub segment_operator: flipt::SegmentOperator,
+    pub segment_keys: Vec<String>,
+}
+
+fn resolve_segments(
+    segments: &HashMap<String, Arc<flipt::EvaluationSegment>>,
+    segment_keys: &[String],
+) -> Option<HashMap<String, Arc<flipt::EvaluationSegment>>> {
+    segment_keys
+        .iter()
+        .map(|key| Some((key.clone(), segments.get(key)?.clone())))
+        .collect()
+}
+
+impl Namespace {
+    /// Rule with its segments looked up, or `None` if it references an unknown segment.
+    pub fn resolve_rule(&self, rule: &Rule) -> Option<flipt::EvaluationRule> {
+        Some(flipt::EvaluationRule {
+            id: rule.id.clone(),
+            flag_key: rule.flag_key.clone(),
+            segments: resolve_segments(&self.segments, &rule.segment_keys)?,
+            rank: rule.rank,
+            segment_operator: rule.segment_operator.clone(),
+        })
+    }
+
+    /// Rollout with its segments looked up, or `None` if it references an unknown segment.
+    pub fn resolve_rollout(&self, rollout: &Rollout) -> Option<flipt::EvaluationRollout> {
+        let segment = match &rollout.segment {
+            Some(segment) => Some(flipt::RolloutSegment {
+                value: segment.value,
+                segment_operator: segment.segment_operator.clone(),
+                segments: resolve_segments(&self.segments, &segment.segment_keys)?,
+            }),
+            None => None,
+        };
+
+        Some(flipt::EvaluationRollout {
+            rollout_type: rollout.rollout_type.clone(),
+            rank: rollout.rank,
+            segment,
+            threshold: rollout.threshold.clone(),
+        })
+    }
+
+    /// Key of the first segment referenced by the rules or rollouts of a flag that does not exist.
+    pub fn missing_segment(&self, flag_key: &str) -> Option<&str> {
+        let rules = self.eval_rules.get(flag_key).into_iter().flatten();
+        let rollouts = self.eval_rollouts.get(flag_key).into_iter().flatten();
+
+        rules
+            .flat_map(|rule| &rule.segment_keys)
+            .chain(
+                rollouts
+                    .filter_map(|rollout| rollout.segment.as_ref())
+                    .flat_map(|segment| &segment.segment_keys),
+            )
+            .find(|key| !self.segments.contains_key(*key))
+            .map(String::as_str)
+    }
+
+    /// Sorts the segment keys of rules and rollouts, whose order does not matter, so equal
+    /// namespaces share a digest however they were built.
+    fn normalize(&mut self) {
+        let rules = self.eval_rules.values_mut().flatten();
+        let rollouts = self
+            .eval_rollouts
+            .values_mut()
+            .flatten()
+            .filter_map(|rollout| rollout.segment.as_mut());
+
+        for segment_keys in rules
+            .map(|rule| &mut rule.segment_keys)
+            .chain(rollouts.map(|segment| &mut segment.segment_keys))
+        {
+            segment_keys.sort();
+            segment_keys.dedup();
+        }
+    }
+
+    /// Keys of the flags whose rules or rollouts reference a segment with a constraint that
+    /// depends on the evaluation time.
+    pub fn clock_dependent_flags(&self) -> Vec<String> {
+        let depends_on_clock = |segment_keys: &[String]| {
+            segment_keys.iter().any(|key| {
+                self.segments.get(key).is_some_and(|segment| {
+                    segment
+                        .constraints
+                        .iter()
+                        .any(flipt::EvaluationConstraint::depends_on_clock)
+                })
+            })
+        };
+
+        self.flags
+            .keys()
+            .filter(|flag_key| {
+                let rules = self.eval_rules.get(*flag_key).into_iter().flatten();
+                let rollouts = self.eval_rollouts.get(*flag_key).into_iter().flatten();
+
+                rules
+                    .map(|rule| rule.segment_keys.as_slice())
+                    .chain(rollouts.filter_map(|rollout| {
+                        rollout
+                            .segment
+                            .as_ref()
+                            .map(|segment| segment.segment_keys.as_slice())
+                    }))
+                    .any(depends_on_clock)
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// Version 1 snapshots inline the segments into every rule and rollout.
+mod v1 {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use serde::Deserialize;
+
+    use crate::models::flipt;
+    use crate::HashMap;
+
+    #[derive(Deserialize)]
+    pub(super) struct Namespace {
+        pub(super) key: String,
+        pub(super) flags: HashMap<String, flipt::Flag>,
+        pub(super) eval_rules: HashMap<String, Vec<Rule>>,
+        pub(super) eval_rollouts: HashMap<String, Vec<Rollout>>,
+        pub(super) eval_distributions: HashMap<String, Vec<flipt::EvaluationDistribution>>,
+    }
+
+    #[derive(Deserialize)]
+    pub(super) struct Rule {
+        pub(super) id: String,
+        pub(super) flag_key: String,
+        pub(super) segments: HashMap<String, flipt::EvaluationSegment>,
+        pub(super) rank: usize,
+        pub(super) segment_operator: flipt::SegmentOperator,
+    }
+
+    #[derive(Deserialize)]
+    pub(super) struct Rollout {
+        pub(super) rollout_type: flipt::RolloutType,
+        pub(super) rank: usize,
+        #[serde(default)]
+        pub(super) segment: Option<RolloutSegment>,
+        #[serde(default)]
+        pub(super) threshold: Option<flipt::RolloutThreshold>,
+    }
+
+    #[derive(Deserialize)]
+    pub(super) struct RolloutSegment {
+        pub(super) value: bool,
+        pub(super) segment_operator: flipt::SegmentOperator,
+        pub(super) segments: HashMap<String, flipt::EvaluationSegment>,
+    }
+}
+
+/// Moves inlined segments into the shared ones and returns the keys referencing them.
+fn share_segments(
+    shared: &mut HashMap<String, Arc<flipt::EvaluationSegment>>,
+    inlined: HashMap<String, flipt::EvaluationSegment>,
+) -> Vec<String> {
+    let segment_keys: Vec<String> = inlined.keys().cloned().collect();
+    for (key, segment) in inlined {
+        shared.entry(key).or_insert_with(|| Arc::new(segment));
+    }
+    segment_keys
+}
+
+fn migrate_v1(repr: v1::Namespace) -> Namespace {
+    let mut segments = HashMap::new();
+
+    let eval_rules = repr
+        .eval_rules
+        .into_iter()
+        .map(|(flag_key, rules)| {
+            let rules = rules
+                .into_iter()
+                .map(|rule| Rule {
+                    id: rule.id,
+                    flag_key: rule.flag_key,
+                    segment_keys: share_segments(&mut segments, rule.segments),
+                    rank: rule.rank,
+                    segment_operator: rule.segment_operator,
+                })
+                .collect();
+            (flag_key, rules)
+        })
+        .collect();
+
+    let eval_rollouts = repr
+        .eval_rollouts
+        .into_iter()
+        .map(|(flag_key, rollouts)| {
+            let rollouts = rollouts
+                .into_iter()
+                .map(|rollout| Rollout {
+                    rollout_type: rollout.rollout_type,
+                    rank: rollout.rank,
+                    segment: rollout.segment.map(|segment| RolloutSegment {
+                        value: segment.value,
+                        segment_operator: segment.segment_operator,
+                        segment_keys: share_segments(&mut segments, segment.segments),
+                    }),
+                    threshold: rollout.threshold,
+                })
+                .collect();
+            (flag_key, rollouts)
+        })
+        .collect();
+
+    Namespace {
+        key: repr.key,
+        flags: repr.flags,
+        segments,
+        eval_rules,
+        eval_rollouts,
+        eval_distributions: repr.eval_distributions,
+    }
+}
+
 impl Default for Snapshot {
     fn default() -> Self {
         Self::empty("default")
@@ -26,23 +414,54 @@ impl Default for Snapshot {
 }
 
 impl Snapshot {
-    pub fn empty(namespace: &str) -> Snapshot {
+    /// Wraps a namespace in a snapshot of the current version.
+    pub fn new(mut namespace: Namespace) -> Snapshot {
+        namespace.normalize();
+        let digest = content_digest(&namespace);
+
         Self {
-            version: 1,
-            namespace: Namespace {
-                key: namespace.to_string(),
-                flags: HashMap::new(),
-                eval_rules: HashMap::new(),
-                eval_rollouts: HashMap::new(),
-                eval_distributions: HashMap::new(),
-            },
+            version: SNAPSHOT_VERSION,
+            namespace,
+            digest,
         }
     }
 
+    /// The namespace of the snapshot, which cannot be changed in place so the digest stays valid.
+    pub fn namespace(&self) -> &Namespace {
+        &self.namespace
+    }
+
+    /// Unwraps the namespace, e.g. to change it and wrap it in a new snapshot with
+    /// [`Snapshot::new`].
+    pub fn into_namespace(self) -> Namespace {
+        self.namespace
+    }
+
+    pub fn empty(namespace: &str) -> Snapshot {
+        Self::new(Namespace {
+            key: namespace.to_string(),
+            flags: HashMap::new(),
+            segments: HashMap::new(),
+            eval_rules: HashMap::new(),
+            eval_rollouts: HashMap::new(),
+            eval_distributions: HashMap::new(),
+        })
+    }
+
+    /// Hex-encoded SHA-256 of the namespace contents.
+    ///
+    /// The digest is computed from a canonical serialization with sorted object keys, so two
+    /// snapshots holding the same configuration share a digest regardless of map ordering or
+    /// the encoding they were loaded from.
+    pub fn digest(&self) -> &str {
+        &self.digest
+    }
+
     pub fn build(doc: source::Document) -> Snapshot {
         let mut flags: HashMap<String, flipt::Flag> = HashMap::new();
-        let mut eval_rules: HashMap<String, Vec<flipt::EvaluationRule>> = HashMap::new();
-        let mut eval_rollouts: HashMap<String, Vec<flipt::EvaluationRollout>> = HashMap::new();
+        let mut segments: HashMap<String, Arc<flipt::EvaluationSegment>> = HashMap::new();
+        let mut eval_rules: HashMap<String, Vec<Rule>> = HashMap::new();
+        let mut eval_rollouts: HashMap<String, Vec<Rollout>> = HashMap::new();
         let mut eval_dists: HashMap<String, Vec<flipt::EvaluationDistribution>> = HashMap::new();
 
         for flag in doc.flags {
@@ -61,41 +480,27 @@ impl Snapshot {
             flags.insert(f.key.clone(), f);
 
             // Flag Rules
-            let mut eval_rules_collection: Vec<flipt::EvaluationRule> = Vec::new();
+            let mut eval_rules_collection: Vec<Rule> = Vec::new();
 
             let flag_rules = flag.rules.unwrap_or(vec![]);
 
             for (idx, rule) in flag_rules.into_iter().enumerate() {
                 let index = idx + 1;
                 let rule_id = format!("{}-{}", flag.key, index);
-                let mut eval_rule = flipt::EvaluationRule {
+                let mut eval_rule = Rule {
                     id: rule_id.clone(),
                     rank: index,
                     flag_key: flag.key.clone(),
-                    segments: HashMap::new(),
+                    segment_keys: Vec::new(),
                     segment_operator: rule.segment_operator,
                 };
 
                 if let Some(rule_segments) = rule.segments {
                     for rule_segment in rule_segments {
-                        let mut eval_constraints: Vec<flipt::EvaluationConstraint> = Vec::new();
-                        for constraint in rule_segment.constraints {
-                            eval_constraints.push(flipt::EvaluationConstraint {
-                                r#type: constraint.r#type,
-                                property: constraint.property,
-                                operator: constraint.operator,
-                                value: constraint.value,
-                            });
-                        }
-
-                        eval_rule.segments.insert(
-                            rule_segment.key.clone(),
-                            flipt::EvaluationSegment {
-                                segment_key: rule_segment.key,
-                                match_type: rule_segment.match_type,
-                                constraints: eval_constraints,
-                            },
-                        );
+                        eval_rule.segment_keys.push(rule_segment.key.clone());
+                        segments
+                            .entry(rule_segment.key.clone())
+                            .or_insert_with(|| Arc::new(evaluation_segment(rule_segment)));
                     }
                 }
 
@@ -118,7 +523,7 @@ impl Snapshot {
             eval_rules.insert(flag.key.clone(), eval_rules_collection);
 
             // Flag Rollouts
-            let mut eval_rollout_collection: Vec<flipt::EvaluationRollout> = Vec::new();
+            let mut eval_rollout_collection: Vec<Rollout> = Vec::new();
             let mut rollout_idx = 0;
 
             let flag_rollouts = flag.rollouts.unwrap_or(vec![]);
@@ -126,7 +531,7 @@ impl Snapshot {
             for rollout in flag_rollouts {
                 rollout_idx += 1;
 
-                let mut evaluation_rollout: flipt::EvaluationRollout = flipt::EvaluationRollout {
+                let mut evaluation_rollout: Rollout = Rollout {
                     rank: rollout_idx,
                     rollout_type: flipt::RolloutType::Unknown,
                     segment: None,
@@ -143,37 +548,22 @@ impl Snapshot {
 
                     evaluation_rollout.rollout_type = flipt::RolloutType::Threshold;
                 } else if let Some(segment_rule) = rollout.segment {
-                    let mut evaluation_rollout_segments: HashMap<String, flipt::EvaluationSegment> =
-                        HashMap::new();
+                    let mut segment_keys: Vec<String> = Vec::new();
 
                     for segment in segment_rule.segments {
-                        let mut constraints: Vec<flipt::EvaluationConstraint> = Vec::new();
-                        for constraint in segment.constraints {
-                            constraints.push(flipt::EvaluationConstraint {
-                                r#type: constraint.r#type,
-                                property: constraint.property,
-                                value: constraint.value,
-                                operator: constraint.operator,
-                            });
-                        }
-
-                        evaluation_rollout_segments.insert(
-                            segment.key.clone(),
-                            flipt::EvaluationSegment {
-                                segment_key: segment.key,
-                                match_type: segment.match_type.clone(),
-                                constraints,
-                            },
-                        );
+                        segment_keys.push(segment.key.clone());
+                        segments
+                            .entry(segment.key.clone())
+                            .or_insert_with(|| Arc::new(evaluation_segment(segment)));
                     }
 
                     evaluation_rollout.rollout_type = flipt::RolloutType::Segment;
-                    evaluation_rollout.segment = Some(flipt::RolloutSegment {
+                    evaluation_rollout.segment = Some(RolloutSegment {
                         value: segment_rule.value,
                         segment_operator: segment_rule
                             .segment_operator
                             .unwrap_or(flipt::SegmentOperator::Or),
-                        segments: evaluation_rollout_segments,
+                        segment_keys,
                     });
                 }
 
@@ -183,15 +573,61 @@ impl Snapshot {
             eval_rollouts.insert(flag.key.clone(), eval_rollout_collection);
         }
 
-        Self {
-            version: 1,
-            namespace: Namespace {
-                key: doc.namespace.key.clone(),
-                flags,
-                eval_rules,
-                eval_rollouts,
-                eval_distributions: eval_dists,
-            },
+        Self::new(Namespace {
+            key: doc.namespace.key.clone(),
+            flags,
+            segments,
+            eval_rules,
+            eval_rollouts,
+            eval_distributions: eval_dists,
+        })
+    }
+}
+
+fn content_digest(namespace: &Namespace) -> String {
+    let value = serde_json::to_value(namespace).expect("namespace serializes to JSON");
+    let canonical =
+        serde_json::to_vec(&canonicalize(value)).expect("canonical namespace serializes to JSON");
+
+    Sha256::digest(canonical)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Sorts object keys recursively so that the serialization does not depend on map ordering.
+fn canonicalize(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            serde_json::Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, canonicalize(value)))
+                    .collect(),
+            )
+        }
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.into_iter().map(canonicalize).collect())
         }
+        value => value,
+    }
+}
+
+fn evaluation_segment(segment: source::Segment) -> flipt::EvaluationSegment {
+    flipt::EvaluationSegment {
+        segment_key: segment.key,
+        match_type: segment.match_type,
+        constraints: segment
+            .constraints
+            .into_iter()
+            .map(|constraint| flipt::EvaluationConstraint {
+                r#type: constraint.r#type,
+                property: constraint.property,
+                operator: constraint.operator,
+                value: constraint.value,
+            })
+            .collect(),
     }
 }
@@ -0,0 +1,156 @@
+//! Verification of signed snapshots and documents.
+//!
+//! Snapshots and documents are signed over their exact serialized bytes, and the signature is
+//! carried alongside them base64-encoded. Payloads that fail verification must be discarded
+//! so the current snapshot stays in place.
+
+use std::fmt;
+
+use base64::prelude::*;
+use ed25519_dalek::{Signature, VerifyingKey};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::error::Error;
+
+/// Key material used to check that a payload was produced by a trusted party.
+#[derive(Clone, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Verifier {
+    /// Base64-encoded 32 byte Ed25519 public key.
+    Ed25519(String),
+    /// Shared secret for HMAC-SHA256 signatures.
+    Hmac(String),
+}
+
+// The HMAC secret must not end up in logs, so it is redacted; public keys are printed as is.
+impl fmt::Debug for Verifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verifier::Ed25519(key) => f.debug_tuple("Ed25519").field(key).finish(),
+            Verifier::Hmac(_) => f.debug_tuple("Hmac").field(&"<redacted>").finish(),
+        }
+    }
+}
+
+impl Verifier {
+    /// Verify the base64-encoded `signature` over `payload`.
+    ///
+    /// A missing signature is an error: once a verifier is configured every payload must be
+    /// signed.
+    pub fn verify(&self, payload: &[u8], signature: Option<&str>) -> Result<(), Error> {
+        let signature =
+            signature.ok_or_else(|| Error::InvalidSignature("payload is not signed".into()))?;
+        let signature = BASE64_STANDARD
+            .decode(signature.trim())
+            .map_err(|e| Error::InvalidSignature(format!("malformed signature: {e}")))?;
+
+        match self {
+            Verifier::Ed25519(public_key) => {
+                let public_key: [u8; 32] = BASE64_STANDARD
+                    .decode(public_key.trim())
+                    .ok()
+                    .and_then(|key| key.try_into().ok())
+                    .ok_or_else(|| {
+                        Error::InvalidSignature("malformed Ed25519 public key".into())
+                    })?;
+                let key = VerifyingKey::from_bytes(&public_key)
+                    .map_err(|e| Error::InvalidSignature(format!("invalid public key: {e}")))?;
+                let signature = Signature::from_slice(&signature)
+                    .map_err(|e| Error::InvalidSignature(format!("malformed signature: {e}")))?;
+
+                key.verify_strict(payload, &signature)
+                    .map_err(|_| Error::InvalidSignature("signature does not match".into()))
+            }
+            Verifier::Hmac(secret) => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+                    .map_err(|e| Error::InvalidSignature(format!("invalid secret: {e}")))?;
+                mac.update(payload);
+
+                mac.verify_slice(&signature)
+                    .map_err(|_| Error::InvalidSignature("signature does not match".into()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signer, SigningKey};
+
+    use super::*;
+
+    const PAYLOAD: &[u8] = br#"{"namespace":{"key":"default"},"flags":[]}"#;
+
+    fn hmac_signature(secret: &str, payload: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(payload);
+        BASE64_STANDARD.encode(mac.finalize().into_bytes())
+    }
+
+    #[test]
+    fn test_verify_ed25519() {
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let verifier =
+            Verifier::Ed25519(BASE64_STANDARD.encode(signing_key.verifying_key().as_bytes()));
+        let signature = BASE64_STANDARD.encode(signing_key.sign(PAYLOAD).to_bytes());
+
+        assert_eq!(verifier.verify(PAYLOAD, Some(&signature)), Ok(()));
+        assert_eq!(
+            verifier.verify(b"tampered", Some(&signature)),
+            Err(Error::InvalidSignature("signature does not match".into()))
+        );
+
+        let other_key = SigningKey::from_bytes(&[8; 32]);
+        let forged = BASE64_STANDARD.encode(other_key.sign(PAYLOAD).to_bytes());
+        assert!(verifier.verify(PAYLOAD, Some(&forged)).is_err());
+    }
+
+    #[test]
+    fn test_verify_hmac() {
+        let verifier = Verifier::Hmac("secret".into());
+
+        assert_eq!(
+            verifier.verify(PAYLOAD, Some(&hmac_signature("secret", PAYLOAD))),
+            Ok(())
+        );
+        assert_eq!(
+            verifier.verify(PAYLOAD, Some(&hmac_signature("other", PAYLOAD))),
+            Err(Error::InvalidSignature("signature does not match".into()))
+        );
+    }
+
+    #[test]
+    fn test_verify_rejects_missing_or_malformed_signature() {
+        let verifier = Verifier::Hmac("secret".into());
+
+        assert_eq!(
+            verifier.verify(PAYLOAD, None),
+            Err(Error::InvalidSignature("payload is not signed".into()))
+        );
+        assert!(verifier.verify(PAYLOAD, Some("not base64!")).is_err());
+        assert!(Verifier::Ed25519("c2hvcnQ=".into())
+            .verify(PAYLOAD, Some("c2ln"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_deserialize_verifier() {
+        let verifier: Verifier = serde_json::from_str(r#"{"hmac":"secret"}"#).unwrap();
+        assert_eq!(verifier, Verifier::Hmac("secret".into()));
+
+        let verifier: Verifier = serde_json::from_str(r#"{"ed25519":"a2V5"}"#).unwrap();
+        assert_eq!(verifier, Verifier::Ed25519("a2V5".into()));
+    }
+
+    #[test]
+    fn test_debug_redacts_hmac_secret() {
+        let debug = format!("{:?}", Verifier::Hmac("secret".into()));
+        assert!(!debug.contains("secret"));
+        assert_eq!(debug, r#"Hmac("<redacted>")"#);
+
+        let debug = format!("{:?}", Verifier::Ed25519("a2V5".into()));
+        assert_eq!(debug, r#"Ed25519("a2V5")"#);
+    }
+}
@@ -0,0 +1,517 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Clock;
+use crate::error::Error;
+use crate::models::flipt;
+use crate::store::Store;
+use crate::{
+    boolean_evaluation_with_options, merge_context, split_context, variant_evaluation_with_options,
+    ContextList, ContextValue, EvaluationOptions, EvaluationRequest, HashMap,
+};
+
+const GENERATED_ENTITY_PREFIX: &str = "entity-";
+/// Maximum number of entities, explicit and generated, a single simulation evaluates.
+pub const MAX_SIMULATED_ENTITIES: usize = 100_000;
+/// Maximum number of entity ids listed in [`SimulationResponse::default_entity_ids`].
+pub const MAX_DEFAULT_ENTITY_IDS: usize = 1000;
+
+/// An entity to run through a flag during a simulation.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(from = "SerializedEntity", into = "SerializedEntity")]
+pub struct SimulationEntity {
+    pub entity_id: String,
+    pub context: HashMap<String, String>,
+    /// List-valued context properties, serialized as arrays in `context`.
+    pub list_context: HashMap<String, ContextList>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct SerializedEntity {
+    entity_id: String,
+    #[serde(default)]
+    context: HashMap<String, ContextValue>,
+}
+
+impl From<SerializedEntity> for SimulationEntity {
+    fn from(entity: SerializedEntity) -> Self {
+        let (context, list_context) = split_context(entity.context);
+        Self {
+            entity_id: entity.entity_id,
+            context,
+            list_context,
+        }
+    }
+}
+
+impl From<SimulationEntity> for SerializedEntity {
+    fn from(entity: SimulationEntity) -> Self {
+        Self {
+            entity_id: entity.entity_id,
+            context: merge_context(entity.context, entity.list_context),
+        }
+    }
+}
+
+/// Entities to run through a flag. Explicit `entities` are evaluated first, followed by
+/// `sample_size` generated entity ids, at most [`MAX_SIMULATED_ENTITIES`] in total. The shared
+/// `context` is used for generated entities and is overridden by the context of explicit
+/// entities.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(from = "SerializedRequest", into = "SerializedRequest")]
+pub struct SimulationRequest {
+    pub flag_key: String,
+    pub entities: Vec<SimulationEntity>,
+    pub sample_size: Option<usize>,
+    pub context: HashMap<String, String>,
+    /// List-valued context properties, serialized as arrays in `context`.
+    pub list_context: HashMap<String, ContextList>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct SerializedRequest {
+    flag_key: String,
+    #[serde(default)]
+    entities: Vec<SimulationEntity>,
+    #[serde(default)]
+    sample_size: Option<usize>,
+    #[serde(default)]
+    context: HashMap<String, ContextValue>,
+}
+
+impl From<SerializedRequest> for SimulationRequest {
+    fn from(request: SerializedRequest) -> Self {
+        let (context, list_context) = split_context(request.context);
+        Self {
+            flag_key: request.flag_key,
+            entities: request.entities,
+            sample_size: request.sample_size,
+            context,
+            list_context,
+        }
+    }
+}
+
+impl From<SimulationRequest> for SerializedRequest {
+    fn from(request: SimulationRequest) -> Self {
+        Self {
+            flag_key: request.flag_key,
+            entities: request.entities,
+            sample_size: request.sample_size,
+            context: merge_context(request.context, request.list_context),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct VariantCount {
+    /// Variant key, or `"true"`/`"false"` for boolean flags.
+    pub key: String,
+    pub count: usize,
+    pub percentage: f64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RuleCount {
+    pub rank: usize,
+    /// Rule id for variant flags; rollouts of boolean flags have no id.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule_id: Option<String>,
+    pub count: usize,
+    pub percentage: f64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SimulationResponse {
+    pub flag_key: String,
+    pub total: usize,
+    pub variants: Vec<VariantCount>,
+    pub rules: Vec<RuleCount>,
+    /// Number of entities that did not match any rule or rollout and received the default.
+    pub default_count: usize,
+    /// The first [`MAX_DEFAULT_ENTITY_IDS`] of those entities.
+    pub default_entity_ids: Vec<String>,
+}
+
+fn percentage(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    count as f64 / total as f64 * 100.0
+}
+
+/// Runs every entity of the request through the same bucketing as [`crate::variant_evaluation`]
+/// and [`crate::boolean_evaluation`] and reports how they are distributed.
+#[cfg(feature = "std")]
+pub fn simulate(
+    store: &dyn Store,
+    namespace: &str,
+    request: &SimulationRequest,
+) -> Result<SimulationResponse, Error> {
+    simulate_with_options(store, namespace, request, &EvaluationOptions::default())
+}
+
+pub fn simulate_with_clock(
+    store: &dyn Store,
+    namespace: &str,
+    request: &SimulationRequest,
+    clock: &dyn Clock,
+) -> Result<SimulationResponse, Error> {
+    let options = EvaluationOptions::new(clock);
+    simulate_with_options(store, namespace, request, &options)
+}
+
+pub fn simulate_with_options(
+    store: &dyn Store,
+    namespace: &str,
+    request: &SimulationRequest,
+    options: &EvaluationOptions,
+) -> Result<SimulationResponse, Error> {
+    let flag = store
+        .get_flag(namespace, &request.flag_key)
+        .ok_or_else(|| {
+            Error::InvalidRequest(format!(
+                "failed to get flag information {}/{}",
+                namespace, &request.flag_key,
+            ))
+        })?;
+
+    let sample_size = request.sample_size.unwrap_or(0);
+    if request.entities.len().saturating_add(sample_size) > MAX_SIMULATED_ENTITIES {
+        return Err(Error::InvalidRequest(format!(
+            "simulations are limited to {MAX_SIMULATED_ENTITIES} entities"
+        )));
+    }
+
+    let explicit = request.entities.iter().map(|entity| {
+        let mut context = merge_context(request.context.clone(), request.list_context.clone());
+        context.extend(merge_context(
+            entity.context.clone(),
+            entity.list_context.clone(),
+        ));
+        let (context, list_context) = split_context(context);
+        EvaluationRequest {
+            flag_key: request.flag_key.clone(),
+            entity_id: entity.entity_id.clone(),
+            context,
+            list_context,
+        }
+    });
+
+    let generated = (0..sample_size).map(|i| EvaluationRequest {
+        flag_key: request.flag_key.clone(),
+        entity_id: format!("{GENERATED_ENTITY_PREFIX}{i}"),
+        context: request.context.clone(),
+        list_context: request.list_context.clone(),
+    });
+
+    let mut variants: BTreeMap<String, usize> = BTreeMap::new();
+    let mut rules: BTreeMap<(usize, Option<String>), usize> = BTreeMap::new();
+    let mut default_entity_ids = vec![];
+    let mut default_count = 0;
+    let mut total = 0;
+
+    for evaluation_request in explicit.chain(generated) {
+        let (key, matched) = match flag.r#type {
+            flipt::FlagType::Variant => {
+                let response = variant_evaluation_with_options(
+                    store,
+                    namespace,
+                    &evaluation_request,
+                    options,
+                )?;
+                let matched = response.rule_rank.map(|rank| (rank, response.rule_id));
+                (response.variant_key, matched)
+            }
+            flipt::FlagType::Boolean => {
+                let response = boolean_evaluation_with_options(
+                    store,
+                    namespace,
+                    &evaluation_request,
+                    options,
+                )?;
+                let matched = response.rollout_rank.map(|rank| (rank, None));
+                (response.enabled.to_string(), matched)
+            }
+        };
+
+        total += 1;
+        *variants.entry(key).or_default() += 1;
+
+        match matched {
+            Some(matched) => *rules.entry(matched).or_default() += 1,
+            None => {
+                default_count += 1;
+                if default_entity_ids.len() < MAX_DEFAULT_ENTITY_IDS {
+                    default_entity_ids.push(evaluation_request.entity_id);
+                }
+            }
+        }
+    }
+
+    Ok(SimulationResponse {
+        flag_key: flag.key,
+        total,
+        variants: variants
+            .into_iter()
+            .map(|(key, count)| VariantCount {
+                key,
+                count,
+                percentage: percentage(count, total),
+            })
+            .collect(),
+        rules: rules
+            .into_iter()
+            .map(|((rank, rule_id), count)| RuleCount {
+                rank,
+                rule_id,
+                count,
+                percentage: percentage(count, total),
+            })
+            .collect(),
+        default_count,
+        default_entity_ids,
+    })
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::models::snapshot::Snapshot;
+
+    fn snapshot() -> Snapshot {
+        serde_json::from_value(serde_json::json!({
+            "version": 1,
+            "namespace": {
+                "key": "default",
+                "flags": {
+                    "split": {
+                        "key": "split",
+                        "enabled": true,
+                        "type": "VARIANT_FLAG_TYPE"
+                    },
+                    "canary": {
+                        "key": "canary",
+                        "enabled": false,
+                        "type": "BOOLEAN_FLAG_TYPE"
+                    }
+                },
+                "eval_rules": {
+                    "split": [{
+                        "id": "rule1",
+                        "flag_key": "split",
+                        "segments": {
+                            "pro": {
+                                "segment_key": "pro",
+                                "match_type": "ALL_SEGMENT_MATCH_TYPE",
+                                "constraints": [{
+                                    "type": "STRING_CONSTRAINT_COMPARISON_TYPE",
+                                    "property": "plan",
+                                    "operator": "eq",
+                                    "value": "pro"
+                                }]
+                            }
+                        },
+                        "rank": 1,
+                        "segment_operator": "OR_SEGMENT_OPERATOR"
+                    }],
+                    "canary": []
+                },
+                "eval_rollouts": {
+                    "split": [],
+                    "canary": [{
+                        "rollout_type": "THRESHOLD_ROLLOUT_TYPE",
+                        "rank": 1,
+                        "threshold": { "percentage": 25.0, "value": true }
+                    }]
+                },
+                "eval_distributions": {
+                    "rule1": [
+                        { "rule_id": "rule1", "rollout": 33.0, "variant_key": "a" },
+                        { "rule_id": "rule1", "rollout": 33.0, "variant_key": "b" },
+                        { "rule_id": "rule1", "rollout": 34.0, "variant_key": "c" }
+                    ]
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_simulate_variant_distribution() {
+        let snapshot = snapshot();
+        let request = SimulationRequest {
+            flag_key: "split".into(),
+            entities: vec![SimulationEntity {
+                entity_id: "free-user".into(),
+                context: HashMap::from([("plan".into(), "free".into())]),
+                list_context: HashMap::new(),
+            }],
+            sample_size: Some(10_000),
+            context: HashMap::from([("plan".into(), "pro".into())]),
+            list_context: HashMap::new(),
+        };
+
+        let response = simulate(&snapshot, "default", &request).unwrap();
+
+        assert_eq!(response.total, 10_001);
+        assert_eq!(response.default_count, 1);
+        assert_eq!(response.default_entity_ids, vec!["free-user"]);
+        assert_eq!(
+            response.rules,
+            vec![RuleCount {
+                rank: 1,
+                rule_id: Some("rule1".into()),
+                count: 10_000,
+                percentage: percentage(10_000, 10_001),
+            }]
+        );
+
+        let keys: Vec<&str> = response.variants.iter().map(|v| v.key.as_str()).collect();
+        assert_eq!(keys, vec!["", "a", "b", "c"]);
+        for variant in &response.variants[1..] {
+            assert!(
+                (variant.percentage - 33.3).abs() < 2.0,
+                "{} landed in {}%",
+                variant.key,
+                variant.percentage
+            );
+        }
+
+        // the simulation must agree with a regular evaluation
+        let evaluation = crate::variant_evaluation(
+            &snapshot,
+            "default",
+            &EvaluationRequest {
+                flag_key: "split".into(),
+                entity_id: "entity-0".into(),
+                context: HashMap::from([("plan".into(), "pro".into())]),
+                list_context: HashMap::new(),
+            },
+        )
+        .unwrap();
+        let single = simulate(
+            &snapshot,
+            "default",
+            &SimulationRequest {
+                flag_key: "split".into(),
+                entities: vec![],
+                sample_size: Some(1),
+                context: HashMap::from([("plan".into(), "pro".into())]),
+                list_context: HashMap::new(),
+            },
+        )
+        .unwrap();
+        assert_eq!(single.variants[0].key, evaluation.variant_key);
+    }
+
+    #[test]
+    fn test_simulate_boolean_threshold() {
+        let response = simulate(
+            &snapshot(),
+            "default",
+            &SimulationRequest {
+                flag_key: "canary".into(),
+                entities: vec![],
+                sample_size: Some(10_000),
+                context: HashMap::new(),
+                list_context: HashMap::new(),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(response.total, 10_000);
+        assert_eq!(response.variants.len(), 2);
+        assert_eq!(response.variants[0].key, "false");
+        assert_eq!(response.variants[1].key, "true");
+        assert!((response.variants[1].percentage - 25.0).abs() < 2.0);
+        assert_eq!(response.rules.len(), 1);
+        assert_eq!(response.rules[0].rule_id, None);
+        assert_eq!(response.rules[0].count, response.variants[1].count);
+        assert_eq!(response.default_count, response.variants[0].count);
+        // only the first ids are listed
+        assert_eq!(response.default_entity_ids.len(), MAX_DEFAULT_ENTITY_IDS);
+    }
+
+    #[test]
+    fn test_simulate_sample_size_limit() {
+        let result = simulate(
+            &snapshot(),
+            "default",
+            &SimulationRequest {
+                flag_key: "canary".into(),
+                entities: vec![SimulationEntity {
+                    entity_id: "explicit".into(),
+                    context: HashMap::new(),
+                    list_context: HashMap::new(),
+                }],
+                sample_size: Some(MAX_SIMULATED_ENTITIES),
+                context: HashMap::new(),
+                list_context: HashMap::new(),
+            },
+        );
+        assert!(matches!(result, Err(Error::InvalidRequest(_))));
+
+        let result = simulate(
+            &snapshot(),
+            "default",
+            &SimulationRequest {
+                flag_key: "canary".into(),
+                entities: vec![],
+                sample_size: Some(usize::MAX),
+                context: HashMap::new(),
+                list_context: HashMap::new(),
+            },
+        );
+        assert!(matches!(result, Err(Error::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn test_simulate_unknown_flag() {
+        let result = simulate(
+            &snapshot(),
+            "default",
+            &SimulationRequest {
+                flag_key: "missing".into(),
+                entities: vec![],
+                sample_size: Some(1),
+                context: HashMap::new(),
+                list_context: HashMap::new(),
+            },
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_simulation_request_list_context() {
+        let request: SimulationRequest = serde_json::from_value(serde_json::json!({
+            "flag_key": "split",
+            "entities": [{
+                "entity_id": "explicit",
+                "context": { "plan": "pro", "roles": ["admin"] }
+            }],
+            "context": { "ids": [1, 2], "region": "eu" }
+        }))
+        .unwrap();
+
+        assert_eq!(request.context.get("region").unwrap(), "eu");
+        assert_eq!(
+            request.list_context.get("ids"),
+            Some(&ContextList::Numbers(vec![1, 2]))
+        );
+        assert_eq!(request.entities[0].context.get("plan").unwrap(), "pro");
+        assert_eq!(
+            request.entities[0].list_context.get("roles"),
+            Some(&ContextList::Strings(vec!["admin".into()]))
+        );
+
+        let round_trip: SimulationRequest =
+            serde_json::from_value(serde_json::to_value(&request).unwrap()).unwrap();
+        assert_eq!(round_trip, request);
+    }
+}
@@ -1,10 +1,13 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
 use crate::models::flipt;
 use crate::models::snapshot::Snapshot;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 use mockall::automock;
 
-#[cfg_attr(test, automock)]
+#[cfg_attr(all(test, feature = "std"), automock)]
 pub trait Store {
     fn list_flags(&self, namespace_key: &str) -> Option<Vec<flipt::Flag>>;
     fn get_flag(&self, namespace_key: &str, flag_key: &str) -> Option<flipt::Flag>;
@@ -23,25 +26,30 @@ pub trait Store {
         namespace_key: &str,
         flag_key: &str,
     ) -> Option<Vec<flipt::EvaluationRollout>>;
+    /// Key of a segment referenced by the rules or rollouts of a flag that does not exist, which
+    /// is why they could not be returned. Stores that cannot tell return `None`.
+    fn get_missing_segment(&self, _namespace_key: &str, _flag_key: &str) -> Option<String> {
+        None
+    }
 }
 
 impl Store for Snapshot {
     fn list_flags(&self, namespace_key: &str) -> Option<Vec<flipt::Flag>> {
-        if self.namespace.key != namespace_key {
+        if self.namespace().key != namespace_key {
             return None;
         }
 
-        let flags = self.namespace.flags.values().cloned().collect();
+        let flags = self.namespace().flags.values().cloned().collect();
 
         Some(flags)
     }
 
     fn get_flag(&self, namespace_key: &str, flag_key: &str) -> Option<flipt::Flag> {
-        if self.namespace.key != namespace_key {
+        if self.namespace().key != namespace_key {
             return None;
         }
 
-        let flag = self.namespace.flags.get(flag_key)?;
+        let flag = self.namespace().flags.get(flag_key)?;
 
         Some(flag.clone())
     }
@@ -51,13 +59,16 @@ impl Store for Snapshot {
         namespace_key: &str,
         flag_key: &str,
     ) -> Option<Vec<flipt::EvaluationRule>> {
-        if self.namespace.key != namespace_key {
+        if self.namespace().key != namespace_key {
             return None;
         }
 
-        let eval_rules = self.namespace.eval_rules.get(flag_key)?;
+        let eval_rules = self.namespace().eval_rules.get(flag_key)?;
 
-        Some(eval_rules.to_vec())
+        eval_rules
+            .iter()
+            .map(|rule| self.namespace().resolve_rule(rule))
+            .collect()
     }
 
     fn get_evaluation_distributions(
@@ -65,11 +76,11 @@ impl Store for Snapshot {
         namespace_key: &str,
         rule_id: &str,
     ) -> Option<Vec<flipt::EvaluationDistribution>> {
-        if self.namespace.key != namespace_key {
+        if self.namespace().key != namespace_key {
             return None;
         }
 
-        let evaluation_distributions = self.namespace.eval_distributions.get(rule_id)?;
+        let evaluation_distributions = self.namespace().eval_distributions.get(rule_id)?;
 
         Some(evaluation_distributions.to_vec())
     }
@@ -79,22 +90,41 @@ impl Store for Snapshot {
         namespace_key: &str,
         flag_key: &str,
     ) -> Option<Vec<flipt::EvaluationRollout>> {
-        if self.namespace.key != namespace_key {
+        if self.namespace().key != namespace_key {
             return None;
         }
 
-        let eval_rollouts = self.namespace.eval_rollouts.get(flag_key)?;
+        let eval_rollouts = self.namespace().eval_rollouts.get(flag_key)?;
+
+        eval_rollouts
+            .iter()
+            .map(|rollout| self.namespace().resolve_rollout(rollout))
+            .collect()
+    }
+
+    fn get_missing_segment(&self, namespace_key: &str, flag_key: &str) -> Option<String> {
+        if self.namespace().key != namespace_key {
+            return None;
+        }
 
-        Some(eval_rollouts.to_vec())
+        self.namespace()
+            .missing_segment(flag_key)
+            .map(ToString::to_string)
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use std::{fs, path::PathBuf};
 
     use super::{Snapshot, Store};
-    use crate::{error::Error, models::flipt, models::source};
+    use crate::{
+        error::Error,
+        models::flipt,
+        models::snapshot::{SnapshotEnvelope, SNAPSHOT_VERSION},
+        models::source,
+        variant_evaluation, EvaluationRequest, HashMap,
+    };
 
     #[cfg(test)]
     pub struct TestFetcher {
@@ -138,7 +168,7 @@ mod tests {
 
         let snapshot = Snapshot::build(doc.unwrap());
 
-        assert_eq!(1, snapshot.version);
+        assert_eq!(SNAPSHOT_VERSION, snapshot.version);
 
         let flag_variant = snapshot
             .get_flag("default", "flag1")
@@ -169,7 +199,7 @@ mod tests {
         assert_eq!(evaluation_rules[0].rank, 1);
         assert_eq!(evaluation_rules[0].segments.len(), 1);
         assert_eq!(
-            *evaluation_rules[0]
+            **evaluation_rules[0]
                 .segments
                 .get("segment1")
                 .expect("segment1 should exist"),
@@ -212,7 +242,7 @@ mod tests {
         assert!(segment_rollout.value);
         assert_eq!(segment_rollout.segment_operator, flipt::SegmentOperator::Or);
         assert_eq!(
-            *segment_rollout
+            **segment_rollout
                 .segments
                 .get("segment1")
                 .expect("segment1 should exist"),
@@ -243,13 +273,200 @@ mod tests {
         assert_eq!(found, 2);
     }
 
+    #[test]
+    fn test_snapshot_shares_segments() {
+        let mut tp = TestFetcher::new();
+        let snapshot = Snapshot::build(tp.fetch("default").unwrap().unwrap());
+        let namespace = snapshot.namespace();
+
+        // segment1 is referenced by a rule and a rollout but stored once
+        assert_eq!(namespace.segments.len(), 1);
+        assert_eq!(
+            namespace.eval_rules["flag1"][0].segment_keys,
+            vec!["segment1"]
+        );
+        assert_eq!(
+            namespace.eval_rollouts["flag_boolean"][0]
+                .segment
+                .as_ref()
+                .unwrap()
+                .segment_keys,
+            vec!["segment1"]
+        );
+
+        let serialized = serde_json::to_string(&snapshot).unwrap();
+        let deserialized: Snapshot = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, snapshot);
+    }
+
+    #[test]
+    fn test_snapshot_with_inlined_segments() {
+        let segment = serde_json::json!({
+            "segment_key": "segment1",
+            "match_type": "ANY_SEGMENT_MATCH_TYPE",
+            "constraints": [{
+                "type": "STRING_CONSTRAINT_COMPARISON_TYPE",
+                "property": "fizz",
+                "operator": "eq",
+                "value": "buzz"
+            }]
+        });
+
+        let snapshot: Snapshot = serde_json::from_value(serde_json::json!({
+            "version": 1,
+            "namespace": {
+                "key": "default",
+                "flags": {},
+                "eval_rules": {
+                    "flag1": [{
+                        "id": "flag1-1",
+                        "flag_key": "flag1",
+                        "segments": { "segment1": segment },
+                        "rank": 1,
+                        "segment_operator": "OR_SEGMENT_OPERATOR"
+                    }]
+                },
+                "eval_rollouts": {
+                    "flag_boolean": [{
+                        "rollout_type": "SEGMENT_ROLLOUT_TYPE",
+                        "rank": 1,
+                        "segment": {
+                            "value": true,
+                            "segment_operator": "OR_SEGMENT_OPERATOR",
+                            "segments": { "segment1": segment }
+                        }
+                    }]
+                },
+                "eval_distributions": {}
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(SNAPSHOT_VERSION, snapshot.version);
+        assert_eq!(snapshot.namespace().segments.len(), 1);
+
+        let rules = snapshot.get_evaluation_rules("default", "flag1").unwrap();
+        let rollouts = snapshot
+            .get_evaluation_rollouts("default", "flag_boolean")
+            .unwrap();
+        assert_eq!(
+            rules[0].segments["segment1"],
+            rollouts[0].segment.as_ref().unwrap().segments["segment1"]
+        );
+        assert_eq!(rules[0].segments["segment1"].constraints[0].value, "buzz");
+    }
+
+    #[test]
+    fn test_snapshot_with_unknown_segment() {
+        let mut tp = TestFetcher::new();
+        let mut namespace = Snapshot::build(tp.fetch("default").unwrap().unwrap()).into_namespace();
+        namespace.segments.clear();
+        let snapshot = Snapshot::new(namespace);
+
+        assert_eq!(snapshot.get_evaluation_rules("default", "flag1"), None);
+        assert_eq!(
+            snapshot.get_missing_segment("default", "flag1").as_deref(),
+            Some("segment1")
+        );
+        assert_eq!(
+            snapshot.get_evaluation_rollouts("default", "flag_boolean"),
+            None
+        );
+        assert_eq!(
+            snapshot
+                .get_missing_segment("default", "flag_boolean")
+                .as_deref(),
+            Some("segment1")
+        );
+        assert_eq!(snapshot.get_missing_segment("default", "unknown"), None);
+        assert_eq!(snapshot.get_missing_segment("other", "flag1"), None);
+
+        let request = EvaluationRequest {
+            flag_key: "flag1".into(),
+            entity_id: "entity".into(),
+            context: HashMap::new(),
+            list_context: HashMap::new(),
+        };
+        assert_eq!(
+            variant_evaluation(&snapshot, "default", &request).err(),
+            Some(Error::InvalidSnapshot("segment segment1 not found".into()))
+        );
+    }
+
+    #[test]
+    fn test_snapshot_digest() {
+        let mut tp = TestFetcher::new();
+        let mut doc = || tp.fetch("default").unwrap().unwrap();
+
+        let snapshot = Snapshot::build(doc());
+        assert_eq!(snapshot.digest().len(), 64);
+
+        // independent of the order flags were inserted in
+        let mut reversed = doc();
+        reversed.flags.reverse();
+        assert_eq!(Snapshot::build(reversed).digest(), snapshot.digest());
+
+        // carried over a serialization round trip, where the digest is recomputed
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert!(!json.contains(snapshot.digest()));
+        let restored: Snapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.digest(), snapshot.digest());
+
+        let mut changed = doc();
+        changed.flags[0].enabled = !changed.flags[0].enabled;
+        assert_ne!(Snapshot::build(changed).digest(), snapshot.digest());
+        assert_ne!(Snapshot::empty("default").digest(), snapshot.digest());
+
+        // recomputed for a namespace changed outside a snapshot
+        let mut namespace = snapshot.clone().into_namespace();
+        namespace.flags.clear();
+        assert_ne!(Snapshot::new(namespace).digest(), snapshot.digest());
+
+        // independent of the order segments are referenced in
+        let with_keys = |keys: [&str; 2]| {
+            let mut namespace = snapshot.clone().into_namespace();
+            namespace.eval_rules.get_mut("flag1").unwrap()[0].segment_keys =
+                keys.iter().map(ToString::to_string).collect();
+            Snapshot::new(namespace)
+        };
+        assert_eq!(
+            with_keys(["segment1", "segment2"]).digest(),
+            with_keys(["segment2", "segment1"]).digest()
+        );
+    }
+
+    #[test]
+    fn test_snapshot_rejects_unsupported_versions() {
+        let newer = serde_json::from_value::<Snapshot>(serde_json::json!({
+            "version": SNAPSHOT_VERSION + 1,
+            "namespace": { "key": "default" }
+        }))
+        .unwrap_err();
+        assert!(newer
+            .to_string()
+            .contains("is newer than the supported version"));
+
+        let namespace =
+            serde_json::value::to_raw_value(&serde_json::json!({ "key": "default" })).unwrap();
+        let unknown = SnapshotEnvelope {
+            version: 0,
+            namespace: &namespace,
+        }
+        .migrate();
+        assert_eq!(
+            unknown,
+            Err(Error::InvalidSnapshot("unknown snapshot version 0".into()))
+        );
+    }
+
     #[test]
     fn test_empty_snapshot() {
         let snapshot = Snapshot::empty("staging");
-        assert_eq!(1, snapshot.version);
-        let namespace = snapshot.namespace;
+        assert_eq!(SNAPSHOT_VERSION, snapshot.version);
+        let namespace = snapshot.namespace();
         assert_eq!("staging", namespace.key);
         assert_eq!(0, namespace.flags.len());
+        assert_eq!(0, namespace.segments.len());
         assert_eq!(0, namespace.eval_rules.len());
         assert_eq!(0, namespace.eval_distributions.len());
         assert_eq!(0, namespace.eval_rollouts.len());
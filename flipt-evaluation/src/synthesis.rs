@@ -0,0 +1,1069 @@
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use chrono::{DateTime, Datelike, TimeDelta, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Clock;
+use crate::error::Error;
+use crate::models::flipt;
+use crate::store::Store;
+use crate::{
+    boolean_evaluation_with_options, lookup_error, merge_context, parse_datetime, parse_duration,
+    parse_numbers, parse_strings, parse_time_of_day, parse_timezone, split_context,
+    variant_evaluation_with_options, ContextList, ContextValue, DayOfWeekWindow, EvaluationOptions,
+    EvaluationRequest, HashMap, TimeOfDayWindow,
+};
+
+const DEFAULT_ENTITY_ID: &str = "entity";
+const GENERATED_ENTITY_PREFIX: &str = "entity-";
+const MAX_GENERATED_ENTITIES: usize = 1000;
+const MAX_CONTEXTS: usize = 4096;
+const MAX_EVALUATIONS: usize = 200_000;
+const MAX_LIST_SIZE: usize = 1000;
+
+const SEARCH_LIMIT_REACHED: &str = "search limit reached before the target was reached";
+
+/// The outcome a synthesized context should produce.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SynthesisTarget {
+    /// A rule of a variant flag.
+    Rule { rule_id: String },
+    /// A rollout of a boolean flag, identified by its rank.
+    Rollout { rank: usize },
+    /// A variant key, or `"true"`/`"false"` for boolean flags.
+    Variant { key: String },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SynthesisRequest {
+    pub flag_key: String,
+    pub target: SynthesisTarget,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(into = "SerializedResponse")]
+pub struct SynthesisResponse {
+    pub flag_key: String,
+    pub reachable: bool,
+    pub entity_id: Option<String>,
+    pub context: HashMap<String, String>,
+    /// List-valued context properties, serialized as arrays in `context`.
+    pub list_context: HashMap<String, ContextList>,
+    /// Rank of the rule or rollout the synthesized request matches, if any.
+    pub rank: Option<usize>,
+    pub rule_id: Option<String>,
+    /// Resulting variant key, or `"true"`/`"false"` for boolean flags.
+    pub variant_key: Option<String>,
+    /// Why the target could not be reached.
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SerializedResponse {
+    flag_key: String,
+    reachable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entity_id: Option<String>,
+    context: HashMap<String, ContextValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rank: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rule_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    variant_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+impl From<SynthesisResponse> for SerializedResponse {
+    fn from(response: SynthesisResponse) -> Self {
+        Self {
+            flag_key: response.flag_key,
+            reachable: response.reachable,
+            entity_id: response.entity_id,
+            context: merge_context(response.context, response.list_context),
+            rank: response.rank,
+            rule_id: response.rule_id,
+            variant_key: response.variant_key,
+            reason: response.reason,
+        }
+    }
+}
+
+impl SynthesisResponse {
+    fn unreachable(flag_key: &str, reason: &str) -> Self {
+        Self {
+            flag_key: flag_key.to_string(),
+            reachable: false,
+            entity_id: None,
+            context: HashMap::new(),
+            list_context: HashMap::new(),
+            rank: None,
+            rule_id: None,
+            variant_key: None,
+            reason: Some(reason.to_string()),
+        }
+    }
+}
+
+/// Searches for a minimal context and entity id whose evaluation reaches the requested rule,
+/// rollout or variant.
+///
+/// Candidate values are derived from the constraints of the flag and every candidate is
+/// checked with the regular evaluation, so a reachable result is always accurate. The search
+/// space is bounded; a target reported as unreachable has no solution among the candidates.
+#[cfg(feature = "std")]
+pub fn synthesize(
+    store: &dyn Store,
+    namespace: &str,
+    request: &SynthesisRequest,
+) -> Result<SynthesisResponse, Error> {
+    synthesize_with_options(store, namespace, request, &EvaluationOptions::default())
+}
+
+pub fn synthesize_with_clock(
+    store: &dyn Store,
+    namespace: &str,
+    request: &SynthesisRequest,
+    clock: &dyn Clock,
+) -> Result<SynthesisResponse, Error> {
+    let options = EvaluationOptions::new(clock);
+    synthesize_with_options(store, namespace, request, &options)
+}
+
+pub fn synthesize_with_options(
+    store: &dyn Store,
+    namespace: &str,
+    request: &SynthesisRequest,
+    options: &EvaluationOptions,
+) -> Result<SynthesisResponse, Error> {
+    let now = options.clock.now();
+
+    let flag = store
+        .get_flag(namespace, &request.flag_key)
+        .ok_or_else(|| {
+            Error::InvalidRequest(format!(
+                "failed to get flag information {}/{}",
+                namespace, &request.flag_key,
+            ))
+        })?;
+
+    let problem = match flag.r#type {
+        flipt::FlagType::Variant => variant_problem(store, namespace, &flag, &request.target)?,
+        flipt::FlagType::Boolean => boolean_problem(store, namespace, &flag, &request.target)?,
+    };
+
+    let (segments, needs_bucketing) = match problem {
+        Problem::Unreachable(reason) => {
+            return Ok(SynthesisResponse::unreachable(&flag.key, reason));
+        }
+        Problem::Search {
+            segments,
+            needs_bucketing,
+        } => (segments, needs_bucketing),
+    };
+
+    let (properties, mut entity_ids) = domains(&segments, now);
+    let (contexts, truncated) = contexts(&properties);
+
+    entity_ids.push(DEFAULT_ENTITY_ID.to_string());
+    if needs_bucketing {
+        entity_ids
+            .extend((0..MAX_GENERATED_ENTITIES).map(|i| format!("{GENERATED_ENTITY_PREFIX}{i}")));
+    }
+    dedup(&mut entity_ids);
+
+    let mut evaluations = 0;
+
+    for context in contexts {
+        for entity_id in &entity_ids {
+            if evaluations == MAX_EVALUATIONS {
+                return Ok(SynthesisResponse::unreachable(
+                    &flag.key,
+                    SEARCH_LIMIT_REACHED,
+                ));
+            }
+            evaluations += 1;
+
+            let (context, list_context) = split_context(context.clone());
+            let evaluation_request = EvaluationRequest {
+                flag_key: request.flag_key.clone(),
+                entity_id: entity_id.clone(),
+                context,
+                list_context,
+            };
+
+            let (key, rank, rule_id) = match flag.r#type {
+                flipt::FlagType::Variant => {
+                    let response = variant_evaluation_with_options(
+                        store,
+                        namespace,
+                        &evaluation_request,
+                        options,
+                    )?;
+                    (response.variant_key, response.rule_rank, response.rule_id)
+                }
+                flipt::FlagType::Boolean => {
+                    let response = boolean_evaluation_with_options(
+                        store,
+                        namespace,
+                        &evaluation_request,
+                        options,
+                    )?;
+                    (response.enabled.to_string(), response.rollout_rank, None)
+                }
+            };
+
+            let reached = match &request.target {
+                SynthesisTarget::Rule { rule_id: target } => rule_id.as_ref() == Some(target),
+                SynthesisTarget::Rollout { rank: target } => rank == Some(*target),
+                SynthesisTarget::Variant { key: target } => &key == target,
+            };
+
+            if reached {
+                return Ok(SynthesisResponse {
+                    flag_key: flag.key.clone(),
+                    reachable: true,
+                    entity_id: Some(evaluation_request.entity_id),
+                    context: evaluation_request.context,
+                    list_context: evaluation_request.list_context,
+                    rank,
+                    rule_id,
+                    variant_key: Some(key),
+                    reason: None,
+                });
+            }
+        }
+    }
+
+    let reason = if truncated {
+        SEARCH_LIMIT_REACHED
+    } else if segments
+        .iter()
+        .flat_map(|s| &s.constraints)
+        .any(exceeds_list_limit)
+    {
+        "list size constraint exceeds the synthesis limit"
+    } else {
+        "no context reaches the target"
+    };
+
+    Ok(SynthesisResponse::unreachable(&flag.key, reason))
+}
+
+enum Problem {
+    Unreachable(&'static str),
+    Search {
+        /// Constraints of every rule or rollout that can decide the outcome, starting with
+        /// the target so its properties are preferred.
+        segments: Vec<Arc<flipt::EvaluationSegment>>,
+        needs_bucketing: bool,
+    },
+}
+
+fn variant_problem(
+    store: &dyn Store,
+    namespace: &str,
+    flag: &flipt::Flag,
+    target: &SynthesisTarget,
+) -> Result<Problem, Error> {
+    let rules = store
+        .get_evaluation_rules(namespace, &flag.key)
+        .ok_or_else(|| lookup_error(store, namespace, &flag.key, "rules"))?;
+
+    let mut distributions = HashMap::new();
+    for rule in &rules {
+        let valid: Vec<flipt::EvaluationDistribution> = store
+            .get_evaluation_distributions(namespace, &rule.id)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|d| d.rollout > 0.0)
+            .collect();
+        distributions.insert(rule.id.clone(), valid);
+    }
+
+    let relevant = match target {
+        SynthesisTarget::Rule { rule_id } => {
+            let position = rules.iter().position(|r| &r.id == rule_id).ok_or_else(|| {
+                Error::InvalidRequest(format!("rule {} not found for flag {}", rule_id, flag.key))
+            })?;
+
+            if !flag.enabled {
+                return Ok(Problem::Unreachable("flag is disabled"));
+            }
+
+            let mut relevant = vec![rules[position].clone()];
+            relevant.extend(rules[..position].iter().cloned());
+            relevant
+        }
+        SynthesisTarget::Variant { key } => {
+            let is_default = flag.default_variant.as_ref().is_some_and(|v| &v.key == key);
+            let is_distributed = distributions
+                .values()
+                .any(|ds| ds.iter().any(|d| &d.variant_key == key));
+
+            if !is_default && (!flag.enabled || !is_distributed) {
+                return Ok(Problem::Unreachable(
+                    "variant is not distributed by any rule",
+                ));
+            }
+
+            rules.clone()
+        }
+        SynthesisTarget::Rollout { .. } => {
+            return Err(Error::InvalidRequest(format!(
+                "{} is not a boolean flag",
+                flag.key
+            )));
+        }
+    };
+
+    let needs_bucketing = matches!(target, SynthesisTarget::Variant { .. })
+        && relevant
+            .iter()
+            .any(|r| distributions.get(&r.id).is_some_and(|ds| ds.len() > 1));
+
+    Ok(Problem::Search {
+        segments: relevant
+            .into_iter()
+            .flat_map(|r| r.segments.into_values())
+            .collect(),
+        needs_bucketing,
+    })
+}
+
+fn boolean_problem(
+    store: &dyn Store,
+    namespace: &str,
+    flag: &flipt::Flag,
+    target: &SynthesisTarget,
+) -> Result<Problem, Error> {
+    let rollouts = store
+        .get_evaluation_rollouts(namespace, &flag.key)
+        .ok_or_else(|| lookup_error(store, namespace, &flag.key, "rollouts"))?;
+
+    let relevant = match target {
+        SynthesisTarget::Rollout { rank } => {
+            let position = rollouts
+                .iter()
+                .position(|r| r.rank == *rank)
+                .ok_or_else(|| {
+                    Error::InvalidRequest(format!(
+                        "rollout {} not found for flag {}",
+                        rank, flag.key
+                    ))
+                })?;
+
+            let mut relevant = vec![rollouts[position].clone()];
+            relevant.extend(rollouts[..position].iter().cloned());
+            relevant
+        }
+        SynthesisTarget::Variant { key } => {
+            if key != "true" && key != "false" {
+                return Ok(Problem::Unreachable(
+                    "boolean flags only evaluate to true or false",
+                ));
+            }
+            rollouts
+        }
+        SynthesisTarget::Rule { .. } => {
+            return Err(Error::InvalidRequest(format!(
+                "{} is not a variant flag",
+                flag.key
+            )));
+        }
+    };
+
+    let needs_bucketing = relevant.iter().any(|r| r.threshold.is_some());
+
+    Ok(Problem::Search {
+        segments: relevant
+            .into_iter()
+            .filter_map(|r| r.segment)
+            .flat_map(|s| s.segments.into_values())
+            .collect(),
+        needs_bucketing,
+    })
+}
+
+/// A context property and its candidate values, `None` meaning absent.
+type Domain = (String, Vec<Option<ContextValue>>);
+
+fn dedup<T: Ord + Clone>(values: &mut Vec<T>) {
+    let mut seen = BTreeSet::new();
+    values.retain(|v| seen.insert(v.clone()));
+}
+
+/// Candidate values of every context property, each starting with the property being absent,
+/// and candidate entity ids.
+fn domains(
+    segments: &[Arc<flipt::EvaluationSegment>],
+    now: DateTime<Utc>,
+) -> (Vec<Domain>, Vec<String>) {
+    let mut properties: Vec<(String, Vec<ContextValue>)> = vec![];
+    let mut entity_ids = vec![];
+
+    for constraint in segments.iter().flat_map(|s| &s.constraints) {
+        let values = candidates(constraint, now);
+
+        if constraint.r#type == flipt::ConstraintComparisonType::EntityId {
+            entity_ids.extend(values.into_iter().filter_map(|value| match value {
+                ContextValue::String(value) => Some(value),
+                ContextValue::List(_) => None,
+            }));
+            continue;
+        }
+
+        match properties
+            .iter_mut()
+            .find(|(property, _)| property == &constraint.property)
+        {
+            Some((_, existing)) => existing.extend(values),
+            None => properties.push((constraint.property.clone(), values)),
+        }
+    }
+
+    let properties = properties
+        .into_iter()
+        .map(|(property, mut values)| {
+            dedup(&mut values);
+            let mut domain = vec![None];
+            domain.extend(values.into_iter().map(Some));
+            (property, domain)
+        })
+        .collect();
+
+    dedup(&mut entity_ids);
+    (properties, entity_ids)
+}
+
+/// Combinations of property values, ordered so contexts with fewer properties come first, and
+/// whether they were truncated to `MAX_CONTEXTS`.
+fn contexts(properties: &[Domain]) -> (Vec<HashMap<String, ContextValue>>, bool) {
+    let mut indexes = vec![0; properties.len()];
+    let mut combinations = vec![];
+
+    loop {
+        let context: HashMap<String, ContextValue> = properties
+            .iter()
+            .zip(&indexes)
+            .filter_map(|((property, domain), i)| {
+                domain[*i].as_ref().map(|v| (property.clone(), v.clone()))
+            })
+            .collect();
+        combinations.push(context);
+
+        if combinations.len() == MAX_CONTEXTS {
+            break;
+        }
+
+        // advance the indexes like an odometer, the last property changing fastest
+        let mut position = properties.len();
+        loop {
+            if position == 0 {
+                combinations.sort_by_key(|c| c.len());
+                return (combinations, false);
+            }
+            position -= 1;
+            indexes[position] += 1;
+            if indexes[position] < properties[position].1.len() {
+                break;
+            }
+            indexes[position] = 0;
+        }
+    }
+
+    combinations.sort_by_key(|c| c.len());
+    (combinations, true)
+}
+
+/// A string that differs from and does not contain any of the given values.
+fn other_string(values: &[String]) -> Option<String> {
+    // a single character only contains values equal to it, or empty ones
+    core::iter::once("other".to_string())
+        .chain(('a'..='z').map(String::from))
+        .find(|candidate| values.iter().all(|v| !candidate.contains(v.as_str())))
+}
+
+fn strings(values: Vec<String>) -> ContextValue {
+    ContextValue::List(ContextList::Strings(values))
+}
+
+fn numbers(values: Vec<i32>) -> ContextValue {
+    ContextValue::List(ContextList::Numbers(values))
+}
+
+/// Values for the constrained property that satisfy the constraint.
+fn candidates(constraint: &flipt::EvaluationConstraint, now: DateTime<Utc>) -> Vec<ContextValue> {
+    let operator = constraint.operator.as_str();
+    let value = constraint.value.as_str();
+
+    let candidate = match constraint.r#type {
+        flipt::ConstraintComparisonType::String | flipt::ConstraintComparisonType::EntityId => {
+            string_candidate(operator, value)
+        }
+        flipt::ConstraintComparisonType::Number => number_candidate(operator, value),
+        flipt::ConstraintComparisonType::Boolean => match operator {
+            "true" | "present" => Some(ContextValue::String("true".to_string())),
+            "false" => Some(ContextValue::String("false".to_string())),
+            _ => None,
+        },
+        flipt::ConstraintComparisonType::DateTime => {
+            datetime_candidate(operator, value, now).map(ContextValue::String)
+        }
+        flipt::ConstraintComparisonType::Unknown => None,
+    };
+
+    candidate.into_iter().collect()
+}
+
+fn string_candidate(operator: &str, value: &str) -> Option<ContextValue> {
+    let candidate = match operator {
+        "eq" | "prefix" | "suffix" | "contains" => Some(value.to_string()),
+        "neq" | "notcontains" => other_string(&[value.to_string()]),
+        "notempty" => other_string(&[]),
+        "isoneof" => parse_strings(value).ok()?.into_iter().next(),
+        "isnotoneof" => other_string(&parse_strings(value).ok()?),
+        "containsany" => return Some(strings(parse_strings(value).ok()?.get(..1)?.to_vec())),
+        "containsall" => return Some(strings(parse_strings(value).ok()?)),
+        "containsnone" => {
+            return Some(strings(vec![other_string(&parse_strings(value).ok()?)?]));
+        }
+        "sizeeq" | "sizegt" | "sizelt" => {
+            let size = list_size(operator, value)?;
+            return Some(strings((0..size).map(|i| format!("item-{i}")).collect()));
+        }
+        _ => None,
+    };
+
+    candidate.map(ContextValue::String)
+}
+
+fn number_candidate(operator: &str, value: &str) -> Option<ContextValue> {
+    let candidate = match operator {
+        "present" => Some(0),
+        "isoneof" => parse_numbers(value).ok()?.first().copied(),
+        "isnotoneof" => {
+            let values = parse_numbers(value).ok()?;
+            let max = values.iter().max().copied().unwrap_or(0);
+            max.checked_add(1)
+        }
+        "containsany" => return Some(numbers(parse_numbers(value).ok()?.get(..1)?.to_vec())),
+        "containsall" => return Some(numbers(parse_numbers(value).ok()?)),
+        "containsnone" => {
+            let values = parse_numbers(value).ok()?;
+            let max = values.iter().max().copied().unwrap_or(0);
+            return Some(numbers(vec![max.checked_add(1)?]));
+        }
+        "sizeeq" | "sizegt" | "sizelt" => {
+            let size = list_size(operator, value)?;
+            return Some(numbers((0..i32::try_from(size).ok()?).collect()));
+        }
+        _ => {
+            let n = value.parse::<i32>().ok()?;
+            match operator {
+                "eq" | "lte" | "gte" => Some(n),
+                "neq" | "gt" => n.checked_add(1),
+                "lt" => n.checked_sub(1),
+                _ => None,
+            }
+        }
+    };
+
+    candidate.map(|n| ContextValue::String(n.to_string()))
+}
+
+/// Size of a list satisfying the size constraint, unless it is larger than `MAX_LIST_SIZE`.
+fn list_size(operator: &str, value: &str) -> Option<usize> {
+    required_list_size(operator, value).filter(|size| *size <= MAX_LIST_SIZE)
+}
+
+fn required_list_size(operator: &str, value: &str) -> Option<usize> {
+    let size = value.parse::<usize>().ok()?;
+    match operator {
+        "sizeeq" => Some(size),
+        "sizegt" => Some(size.saturating_add(1)),
+        _ => size.checked_sub(1),
+    }
+}
+
+fn exceeds_list_limit(constraint: &flipt::EvaluationConstraint) -> bool {
+    matches!(constraint.operator.as_str(), "sizeeq" | "sizegt" | "sizelt")
+        && required_list_size(&constraint.operator, &constraint.value)
+            .is_some_and(|size| size > MAX_LIST_SIZE)
+}
+
+fn datetime_candidate(operator: &str, value: &str, now: DateTime<Utc>) -> Option<String> {
+    let instant = match operator {
+        "present" => now,
+        "withinlast" => now.checked_sub_signed(parse_duration(value).ok()? / 2)?,
+        "withinnext" => now.checked_add_signed(parse_duration(value).ok()? / 2)?,
+        "olderthan" => now
+            .checked_sub_signed(parse_duration(value).ok()?)?
+            .checked_sub_signed(TimeDelta::hours(1))?,
+        "timeofday" => {
+            let window: TimeOfDayWindow = serde_json::from_str(value).ok()?;
+            let start = parse_time_of_day(&window.start).ok()?;
+            let tz = parse_timezone(window.timezone.as_deref()).ok()?;
+            now.with_timezone(&tz)
+                .date_naive()
+                .and_time(start)
+                .and_local_timezone(tz)
+                .earliest()?
+                .with_timezone(&Utc)
+        }
+        "dayofweek" => {
+            let window: DayOfWeekWindow = serde_json::from_str(value).ok()?;
+            let day = window.days.first()?.parse::<Weekday>().ok()?;
+            let tz = parse_timezone(window.timezone.as_deref()).ok()?;
+            let local = now.with_timezone(&tz);
+            let offset = (7 + day.num_days_from_monday() as i64
+                - local.weekday().num_days_from_monday() as i64)
+                % 7;
+            local
+                .checked_add_signed(TimeDelta::days(offset))?
+                .with_timezone(&Utc)
+        }
+        _ => {
+            let d = parse_datetime(value).ok()?;
+            match operator {
+                "eq" | "lte" | "gte" => d,
+                "neq" | "gt" => d.checked_add_signed(TimeDelta::seconds(1))?,
+                "lt" => d.checked_sub_signed(TimeDelta::seconds(1))?,
+                _ => return None,
+            }
+        }
+    };
+
+    Some(instant.to_rfc3339())
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+    use crate::models::snapshot::Snapshot;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2006-01-04T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn constraint(r#type: &str, property: &str, operator: &str, value: &str) -> serde_json::Value {
+        serde_json::json!({
+            "type": r#type,
+            "property": property,
+            "operator": operator,
+            "value": value
+        })
+    }
+
+    fn snapshot() -> Snapshot {
+        serde_json::from_value(serde_json::json!({
+            "version": 1,
+            "namespace": {
+                "key": "default",
+                "flags": {
+                    "checkout": {
+                        "key": "checkout",
+                        "enabled": true,
+                        "type": "VARIANT_FLAG_TYPE"
+                    },
+                    "beta": {
+                        "key": "beta",
+                        "enabled": false,
+                        "type": "BOOLEAN_FLAG_TYPE"
+                    }
+                },
+                "eval_rules": {
+                    "checkout": [
+                        {
+                            "id": "internal",
+                            "flag_key": "checkout",
+                            "segments": {
+                                "staff": {
+                                    "segment_key": "staff",
+                                    "match_type": "ANY_SEGMENT_MATCH_TYPE",
+                                    "constraints": [
+                                        constraint("STRING_CONSTRAINT_COMPARISON_TYPE", "email", "suffix", "@flipt.io"),
+                                        constraint("ENTITY_ID_CONSTRAINT_COMPARISON_TYPE", "", "prefix", "admin-")
+                                    ]
+                                }
+                            },
+                            "rank": 1,
+                            "segment_operator": "OR_SEGMENT_OPERATOR"
+                        },
+                        {
+                            "id": "premium",
+                            "flag_key": "checkout",
+                            "segments": {
+                                "pro": {
+                                    "segment_key": "pro",
+                                    "match_type": "ALL_SEGMENT_MATCH_TYPE",
+                                    "constraints": [
+                                        constraint("STRING_CONSTRAINT_COMPARISON_TYPE", "plan", "isoneof", r#"["pro","enterprise"]"#),
+                                        constraint("NUMBER_CONSTRAINT_COMPARISON_TYPE", "seats", "gt", "10"),
+                                        constraint("BOOLEAN_CONSTRAINT_COMPARISON_TYPE", "verified", "true", ""),
+                                        constraint("DATETIME_CONSTRAINT_COMPARISON_TYPE", "signed_up", "withinlast", "30d")
+                                    ]
+                                }
+                            },
+                            "rank": 2,
+                            "segment_operator": "OR_SEGMENT_OPERATOR"
+                        },
+                        {
+                            "id": "shadowed",
+                            "flag_key": "checkout",
+                            "segments": {
+                                "contradiction": {
+                                    "segment_key": "contradiction",
+                                    "match_type": "ALL_SEGMENT_MATCH_TYPE",
+                                    "constraints": [
+                                        constraint("NUMBER_CONSTRAINT_COMPARISON_TYPE", "seats", "lt", "5"),
+                                        constraint("NUMBER_CONSTRAINT_COMPARISON_TYPE", "seats", "gt", "5")
+                                    ]
+                                }
+                            },
+                            "rank": 3,
+                            "segment_operator": "OR_SEGMENT_OPERATOR"
+                        }
+                    ],
+                    "beta": []
+                },
+                "eval_rollouts": {
+                    "checkout": [],
+                    "beta": [
+                        {
+                            "rollout_type": "SEGMENT_ROLLOUT_TYPE",
+                            "rank": 1,
+                            "segment": {
+                                "value": false,
+                                "segment_operator": "OR_SEGMENT_OPERATOR",
+                                "segments": {
+                                    "blocked": {
+                                        "segment_key": "blocked",
+                                        "match_type": "ALL_SEGMENT_MATCH_TYPE",
+                                        "constraints": [
+                                            constraint("STRING_CONSTRAINT_COMPARISON_TYPE", "country", "eq", "XX")
+                                        ]
+                                    }
+                                }
+                            }
+                        },
+                        {
+                            "rollout_type": "THRESHOLD_ROLLOUT_TYPE",
+                            "rank": 2,
+                            "threshold": { "percentage": 10.0, "value": true }
+                        }
+                    ]
+                },
+                "eval_distributions": {
+                    "internal": [
+                        { "rule_id": "internal", "rollout": 100.0, "variant_key": "new" }
+                    ],
+                    "premium": [
+                        { "rule_id": "premium", "rollout": 50.0, "variant_key": "new" },
+                        { "rule_id": "premium", "rollout": 50.0, "variant_key": "express" }
+                    ],
+                    "shadowed": [
+                        { "rule_id": "shadowed", "rollout": 100.0, "variant_key": "never" }
+                    ]
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    fn synthesize(flag_key: &str, target: SynthesisTarget) -> SynthesisResponse {
+        synthesize_with_clock(
+            &snapshot(),
+            "default",
+            &SynthesisRequest {
+                flag_key: flag_key.into(),
+                target,
+            },
+            &FixedClock(now()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_synthesize_rule() {
+        let response = synthesize(
+            "checkout",
+            SynthesisTarget::Rule {
+                rule_id: "internal".into(),
+            },
+        );
+        assert!(response.reachable);
+        assert_eq!(response.rule_id.as_deref(), Some("internal"));
+        // either constraint of the ANY segment is enough
+        assert!(response.context.len() <= 1);
+
+        let response = synthesize(
+            "checkout",
+            SynthesisTarget::Rule {
+                rule_id: "premium".into(),
+            },
+        );
+        assert!(response.reachable);
+        assert_eq!(response.rank, Some(2));
+        assert_eq!(response.context.len(), 4);
+        assert_eq!(response.context.get("plan").unwrap(), "pro");
+        assert_eq!(response.context.get("seats").unwrap(), "11");
+        assert_eq!(response.context.get("verified").unwrap(), "true");
+        assert!(!response.entity_id.unwrap().starts_with("admin-"));
+    }
+
+    #[test]
+    fn test_synthesize_variant() {
+        let response = synthesize(
+            "checkout",
+            SynthesisTarget::Variant {
+                key: "express".into(),
+            },
+        );
+        assert!(response.reachable);
+        assert_eq!(response.rule_id.as_deref(), Some("premium"));
+
+        // the synthesized request evaluates to the target
+        let evaluation = crate::variant_evaluation_with_clock(
+            &snapshot(),
+            "default",
+            &EvaluationRequest {
+                flag_key: "checkout".into(),
+                entity_id: response.entity_id.unwrap(),
+                context: response.context,
+                list_context: HashMap::new(),
+            },
+            &FixedClock(now()),
+        )
+        .unwrap();
+        assert_eq!(evaluation.variant_key, "express");
+    }
+
+    #[test]
+    fn test_synthesize_unreachable() {
+        let response = synthesize(
+            "checkout",
+            SynthesisTarget::Rule {
+                rule_id: "shadowed".into(),
+            },
+        );
+        assert!(!response.reachable);
+        assert!(response.reason.is_some());
+
+        let response = synthesize(
+            "checkout",
+            SynthesisTarget::Variant {
+                key: "missing".into(),
+            },
+        );
+        assert!(!response.reachable);
+        assert_eq!(
+            response.reason.as_deref(),
+            Some("variant is not distributed by any rule")
+        );
+    }
+
+    fn single_rule_snapshot(constraints: Vec<serde_json::Value>) -> Snapshot {
+        serde_json::from_value(serde_json::json!({
+            "version": 1,
+            "namespace": {
+                "key": "default",
+                "flags": {
+                    "limits": { "key": "limits", "enabled": true, "type": "VARIANT_FLAG_TYPE" }
+                },
+                "eval_rules": {
+                    "limits": [
+                        {
+                            "id": "target",
+                            "flag_key": "limits",
+                            "segments": {
+                                "all": {
+                                    "segment_key": "all",
+                                    "match_type": "ALL_SEGMENT_MATCH_TYPE",
+                                    "constraints": constraints
+                                }
+                            },
+                            "rank": 1,
+                            "segment_operator": "OR_SEGMENT_OPERATOR"
+                        }
+                    ]
+                },
+                "eval_rollouts": { "limits": [] },
+                "eval_distributions": {
+                    "target": [
+                        { "rule_id": "target", "rollout": 100.0, "variant_key": "on" }
+                    ]
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    fn synthesize_rule(snapshot: &Snapshot) -> SynthesisResponse {
+        synthesize_with_clock(
+            snapshot,
+            "default",
+            &SynthesisRequest {
+                flag_key: "limits".into(),
+                target: SynthesisTarget::Rule {
+                    rule_id: "target".into(),
+                },
+            },
+            &FixedClock(now()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_synthesize_list_size_limit() {
+        for r#type in [
+            "STRING_CONSTRAINT_COMPARISON_TYPE",
+            "NUMBER_CONSTRAINT_COMPARISON_TYPE",
+        ] {
+            let response = synthesize_rule(&single_rule_snapshot(vec![constraint(
+                r#type,
+                "items",
+                "sizegt",
+                "4000000000",
+            )]));
+            assert!(!response.reachable);
+            assert_eq!(
+                response.reason.as_deref(),
+                Some("list size constraint exceeds the synthesis limit")
+            );
+        }
+
+        let response = synthesize_rule(&single_rule_snapshot(vec![constraint(
+            "NUMBER_CONSTRAINT_COMPARISON_TYPE",
+            "items",
+            "sizeeq",
+            "3",
+        )]));
+        assert!(response.reachable);
+        assert_eq!(
+            response.list_context.get("items"),
+            Some(&ContextList::Numbers(vec![0, 1, 2]))
+        );
+    }
+
+    #[test]
+    fn test_synthesize_truncated_search() {
+        // 2^13 combinations of present and absent properties exceed MAX_CONTEXTS
+        let constraints = (0..13)
+            .map(|i| {
+                constraint(
+                    "STRING_CONSTRAINT_COMPARISON_TYPE",
+                    &format!("p{i}"),
+                    "eq",
+                    "v",
+                )
+            })
+            .collect();
+
+        let response = synthesize_rule(&single_rule_snapshot(constraints));
+        assert!(!response.reachable);
+        assert_eq!(response.reason.as_deref(), Some(SEARCH_LIMIT_REACHED));
+    }
+
+    #[test]
+    fn test_synthesize_boolean() {
+        let response = synthesize("beta", SynthesisTarget::Rollout { rank: 1 });
+        assert!(response.reachable);
+        assert_eq!(response.context.get("country").unwrap(), "XX");
+
+        let response = synthesize("beta", SynthesisTarget::Variant { key: "true".into() });
+        assert!(response.reachable);
+        assert_eq!(response.rank, Some(2));
+        assert!(response.context.is_empty());
+    }
+
+    #[test]
+    fn test_synthesize_invalid_target() {
+        let result = super::synthesize(
+            &snapshot(),
+            "default",
+            &SynthesisRequest {
+                flag_key: "checkout".into(),
+                target: SynthesisTarget::Rule {
+                    rule_id: "missing".into(),
+                },
+            },
+        );
+        assert!(result.is_err());
+
+        let result = super::synthesize(
+            &snapshot(),
+            "default",
+            &SynthesisRequest {
+                flag_key: "beta".into(),
+                target: SynthesisTarget::Rule {
+                    rule_id: "internal".into(),
+                },
+            },
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_candidates() {
+        let c = |r#type, operator: &str, value: &str| flipt::EvaluationConstraint {
+            r#type,
+            property: "p".into(),
+            operator: operator.into(),
+            value: value.into(),
+        };
+        let value = |v: &str| ContextValue::String(v.to_string());
+
+        use flipt::ConstraintComparisonType::*;
+        assert_eq!(
+            candidates(&c(String, "neq", "other"), now()),
+            vec![value("a")]
+        );
+        assert_eq!(
+            candidates(&c(String, "containsall", r#"["a","b"]"#), now()),
+            vec![strings(vec!["a".to_string(), "b".to_string()])]
+        );
+        assert_eq!(candidates(&c(Number, "lt", "0"), now()), vec![value("-1")]);
+        assert_eq!(
+            candidates(&c(Number, "sizegt", "1"), now()),
+            vec![numbers(vec![0, 1])]
+        );
+        assert_eq!(
+            candidates(&c(DateTime, "gt", "2006-01-02"), now()),
+            vec![value("2006-01-02T00:00:01+00:00")]
+        );
+        assert_eq!(
+            candidates(
+                &c(
+                    DateTime,
+                    "timeofday",
+                    r#"{"start":"09:00","end":"17:00","timezone":"Europe/Berlin"}"#
+                ),
+                now()
+            ),
+            vec![value("2006-01-04T08:00:00+00:00")]
+        );
+        assert_eq!(
+            candidates(&c(DateTime, "dayofweek", r#"{"days":["sat"]}"#), now()),
+            vec![value("2006-01-07T12:00:00+00:00")]
+        );
+        assert!(candidates(&c(Boolean, "notpresent", ""), now()).is_empty());
+        assert_eq!(
+            candidates(&c(DateTime, "withinlast", "2d"), now()),
+            vec![value("2006-01-03T12:00:00+00:00")]
+        );
+        for operator in ["withinlast", "withinnext", "olderthan"] {
+            assert!(candidates(&c(DateTime, operator, "100000000w"), now()).is_empty());
+        }
+    }
+}